@@ -0,0 +1,176 @@
+// 命令行参数，用法与 zenity 类似，便于在 shell 脚本中使用：
+//
+//   gpui-dialog --title=登录 --message=请输入密码 --password
+//
+// 确认时把输入内容打印到标准输出并以 0 退出，取消以 1 退出，超时以 5 退出；
+// 参数错误以 2 退出，对话框打不开以 3 退出。
+
+use std::time::Duration;
+
+use crate::dialog::DialogOptions;
+
+pub const EXIT_CONFIRMED: i32 = 0;
+pub const EXIT_CANCELLED: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_ERROR: i32 = 3;
+pub const EXIT_TIMEOUT: i32 = 5;
+
+pub const USAGE: &str = "\
+Usage: gpui-dialog [OPTIONS]
+
+Shows a dialog and reports the result through the exit code:
+0 when confirmed, 1 when cancelled, 2 on invalid options, 3 when the
+dialog can't be shown, 5 on timeout.
+
+Options:
+  --title=TEXT          Window and dialog title
+  --message=TEXT        Message shown above the input
+  --input[=DEFAULT]     Ask for text and print it to stdout on confirm
+  --password            Ask for text without showing it (implies --input)
+  --reveal-toggle       With --password, show a button that reveals the text
+                        while held down
+  --alert               Show only the confirm button
+  --ok-label=TEXT       Label of the confirm button (default: OK)
+  --cancel-label=TEXT   Label of the cancel button (default: Cancel)
+  --timeout=SECONDS     Close the dialog after SECONDS and exit with 5
+  -h, --help            Print this help
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Alert,
+    Confirm,
+    Prompt,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub mode: Mode,
+    pub options: DialogOptions,
+    pub default_input: String,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Run(Args),
+    Help,
+}
+
+/// 解析参数（不含程序名）。值既可以写成 `--title=标题`，也可以写成
+/// `--title 标题`；`--input` 的默认值只能用 `=` 给出。
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let mut options = DialogOptions::new("gpui-dialog", "");
+    let mut mode = Mode::Confirm;
+    let mut default_input = String::new();
+    let mut timeout = None;
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{name} needs a value"))
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--title" => options.title = value("--title")?.into(),
+            "--message" | "--text" => options.message = value("--message")?.into(),
+            "--ok-label" => options.confirm_label = value("--ok-label")?.into(),
+            "--cancel-label" => options.cancel_label = value("--cancel-label")?.into(),
+            "--input" | "--entry" => {
+                mode = Mode::Prompt;
+                default_input = inline_value.unwrap_or_default();
+            }
+            "--password" => {
+                mode = Mode::Prompt;
+                options.secret = true;
+            }
+            "--reveal-toggle" => options.reveal_toggle = true,
+            "--alert" => mode = Mode::Alert,
+            "--timeout" => {
+                let seconds = value("--timeout")?;
+                let seconds: u64 = seconds
+                    .parse()
+                    .map_err(|_| format!("--timeout expects whole seconds, got {seconds:?}"))?;
+                timeout = Some(Duration::from_secs(seconds));
+            }
+            _ => return Err(format!("unknown option {arg:?}")),
+        }
+    }
+
+    Ok(Command::Run(Args {
+        mode,
+        options,
+        default_input,
+        timeout,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Args, String> {
+        match parse(args.iter().map(|arg| arg.to_string()))? {
+            Command::Run(args) => Ok(args),
+            Command::Help => panic!("expected options, got --help"),
+        }
+    }
+
+    #[test]
+    fn flag_order_does_not_matter() {
+        for args in [
+            ["--input=admin", "--password", "--title", "登录"],
+            ["--title", "登录", "--password", "--input=admin"],
+        ] {
+            let args = parse_args(&args).unwrap();
+            assert_eq!(args.mode, Mode::Prompt);
+            assert!(args.options.secret);
+            assert_eq!(args.default_input, "admin");
+            assert_eq!(&*args.options.title, "登录");
+        }
+    }
+
+    #[test]
+    fn later_mode_flags_win() {
+        assert_eq!(parse_args(&["--input", "--alert"]).unwrap().mode, Mode::Alert);
+        assert_eq!(parse_args(&["--alert", "--input"]).unwrap().mode, Mode::Prompt);
+    }
+
+    #[test]
+    fn values_keep_everything_after_the_first_equals_sign() {
+        let args = parse_args(&["--input=key=value=1", "--message=a = b"]).unwrap();
+        assert_eq!(args.default_input, "key=value=1");
+        assert_eq!(&*args.options.message, "a = b");
+
+        let args = parse_args(&["--input="]).unwrap();
+        assert_eq!(args.mode, Mode::Prompt);
+        assert_eq!(args.default_input, "");
+    }
+
+    #[test]
+    fn timeout_must_be_whole_seconds() {
+        let args = parse_args(&["--timeout=30"]).unwrap();
+        assert_eq!(args.timeout, Some(Duration::from_secs(30)));
+
+        for timeout in ["--timeout=1.5", "--timeout=-1", "--timeout=soon"] {
+            let error = parse_args(&[timeout]).unwrap_err();
+            assert!(error.contains("--timeout expects whole seconds"), "{error}");
+        }
+        let error = parse_args(&["--timeout"]).unwrap_err();
+        assert_eq!(error, "--timeout needs a value");
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        let error = parse_args(&["--colour=red"]).unwrap_err();
+        assert_eq!(error, "unknown option \"--colour=red\"");
+        assert!(matches!(parse(["-h".to_string()]), Ok(Command::Help)));
+    }
+}
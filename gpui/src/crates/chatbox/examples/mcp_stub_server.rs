@@ -0,0 +1,94 @@
+// Stand-in MCP server for trying the MCP client locally
+// Speaks newline-delimited JSON-RPC over stdio and offers `echo` and `add`
+// tools plus one text resource. Point a config at it with:
+//
+//   { "mcpServers": { "stub": { "command": "cargo", "args": ["run", "-q", "--example", "mcp_stub_server"] } } }
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+fn handle(method: &str, params: &Value) -> Result<Value, String> {
+    match method {
+        "initialize" => Ok(json!({
+            "protocolVersion": "2024-11-05",
+            "capabilities": { "tools": {}, "resources": {} },
+            "serverInfo": { "name": "mcp-stub-server", "version": "0.1.0" },
+        })),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({
+            "tools": [
+                {
+                    "name": "echo",
+                    "description": "Returns its input text.",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": { "text": { "type": "string" } },
+                        "required": ["text"]
+                    },
+                    "annotations": { "readOnlyHint": true }
+                },
+                {
+                    "name": "add",
+                    "description": "Adds two numbers.",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": { "a": { "type": "number" }, "b": { "type": "number" } },
+                        "required": ["a", "b"]
+                    }
+                }
+            ]
+        })),
+        "resources/list" => Ok(json!({
+            "resources": [
+                { "uri": "stub://readme", "name": "README", "mimeType": "text/plain" }
+            ]
+        })),
+        "tools/call" => {
+            let arguments = &params["arguments"];
+            match params["name"].as_str() {
+                Some("echo") => Ok(json!({
+                    "content": [{ "type": "text", "text": arguments["text"].as_str().unwrap_or_default() }]
+                })),
+                Some("add") => match (arguments["a"].as_f64(), arguments["b"].as_f64()) {
+                    (Some(a), Some(b)) => Ok(json!({
+                        "content": [{ "type": "text", "text": (a + b).to_string() }]
+                    })),
+                    _ => Ok(json!({
+                        "content": [{ "type": "text", "text": "a and b must be numbers" }],
+                        "isError": true
+                    })),
+                },
+                _ => Err(format!("unknown tool: {}", params["name"])),
+            }
+        }
+        _ => Err(format!("method not found: {method}")),
+    }
+}
+
+fn main() -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        // Notifications have no id and get no response
+        let Some(id) = message.get("id").cloned() else {
+            continue;
+        };
+        let method = message["method"].as_str().unwrap_or_default();
+        let response = match handle(method, &message["params"]) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": error }
+            }),
+        };
+        writeln!(stdout, "{response}")?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
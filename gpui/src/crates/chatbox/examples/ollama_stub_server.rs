@@ -0,0 +1,146 @@
+// Stand-in Ollama server for trying the Ollama provider offline
+// Serves `/api/tags`, `/api/show` and a streaming `/api/chat` that echoes the
+// last user message word by word. A user message starting with `call <tool>`
+// makes it request that tool with empty arguments, and a tool result is
+// answered by quoting it. Run it and point the app at it with:
+//
+//   cargo run --example ollama_stub_server -- 127.0.0.1:11435
+//   OLLAMA_HOST=127.0.0.1:11435
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+const MODEL: &str = "stub-llama:latest";
+
+fn read_request(stream: &TcpStream) -> std::io::Result<(String, String, Value)> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    Ok((method, path, body))
+}
+
+fn write_json(stream: &mut TcpStream, status: &str, body: &Value) -> std::io::Result<()> {
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn chunk(message: Value, done: bool) -> Value {
+    let mut chunk = json!({
+        "model": MODEL,
+        "created_at": "2024-01-01T00:00:00Z",
+        "message": message,
+        "done": done,
+    });
+    if done {
+        chunk["done_reason"] = json!("stop");
+        chunk["prompt_eval_count"] = json!(12);
+        chunk["eval_count"] = json!(8);
+    }
+    chunk
+}
+
+fn stream_chat(stream: &mut TcpStream, request: &Value) -> std::io::Result<()> {
+    let last = request["messages"]
+        .as_array()
+        .and_then(|messages| messages.last())
+        .cloned()
+        .unwrap_or(Value::Null);
+    let content = last["content"].as_str().unwrap_or_default();
+    let has_tools = request["tools"].as_array().is_some_and(|tools| !tools.is_empty());
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nConnection: close\r\n\r\n"
+    )?;
+    let mut send = |value: Value| -> std::io::Result<()> {
+        writeln!(stream, "{value}")?;
+        stream.flush()?;
+        thread::sleep(Duration::from_millis(40));
+        Ok(())
+    };
+
+    if last["role"] == "tool" {
+        send(chunk(json!({ "role": "assistant", "content": format!("The tool said: {content}") }), false))?;
+    } else if let Some(tool) = content.strip_prefix("call ").filter(|_| has_tools) {
+        send(chunk(
+            json!({
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [{ "function": { "name": tool.trim(), "arguments": {} } }]
+            }),
+            false,
+        ))?;
+    } else {
+        for word in content.split_inclusive(' ') {
+            send(chunk(json!({ "role": "assistant", "content": word }), false))?;
+        }
+    }
+    send(chunk(json!({ "role": "assistant", "content": "" }), true))
+}
+
+fn handle(mut stream: TcpStream) -> std::io::Result<()> {
+    let (method, path, body) = read_request(&stream)?;
+    match (method.as_str(), path.as_str()) {
+        ("GET", "/api/tags") => write_json(
+            &mut stream,
+            "200 OK",
+            &json!({ "models": [{ "name": MODEL, "model": MODEL, "size": 0 }] }),
+        ),
+        ("POST", "/api/show") => write_json(
+            &mut stream,
+            "200 OK",
+            &json!({
+                "capabilities": ["completion", "tools"],
+                "model_info": { "llama.context_length": 32768 }
+            }),
+        ),
+        ("POST", "/api/chat") => stream_chat(&mut stream, &body),
+        _ => write_json(&mut stream, "404 Not Found", &json!({ "error": "not found" })),
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:11435".to_string());
+    let listener = TcpListener::bind(&address)?;
+    eprintln!("ollama stub listening on {address}");
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::spawn(move || {
+            if let Err(error) = handle(stream) {
+                eprintln!("request failed: {error}");
+            }
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,375 @@
+// ANSI escape handling for terminal-style tool output
+// SGR color and weight codes become styled spans; other control sequences are
+// dropped. A lone carriage return rewrites the current line, as progress bars
+// expect.
+
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AnsiStyle {
+    /// 0xRRGGBB, or `None` for the default color.
+    pub foreground: Option<u32>,
+    pub background: Option<u32>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnsiText {
+    /// The text with every escape sequence removed.
+    pub text: String,
+    /// Styled byte ranges of `text`; unstyled text has no span.
+    pub spans: Vec<(Range<usize>, AnsiStyle)>,
+}
+
+// xterm's 16 color palette, tuned for a dark background
+const PALETTE: [u32; 16] = [
+    0x1f2937, 0xef4444, 0x22c55e, 0xeab308, 0x3b82f6, 0xa855f7, 0x06b6d4, 0xe5e7eb,
+    0x6b7280, 0xf87171, 0x4ade80, 0xfacc15, 0x60a5fa, 0xc084fc, 0x22d3ee, 0xffffff,
+];
+
+fn color_256(index: u8) -> u32 {
+    match index {
+        0..=15 => PALETTE[index as usize],
+        16..=231 => {
+            let index = index - 16;
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value as u32 * 40 };
+            (level(index / 36) << 16) | (level(index / 6 % 6) << 8) | level(index % 6)
+        }
+        232..=255 => {
+            let gray = 8 + (index - 232) as u32 * 10;
+            (gray << 16) | (gray << 8) | gray
+        }
+    }
+}
+
+// Reads an extended color (`5;n` or `2;r;g;b`) following a 38 or 48 code
+fn extended_color(codes: &mut impl Iterator<Item = u32>) -> Option<u32> {
+    match codes.next()? {
+        5 => Some(color_256(codes.next()?.min(255) as u8)),
+        2 => {
+            let r = codes.next()?.min(255);
+            let g = codes.next()?.min(255);
+            let b = codes.next()?.min(255);
+            Some((r << 16) | (g << 8) | b)
+        }
+        _ => None,
+    }
+}
+
+fn apply_sgr(style: &mut AnsiStyle, params: &str) {
+    let mut codes = params
+        .split([';', ':'])
+        .map(|code| code.parse::<u32>().unwrap_or(0));
+    // `ESC[m` is a reset
+    if params.is_empty() {
+        *style = AnsiStyle::default();
+        return;
+    }
+    while let Some(code) = codes.next() {
+        match code {
+            0 => *style = AnsiStyle::default(),
+            1 => style.bold = true,
+            2 => style.dim = true,
+            3 => style.italic = true,
+            4 => style.underline = true,
+            22 => {
+                style.bold = false;
+                style.dim = false;
+            }
+            23 => style.italic = false,
+            24 => style.underline = false,
+            30..=37 => style.foreground = Some(PALETTE[(code - 30) as usize]),
+            38 => style.foreground = extended_color(&mut codes),
+            39 => style.foreground = None,
+            40..=47 => style.background = Some(PALETTE[(code - 40) as usize]),
+            48 => style.background = extended_color(&mut codes),
+            49 => style.background = None,
+            90..=97 => style.foreground = Some(PALETTE[(code - 90 + 8) as usize]),
+            100..=107 => style.background = Some(PALETTE[(code - 100 + 8) as usize]),
+            _ => {}
+        }
+    }
+}
+
+impl AnsiText {
+    fn push(&mut self, ch: char, style: AnsiStyle) {
+        let start = self.text.len();
+        self.text.push(ch);
+        if style == AnsiStyle::default() {
+            return;
+        }
+        match self.spans.last_mut() {
+            Some((range, last)) if *last == style && range.end == start => {
+                range.end = self.text.len()
+            }
+            _ => self.spans.push((start..self.text.len(), style)),
+        }
+    }
+
+    fn append(&mut self, other: AnsiText) {
+        let offset = self.text.len();
+        self.text.push_str(&other.text);
+        self.spans.extend(
+            other
+                .spans
+                .into_iter()
+                .map(|(range, style)| (range.start + offset..range.end + offset, style)),
+        );
+    }
+
+    // The text from byte `start` on, with its spans
+    fn slice(&self, start: usize) -> AnsiText {
+        AnsiText {
+            text: self.text[start..].to_string(),
+            spans: self
+                .spans
+                .iter()
+                .filter(|(range, _)| range.end > start)
+                .map(|(range, style)| (range.start.max(start) - start..range.end - start, *style))
+                .collect(),
+        }
+    }
+
+    // Drops the current line so a carriage return can overwrite it
+    fn truncate_line(&mut self) {
+        let line_start = self.text.rfind('\n').map_or(0, |ix| ix + 1);
+        self.text.truncate(line_start);
+        self.spans.retain_mut(|(range, _)| {
+            range.end = range.end.min(line_start);
+            range.start < range.end
+        });
+    }
+}
+
+pub fn parse_ansi(input: &str) -> AnsiText {
+    parse_ansi_from(input, &mut AnsiStyle::default())
+}
+
+// Parses starting in `style` and leaves it set to the style in effect at the
+// end, so output can be parsed a piece at a time
+fn parse_ansi_from(input: &str, style: &mut AnsiStyle) -> AnsiText {
+    let mut output = AnsiText::default();
+    let mut chars = input.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x1b' => match chars.next() {
+                // CSI: parameters, then a final byte in @..~
+                Some('[') => {
+                    let mut params = String::new();
+                    for ch in chars.by_ref() {
+                        if ('@'..='~').contains(&ch) {
+                            if ch == 'm' {
+                                apply_sgr(style, &params);
+                            }
+                            break;
+                        }
+                        params.push(ch);
+                    }
+                }
+                // OSC: ends with BEL or ESC \
+                Some(']') => {
+                    while let Some(ch) = chars.next() {
+                        if ch == '\x07' {
+                            break;
+                        }
+                        if ch == '\x1b' {
+                            chars.next_if_eq(&'\\');
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\r' => output.truncate_line(),
+            '\n' | '\t' => output.push(ch, *style),
+            ch if ch.is_control() => {}
+            ch => output.push(ch, *style),
+        }
+    }
+    output
+}
+
+pub fn strip_ansi(input: &str) -> String {
+    parse_ansi(input).text
+}
+
+/// Terminal output parsed as it streams in. Finished lines are parsed once;
+/// only the current line is kept raw, since a carriage return may still
+/// rewrite it. The oldest lines are dropped past `max_len` bytes.
+#[derive(Clone, Debug)]
+pub struct AnsiOutput {
+    lines: AnsiText,
+    // Style in effect at the start of `partial`
+    style: AnsiStyle,
+    partial: String,
+    max_len: usize,
+}
+
+impl AnsiOutput {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            lines: AnsiText::default(),
+            style: AnsiStyle::default(),
+            partial: String::new(),
+            max_len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.text.is_empty() && self.partial.is_empty()
+    }
+
+    pub fn push(&mut self, text: &str) {
+        self.partial.push_str(text);
+        let finished = match self.partial.rfind('\n') {
+            Some(ix) => ix + 1,
+            // A line that never ends is flushed anyway rather than re-parsed
+            // on every render
+            None if self.partial.len() > self.max_len => self.partial.len(),
+            None => return,
+        };
+        let rest = self.partial.split_off(finished);
+        let parsed = parse_ansi_from(&self.partial, &mut self.style);
+        self.partial = rest;
+
+        self.lines.append(parsed);
+        if self.lines.text.len() > self.max_len {
+            // Cut at a line start so no line is shown half; the text is
+            // already parsed, so no escape sequence can be split either
+            let mut start = self.lines.text.len() - self.max_len;
+            match self.lines.text.as_bytes()[start..]
+                .iter()
+                .position(|byte| *byte == b'\n')
+            {
+                Some(ix) => start += ix + 1,
+                None => {
+                    while !self.lines.text.is_char_boundary(start) {
+                        start += 1;
+                    }
+                }
+            }
+            self.lines = self.lines.slice(start);
+        }
+    }
+
+    /// The last `count` lines, with the current line parsed as it stands.
+    pub fn tail(&self, count: usize) -> AnsiText {
+        let mut style = self.style;
+        let current = parse_ansi_from(&self.partial, &mut style);
+        // `partial` never holds a newline, so it adds at most one line
+        let previous = count.saturating_sub(usize::from(!current.text.is_empty()));
+        let finished = self.lines.text.trim_end_matches('\n');
+        let start = if previous == 0 {
+            self.lines.text.len()
+        } else {
+            finished
+                .rmatch_indices('\n')
+                .nth(previous - 1)
+                .map_or(0, |(ix, _)| ix + 1)
+        };
+        let mut tail = self.lines.slice(start);
+        tail.append(current);
+        tail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn red() -> AnsiStyle {
+        AnsiStyle {
+            foreground: Some(PALETTE[1]),
+            ..AnsiStyle::default()
+        }
+    }
+
+    #[test]
+    fn parses_sgr_colors_into_spans() {
+        let parsed = parse_ansi("plain \x1b[31mred\x1b[0m \x1b[1;38;5;196mbold\x1b[m");
+        assert_eq!(parsed.text, "plain red bold");
+        assert_eq!(parsed.spans.len(), 2);
+        assert_eq!(parsed.spans[0], (6..9, red()));
+        let (range, style) = &parsed.spans[1];
+        assert_eq!(*range, 10..14);
+        assert!(style.bold);
+        assert_eq!(style.foreground, Some(0xff0000));
+    }
+
+    #[test]
+    fn parses_truecolor_and_resets() {
+        let parsed = parse_ansi("\x1b[48;2;1;2;3mbg\x1b[49m\x1b[4mu\x1b[24mx");
+        assert_eq!(parsed.text, "bgux");
+        assert_eq!(parsed.spans[0].1.background, Some(0x010203));
+        let underline = AnsiStyle {
+            underline: true,
+            ..AnsiStyle::default()
+        };
+        assert_eq!(parsed.spans[1], (2..3, underline));
+        assert_eq!(parsed.spans.len(), 2);
+    }
+
+    #[test]
+    fn drops_other_escapes_and_controls() {
+        let parsed = parse_ansi("a\x1b[2Kb\x1b]0;title\x07c\x1b]8;;url\x1b\\d\x08e");
+        assert_eq!(parsed.text, "abcde");
+        assert!(parsed.spans.is_empty());
+    }
+
+    #[test]
+    fn carriage_return_rewrites_the_line() {
+        assert_eq!(parse_ansi("one\n10%\r\x1b[31m50%\r100%\n").text, "one\n100%\n");
+        assert_eq!(parse_ansi("crlf\r\nline").text, "crlf\nline");
+        let parsed = parse_ansi("\x1b[31mred\rplain");
+        assert_eq!(parsed.text, "plain");
+        assert_eq!(parsed.spans, vec![(0..5, red())]);
+    }
+
+    #[test]
+    fn output_keeps_style_across_pushes() {
+        let mut output = AnsiOutput::new(1024);
+        output.push("\x1b[3");
+        output.push("1mred\n");
+        output.push("still red\x1b[0m\n");
+        let tail = output.tail(10);
+        assert_eq!(tail.text, "red\nstill red\n");
+        assert_eq!(tail.spans, vec![(0..4, red()), (4..13, red())]);
+    }
+
+    #[test]
+    fn output_rewrites_the_current_line() {
+        let mut output = AnsiOutput::new(1024);
+        output.push("done\n10%");
+        assert_eq!(output.tail(10).text, "done\n10%");
+        output.push("\r50%\r");
+        output.push("\n");
+        assert_eq!(output.tail(10).text, "done\n50%\n");
+    }
+
+    #[test]
+    fn output_tail_counts_lines() {
+        let mut output = AnsiOutput::new(1024);
+        output.push("1\n2\n3\n");
+        assert_eq!(output.tail(2).text, "2\n3\n");
+        output.push("4");
+        assert_eq!(output.tail(2).text, "3\n4");
+        assert_eq!(output.tail(10).text, "1\n2\n3\n4");
+    }
+
+    #[test]
+    fn output_drops_whole_lines_past_the_cap() {
+        let mut output = AnsiOutput::new(10);
+        output.push("\x1b[31mfirst\x1b[0m\nsecond\nthird\n");
+        let tail = output.tail(10);
+        assert_eq!(tail.text, "third\n");
+        assert!(tail.spans.is_empty());
+
+        let mut output = AnsiOutput::new(4);
+        output.push("ééééé");
+        assert_eq!(output.tail(10).text, "éé");
+    }
+}
@@ -0,0 +1,429 @@
+// Anthropic Messages API provider
+// Streams `/v1/messages` and maps its typed events onto completion events:
+// text deltas become text, tool_use blocks (whose input arrives as partial
+// JSON) become tool calls, and message_start/message_delta carry usage.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, stream::BoxStream, AsyncReadExt, FutureExt};
+use gpui::{AsyncApp, SharedString};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::backend::{
+    sse_completion_events, AgentBackend, ChatRequest, CompletionEvent, SseEventMapper, StopReason,
+    TokenUsage,
+};
+use crate::conversation::{RequestMessage, Role, ToolCall};
+use crate::model_catalog::parse_model_id;
+use crate::sse::SseEvent;
+
+pub const PROVIDER_ID: &str = "anthropic";
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+pub const BASE_URL_ENV_VAR: &str = "CODE_AGENT_ANTHROPIC_BASE_URL";
+pub const API_KEY_ENV_VAR: &str = "ANTHROPIC_API_KEY";
+const API_VERSION: &str = "2023-06-01";
+// The API requires max_tokens on every request
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnthropicSettings {
+    /// Defaults to the public API.
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+}
+
+impl AnthropicSettings {
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|value: &String| !value.is_empty());
+        Self {
+            base_url: var(BASE_URL_ENV_VAR),
+            api_key: var(API_KEY_ENV_VAR),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.api_key.is_some()
+    }
+
+    pub fn base_url(&self) -> &str {
+        self.base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_BASE_URL)
+            .trim_end_matches('/')
+    }
+}
+
+pub struct AnthropicBackend {
+    settings: AnthropicSettings,
+    http_client: Arc<dyn HttpClient>,
+}
+
+impl AnthropicBackend {
+    pub fn new(settings: AnthropicSettings, http_client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            settings,
+            http_client,
+        }
+    }
+}
+
+// Request body types
+
+#[derive(Serialize)]
+struct AnthropicRequest {
+    model: String,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<AnthropicMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct AnthropicMessage {
+    role: &'static str,
+    content: Vec<ContentBlock>,
+}
+
+/// Content blocks as they appear in requests and in `content_block_start`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        #[serde(default)]
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+// System messages go in the top-level `system` field, tool results are user
+// content, and consecutive messages with the same role are merged since the
+// API expects the roles to alternate
+fn request_messages(messages: &[RequestMessage]) -> (Option<String>, Vec<AnthropicMessage>) {
+    let mut system = Vec::new();
+    let mut result: Vec<AnthropicMessage> = Vec::new();
+    for message in messages {
+        let (role, blocks) = match message.role {
+            Role::System => {
+                system.push(message.content.clone());
+                continue;
+            }
+            Role::User => ("user", vec![ContentBlock::Text {
+                text: message.text().into_owned(),
+            }]),
+            Role::Assistant => {
+                let mut blocks = Vec::new();
+                if !message.content.is_empty() {
+                    blocks.push(ContentBlock::Text {
+                        text: message.content.clone(),
+                    });
+                }
+                blocks.extend(message.tool_calls.iter().map(|call| ContentBlock::ToolUse {
+                    id: call.id.clone(),
+                    name: call.name.clone(),
+                    input: call.arguments.clone(),
+                }));
+                ("assistant", blocks)
+            }
+            Role::Tool => {
+                let Some(call_id) = message.tool_call_id.clone() else {
+                    continue;
+                };
+                ("user", vec![ContentBlock::ToolResult {
+                    tool_use_id: call_id,
+                    content: message.content.clone(),
+                    is_error: message.tool_status.is_some_and(|status| status.is_error()),
+                }])
+            }
+        };
+        if blocks.is_empty() {
+            continue;
+        }
+        match result.last_mut() {
+            Some(last) if last.role == role => last.content.extend(blocks),
+            _ => result.push(AnthropicMessage {
+                role,
+                content: blocks,
+            }),
+        }
+    }
+    let system = (!system.is_empty()).then(|| system.join("\n\n"));
+    (system, result)
+}
+
+// Streamed event types
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    MessageStart {
+        message: MessageStart,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: BlockDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+        #[serde(default)]
+        usage: Option<Usage>,
+    },
+    MessageStop,
+    Ping,
+    Error {
+        error: ApiError,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessageStart {
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessageDelta {
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub input_tokens: Option<u64>,
+    #[serde(default)]
+    pub output_tokens: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiError {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub message: String,
+}
+
+struct PendingToolUse {
+    id: String,
+    name: String,
+    input_json: String,
+}
+
+/// Turns stream events into completion events, assembling tool_use input
+/// from its JSON fragments.
+#[derive(Default)]
+pub struct AnthropicEventMapper {
+    tool_uses: BTreeMap<usize, PendingToolUse>,
+    usage: TokenUsage,
+    stop_reason: Option<StopReason>,
+}
+
+impl AnthropicEventMapper {
+    /// Maps the JSON payload of one event. Every payload carries its own
+    /// `type`, so the SSE event name isn't needed.
+    pub fn map_payload(&mut self, data: &str) -> Result<Vec<CompletionEvent>> {
+        let event: StreamEvent =
+            serde_json::from_str(data).with_context(|| format!("invalid event: {data}"))?;
+        let mut events = Vec::new();
+        match event {
+            StreamEvent::MessageStart { message } => {
+                if let Some(usage) = message.usage {
+                    self.apply_usage(usage);
+                }
+            }
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => match content_block {
+                ContentBlock::Text { text } if !text.is_empty() => {
+                    events.push(CompletionEvent::Text(text))
+                }
+                ContentBlock::ToolUse { id, name, .. } => {
+                    self.tool_uses.insert(
+                        index,
+                        PendingToolUse {
+                            id,
+                            name,
+                            input_json: String::new(),
+                        },
+                    );
+                }
+                _ => {}
+            },
+            StreamEvent::ContentBlockDelta { index, delta } => match delta {
+                BlockDelta::TextDelta { text } => events.push(CompletionEvent::Text(text)),
+                BlockDelta::InputJsonDelta { partial_json } => {
+                    if let Some(tool_use) = self.tool_uses.get_mut(&index) {
+                        tool_use.input_json.push_str(&partial_json);
+                    }
+                }
+                BlockDelta::Unknown => {}
+            },
+            StreamEvent::ContentBlockStop { index } => {
+                if let Some(tool_use) = self.tool_uses.remove(&index) {
+                    events.push(tool_use_event(tool_use)?);
+                }
+            }
+            StreamEvent::MessageDelta { delta, usage } => {
+                if let Some(reason) = delta.stop_reason {
+                    self.stop_reason = Some(match reason.as_str() {
+                        "max_tokens" => StopReason::MaxTokens,
+                        "tool_use" => StopReason::ToolUse,
+                        _ => StopReason::EndTurn,
+                    });
+                }
+                if let Some(usage) = usage {
+                    self.apply_usage(usage);
+                    events.push(CompletionEvent::Usage(self.usage));
+                }
+            }
+            StreamEvent::MessageStop => events.extend(self.finish()?),
+            StreamEvent::Ping => {}
+            StreamEvent::Error { error } => {
+                return Err(anyhow!("Anthropic error ({}): {}", error.kind, error.message));
+            }
+        }
+        Ok(events)
+    }
+
+    fn apply_usage(&mut self, usage: Usage) {
+        if let Some(input_tokens) = usage.input_tokens {
+            self.usage.input_tokens = input_tokens;
+        }
+        if let Some(output_tokens) = usage.output_tokens {
+            self.usage.output_tokens = output_tokens;
+        }
+    }
+}
+
+impl SseEventMapper for AnthropicEventMapper {
+    fn map_event(&mut self, event: SseEvent) -> Result<Vec<CompletionEvent>> {
+        self.map_payload(&event.data)
+    }
+
+    /// Flushes tool uses whose block never saw a stop.
+    fn finish(&mut self) -> Result<Vec<CompletionEvent>> {
+        let mut events = std::mem::take(&mut self.tool_uses)
+            .into_values()
+            .map(tool_use_event)
+            .collect::<Result<Vec<_>>>()?;
+        let stop_reason = self.stop_reason.take().unwrap_or(StopReason::EndTurn);
+        events.push(CompletionEvent::Stop(stop_reason));
+        Ok(events)
+    }
+}
+
+fn tool_use_event(tool_use: PendingToolUse) -> Result<CompletionEvent> {
+    let arguments = if tool_use.input_json.trim().is_empty() {
+        json!({})
+    } else {
+        serde_json::from_str(&tool_use.input_json).with_context(|| {
+            format!("invalid input for tool use {}: {}", tool_use.name, tool_use.input_json)
+        })?
+    };
+    Ok(CompletionEvent::ToolUse(ToolCall {
+        id: tool_use.id,
+        name: tool_use.name,
+        arguments,
+        status: Default::default(),
+    }))
+}
+
+impl AgentBackend for AnthropicBackend {
+    fn name(&self) -> SharedString {
+        PROVIDER_ID.into()
+    }
+
+    fn stream_completion(
+        &self,
+        request: ChatRequest,
+        _cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let url = format!("{}/v1/messages", self.settings.base_url());
+        let api_key = self.settings.api_key.clone();
+        let http_client = self.http_client.clone();
+        async move {
+            let (_, model) = parse_model_id(&request.model)?;
+            let api_key = api_key.ok_or_else(|| anyhow!("{API_KEY_ENV_VAR} is not set"))?;
+            let (system, messages) = request_messages(&request.messages);
+            let body = serde_json::to_string(&AnthropicRequest {
+                model: model.to_string(),
+                max_tokens: request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+                system,
+                messages,
+                stream: true,
+                temperature: request.temperature,
+                stop_sequences: request.stop,
+                tools: request
+                    .tools
+                    .iter()
+                    .map(|tool| {
+                        json!({
+                            "name": tool.name,
+                            "description": tool.description,
+                            "input_schema": tool.input_schema,
+                        })
+                    })
+                    .collect(),
+            })?;
+            let request = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(url)
+                .header("Content-Type", "application/json")
+                .header("Accept", "text/event-stream")
+                .header("x-api-key", api_key)
+                .header("anthropic-version", API_VERSION)
+                .body(AsyncBody::from(body))?;
+            let mut response = http_client.send(request).await?;
+            if !response.status().is_success() {
+                let mut body = String::new();
+                response.body_mut().read_to_string(&mut body).await?;
+                return Err(anyhow!("Anthropic request failed: {} {}", response.status(), body));
+            }
+
+            Ok(sse_completion_events(response.into_body(), AnthropicEventMapper::default()))
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,383 @@
+// Assistant settings
+// App-wide preferences, registered with the settings store. They sit at the
+// top level of `settings.json` in the platform config dir (or wherever
+// `CODE_AGENT_SETTINGS` points), next to any other settings, and the store
+// reloads them whenever the file changes. The file content has a JSON schema;
+// what the schema can't express, like the form of a model id, is checked when
+// the settings load, and a file that fails keeps the previous settings.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use fs::Fs;
+use futures::StreamExt;
+use gpui::{App, Global, SharedString, Task};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsSources, SettingsStore};
+
+use crate::model_catalog::parse_model_id;
+
+pub const SETTINGS_ENV_VAR: &str = "CODE_AGENT_SETTINGS";
+// Font size bounds, repeated as literals in the schema attributes below
+pub const MIN_FONT_SIZE: f32 = 8.0;
+pub const MAX_FONT_SIZE: f32 = 40.0;
+// About a century; longer periods would overflow date arithmetic
+pub const MAX_HISTORY_RETENTION_DAYS: u32 = 36_500;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SendKeybinding {
+    #[default]
+    Enter,
+    CmdEnter,
+}
+
+impl SendKeybinding {
+    pub const ALL: [Self; 2] = [Self::Enter, Self::CmdEnter];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Enter => "Enter",
+            Self::CmdEnter if cfg!(target_os = "macos") => "Cmd-Enter",
+            Self::CmdEnter => "Ctrl-Enter",
+        }
+    }
+}
+
+/// What happens when the model calls a tool that needs approval.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolApproval {
+    /// Ask unless the workspace's tool policy already allows the call.
+    #[default]
+    Ask,
+    AlwaysAllow,
+    Deny,
+}
+
+impl ToolApproval {
+    pub const ALL: [Self; 3] = [Self::Ask, Self::AlwaysAllow, Self::Deny];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ask => "Ask",
+            Self::AlwaysAllow => "Always allow",
+            Self::Deny => "Deny",
+        }
+    }
+}
+
+/// Base URLs that take precedence over the providers' environment variables.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderEndpoints {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_agent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openai: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anthropic: Option<String>,
+    /// Also accepts Ollama's `host:port` form.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ollama: Option<String>,
+}
+
+/// OpenAI-compatible provider options besides its endpoint. Each falls back
+/// to its environment variable when unset.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenAiOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Model added to the catalog as `openai/<model>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontSizes {
+    /// Text size of the transcript, in pixels.
+    #[schemars(range(min = 8.0, max = 40.0))]
+    pub messages: f32,
+    /// Text size of the message input, in pixels.
+    #[schemars(range(min = 8.0, max = 40.0))]
+    pub input: f32,
+}
+
+impl Default for FontSizes {
+    fn default() -> Self {
+        Self {
+            messages: 15.0,
+            input: 16.0,
+        }
+    }
+}
+
+/// The resolved settings: the file's values over the defaults.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AssistantSettings {
+    /// `provider/model` id; `None` keeps the model catalog's default.
+    pub default_model: Option<String>,
+    pub endpoints: ProviderEndpoints,
+    pub openai: OpenAiOptions,
+    pub send_keybinding: SendKeybinding,
+    pub font_sizes: FontSizes,
+    pub tool_approval: ToolApproval,
+    /// Conversations not updated for this many days are deleted; `None`
+    /// keeps them forever.
+    pub history_retention_days: Option<u32>,
+}
+
+/// The assistant's keys in the settings file. Every key is optional, and
+/// other settings may share the file, so unknown keys are left alone.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AssistantSettingsContent {
+    /// Model used for new conversations, as `provider/model`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_model: Option<String>,
+    /// Provider base URLs, overriding their environment variables.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoints: Option<ProviderEndpoints>,
+    /// OpenAI-compatible provider key and model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openai: Option<OpenAiOptions>,
+    /// Whether Enter or Cmd-Enter sends a message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_keybinding: Option<SendKeybinding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_sizes: Option<FontSizes>,
+    /// What happens when the model calls a tool that needs approval.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_approval: Option<ToolApproval>,
+    /// Delete conversations not updated for this many days.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1, max = 36500))]
+    pub history_retention_days: Option<u32>,
+}
+
+impl From<AssistantSettings> for AssistantSettingsContent {
+    fn from(settings: AssistantSettings) -> Self {
+        Self {
+            default_model: settings.default_model,
+            endpoints: Some(settings.endpoints),
+            openai: Some(settings.openai),
+            send_keybinding: Some(settings.send_keybinding),
+            font_sizes: Some(settings.font_sizes),
+            tool_approval: Some(settings.tool_approval),
+            history_retention_days: settings.history_retention_days,
+        }
+    }
+}
+
+impl Settings for AssistantSettings {
+    // The assistant owns the top level of its settings file
+    const KEY: Option<&'static str> = None;
+
+    type FileContent = AssistantSettingsContent;
+
+    fn load(sources: SettingsSources<Self::FileContent>, _: &mut App) -> Result<Self> {
+        let settings: Self = sources.json_merge()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn import_from_vscode(_: &settings::VsCodeSettings, _: &mut Self::FileContent) {}
+}
+
+impl AssistantSettings {
+    /// Checks the values serde can't, reporting every problem at once.
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        if let Some(model) = &self.default_model {
+            if let Err(error) = parse_model_id(model) {
+                errors.push(format!("default_model: {error}"));
+            }
+        }
+        let endpoints = [
+            ("code_agent", &self.endpoints.code_agent, true),
+            ("openai", &self.endpoints.openai, true),
+            ("anthropic", &self.endpoints.anthropic, true),
+            ("ollama", &self.endpoints.ollama, false),
+        ];
+        for (name, url, needs_scheme) in endpoints {
+            let Some(url) = url else {
+                continue;
+            };
+            let has_scheme = url.starts_with("http://") || url.starts_with("https://");
+            if url.trim().is_empty() || (needs_scheme && !has_scheme) {
+                errors.push(format!("endpoints.{name}: {url:?} is not an http(s) URL"));
+            }
+        }
+        if let Some(model) = &self.openai.model {
+            if model.trim().is_empty() || model.contains(char::is_whitespace) {
+                errors.push(format!("openai.model: {model:?} is not a model name"));
+            }
+        }
+        for (name, size) in [
+            ("messages", self.font_sizes.messages),
+            ("input", self.font_sizes.input),
+        ] {
+            if !(MIN_FONT_SIZE..=MAX_FONT_SIZE).contains(&size) {
+                errors.push(format!(
+                    "font_sizes.{name}: {size} is outside {MIN_FONT_SIZE}–{MAX_FONT_SIZE}"
+                ));
+            }
+        }
+        if let Some(days) = self.history_retention_days {
+            if !(1..=MAX_HISTORY_RETENTION_DAYS).contains(&days) {
+                errors.push(format!(
+                    "history_retention_days: {days} is outside 1–{MAX_HISTORY_RETENTION_DAYS}"
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(errors.join("; ")))
+        }
+    }
+
+    /// Conversations last updated before this are past the retention period.
+    pub fn retention_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let days = self.history_retention_days?;
+        now.checked_sub_signed(TimeDelta::try_days(days.into())?)
+    }
+}
+
+/// Uses `CODE_AGENT_SETTINGS` if set, otherwise the platform config dir.
+pub fn settings_file_path() -> PathBuf {
+    std::env::var_os(SETTINGS_ENV_VAR)
+        .map(PathBuf::from)
+        .or_else(|| dirs::config_dir().map(|dir| dir.join("agent-ui").join("settings.json")))
+        .unwrap_or_else(|| PathBuf::from(".agent-ui/settings.json"))
+}
+
+/// The settings file and why its last change wasn't applied, if it wasn't.
+pub struct SettingsFile {
+    fs: Arc<dyn Fs>,
+    path: PathBuf,
+    error: Option<SharedString>,
+}
+
+impl Global for SettingsFile {}
+
+impl SettingsFile {
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn error(&self) -> Option<&SharedString> {
+        self.error.as_ref()
+    }
+}
+
+/// Registers the assistant settings and keeps them in sync with the settings
+/// file. Sets up a settings store if the app has none; calling it again does
+/// nothing.
+pub fn init(cx: &mut App) {
+    if cx.has_global::<SettingsFile>() {
+        return;
+    }
+    if !cx.has_global::<SettingsStore>() {
+        settings::init(cx);
+    }
+    AssistantSettings::register(cx);
+
+    let executor = cx.background_executor().clone();
+    let fs: Arc<dyn Fs> = Arc::new(fs::RealFs::new(None, executor.clone()));
+    let path = settings_file_path();
+    let mut contents = settings::watch_config_file(&executor, fs.clone(), path.clone());
+    cx.set_global(SettingsFile {
+        fs,
+        path,
+        error: None,
+    });
+    cx.spawn(async move |cx| {
+        while let Some(content) = contents.next().await {
+            let updated = cx.update(|cx| {
+                let result = cx.update_global::<SettingsStore, _>(|store, cx| {
+                    store.set_user_settings(&content, cx)
+                });
+                cx.update_global::<SettingsFile, _>(|file, _| {
+                    file.error = result.err().map(|error| format!("{error:#}").into());
+                });
+            });
+            if updated.is_err() {
+                break;
+            }
+        }
+    })
+    .detach();
+}
+
+/// Writes `settings` to the settings file, changing only the assistant's keys
+/// and keeping the rest of the file, comments included. The file watcher
+/// then applies them like any other edit.
+pub fn save_settings(settings: AssistantSettings, cx: &App) -> Task<Result<()>> {
+    let Some(file) = cx.try_global::<SettingsFile>() else {
+        return Task::ready(Err(anyhow!("assistant settings are not initialized")));
+    };
+    if let Err(error) = settings.validate() {
+        return Task::ready(Err(error));
+    }
+    let fs = file.fs.clone();
+    let path = file.path.clone();
+    cx.spawn(async move |cx| {
+        let old_text = if fs.is_file(&path).await {
+            fs.load(&path).await?
+        } else {
+            String::new()
+        };
+        let new_text = cx.read_global(|store: &SettingsStore, _| {
+            store.new_text_for_update::<AssistantSettings>(old_text, |content| {
+                *content = settings.into();
+            })
+        })?;
+        if let Some(parent) = path.parent() {
+            fs.create_dir(parent).await?;
+        }
+        fs.atomic_write(path, new_text).await
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_is_bounded() {
+        let mut settings = AssistantSettings {
+            history_retention_days: Some(MAX_HISTORY_RETENTION_DAYS),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_ok());
+
+        settings.history_retention_days = Some(MAX_HISTORY_RETENTION_DAYS + 1);
+        assert!(settings.validate().is_err());
+        settings.history_retention_days = Some(0);
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn retention_cutoff_does_not_overflow() {
+        let settings = AssistantSettings {
+            history_retention_days: Some(999_999_999),
+            ..Default::default()
+        };
+        assert_eq!(settings.retention_cutoff(DateTime::<Utc>::MIN_UTC), None);
+        assert!(settings.retention_cutoff(Utc::now()).is_none());
+
+        let settings = AssistantSettings {
+            history_retention_days: Some(30),
+            ..Default::default()
+        };
+        let now = Utc::now();
+        assert_eq!(settings.retention_cutoff(now), Some(now - TimeDelta::days(30)));
+    }
+}
@@ -0,0 +1,484 @@
+// Assistant settings view
+// Edits the app-wide settings; saving writes the settings file, and the
+// settings store applies it from there
+
+use std::path::PathBuf;
+
+use gpui::{
+    div, px, rgb, App, Context, Div, Entity, EventEmitter, FocusHandle, Focusable, FontWeight,
+    IntoElement, MouseButton, ParentElement, Render, SharedString, Stateful, Styled,
+    Subscription, Window, prelude::*,
+};
+
+use crate::assistant_settings::{
+    AssistantSettings, FontSizes, OpenAiOptions, ProviderEndpoints, SendKeybinding,
+    ToolApproval, MAX_FONT_SIZE, MAX_HISTORY_RETENTION_DAYS, MIN_FONT_SIZE,
+};
+use crate::interactive_chatbox::{InteractiveChatInput, Send};
+use crate::model_catalog::parse_model_id;
+
+#[derive(Clone, Debug)]
+pub enum AssistantSettingsEvent {
+    Saved(AssistantSettings),
+    Dismissed,
+}
+
+pub struct AssistantSettingsView {
+    path: PathBuf,
+    default_model: Entity<InteractiveChatInput>,
+    code_agent_endpoint: Entity<InteractiveChatInput>,
+    openai_endpoint: Entity<InteractiveChatInput>,
+    openai_api_key: Entity<InteractiveChatInput>,
+    openai_model: Entity<InteractiveChatInput>,
+    anthropic_endpoint: Entity<InteractiveChatInput>,
+    ollama_endpoint: Entity<InteractiveChatInput>,
+    message_font_size: Entity<InteractiveChatInput>,
+    input_font_size: Entity<InteractiveChatInput>,
+    history_retention_days: Entity<InteractiveChatInput>,
+    send_keybinding: SendKeybinding,
+    tool_approval: ToolApproval,
+    error: Option<SharedString>,
+    focus_handle: FocusHandle,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl EventEmitter<AssistantSettingsEvent> for AssistantSettingsView {}
+
+impl AssistantSettingsView {
+    pub fn new(settings: &AssistantSettings, path: PathBuf, cx: &mut Context<Self>) -> Self {
+        let or_empty = |text: &Option<String>| text.clone().unwrap_or_default();
+        let mut this = Self {
+            default_model: Self::model_field(settings.default_model.clone(), cx),
+            code_agent_endpoint: Self::field(
+                "From CODE_AGENT_BASE_URL",
+                or_empty(&settings.endpoints.code_agent),
+                cx,
+            ),
+            openai_endpoint: Self::field(
+                "From CODE_AGENT_OPENAI_BASE_URL",
+                or_empty(&settings.endpoints.openai),
+                cx,
+            ),
+            openai_api_key: Self::secret_field(
+                "From OPENAI_API_KEY",
+                or_empty(&settings.openai.api_key),
+                cx,
+            ),
+            openai_model: Self::field(
+                "From CODE_AGENT_OPENAI_MODEL",
+                or_empty(&settings.openai.model),
+                cx,
+            ),
+            anthropic_endpoint: Self::field(
+                "From CODE_AGENT_ANTHROPIC_BASE_URL",
+                or_empty(&settings.endpoints.anthropic),
+                cx,
+            ),
+            ollama_endpoint: Self::field(
+                "From OLLAMA_HOST",
+                or_empty(&settings.endpoints.ollama),
+                cx,
+            ),
+            message_font_size: Self::font_size_field("15", settings.font_sizes.messages, cx),
+            input_font_size: Self::font_size_field("16", settings.font_sizes.input, cx),
+            history_retention_days: Self::retention_field(settings.history_retention_days, cx),
+            send_keybinding: settings.send_keybinding,
+            tool_approval: settings.tool_approval,
+            path,
+            error: None,
+            focus_handle: cx.focus_handle(),
+            _subscriptions: Vec::new(),
+        };
+        // Re-render so the save button follows the fields' validity
+        this._subscriptions = this
+            .inputs()
+            .into_iter()
+            .map(|input| cx.observe(input, |_, _, cx| cx.notify()))
+            .collect();
+        this
+    }
+
+    fn field(
+        placeholder: &str,
+        text: String,
+        cx: &mut Context<Self>,
+    ) -> Entity<InteractiveChatInput> {
+        let placeholder = SharedString::from(placeholder.to_string());
+        cx.new(|cx| {
+            let mut input = InteractiveChatInput::new(cx);
+            input.set_placeholder(placeholder);
+            input.set_text(text, cx);
+            input
+        })
+    }
+
+    fn secret_field(
+        placeholder: &str,
+        text: String,
+        cx: &mut Context<Self>,
+    ) -> Entity<InteractiveChatInput> {
+        let input = Self::field(placeholder, text, cx);
+        input.update(cx, |input, cx| {
+            input.set_secret(true, cx);
+            input.set_reveal_toggle(true, cx);
+        });
+        input
+    }
+
+    fn model_field(model: Option<String>, cx: &mut Context<Self>) -> Entity<InteractiveChatInput> {
+        let input = Self::field("Model catalog default", model.unwrap_or_default(), cx);
+        input.update(cx, |input, cx| {
+            input.set_validator(
+                |text| match text.trim() {
+                    "" => Ok(()),
+                    model => parse_model_id(model)
+                        .map(|_| ())
+                        .map_err(|error| error.to_string().into()),
+                },
+                cx,
+            );
+        });
+        input
+    }
+
+    fn font_size_field(
+        placeholder: &str,
+        size: f32,
+        cx: &mut Context<Self>,
+    ) -> Entity<InteractiveChatInput> {
+        let input = Self::field(placeholder, size.to_string(), cx);
+        input.update(cx, |input, cx| {
+            input.set_char_filter(|ch| ch.is_ascii_digit() || ch == '.');
+            input.set_max_length(Some(5), cx);
+            input.set_validator(
+                |text| match text.trim().parse::<f32>() {
+                    Ok(size) if (MIN_FONT_SIZE..=MAX_FONT_SIZE).contains(&size) => Ok(()),
+                    _ => Err(format!("Enter a size from {MIN_FONT_SIZE} to {MAX_FONT_SIZE}").into()),
+                },
+                cx,
+            );
+        });
+        input
+    }
+
+    fn retention_field(days: Option<u32>, cx: &mut Context<Self>) -> Entity<InteractiveChatInput> {
+        let days = days.map(|days| days.to_string()).unwrap_or_default();
+        let input = Self::field("Keep forever", days, cx);
+        input.update(cx, |input, cx| {
+            input.set_char_filter(|ch| ch.is_ascii_digit());
+            input.set_max_length(Some(MAX_HISTORY_RETENTION_DAYS.to_string().len()), cx);
+            input.set_validator(
+                |text| match text.trim() {
+                    "" => Ok(()),
+                    days => match days.parse::<u32>() {
+                        Ok(days) if (1..=MAX_HISTORY_RETENTION_DAYS).contains(&days) => Ok(()),
+                        _ => Err(format!(
+                            "Enter 1 to {MAX_HISTORY_RETENTION_DAYS} days, or leave empty"
+                        )
+                        .into()),
+                    },
+                },
+                cx,
+            );
+        });
+        input
+    }
+
+    fn inputs(&self) -> [&Entity<InteractiveChatInput>; 10] {
+        [
+            &self.default_model,
+            &self.code_agent_endpoint,
+            &self.openai_endpoint,
+            &self.openai_api_key,
+            &self.openai_model,
+            &self.anthropic_endpoint,
+            &self.ollama_endpoint,
+            &self.message_font_size,
+            &self.input_font_size,
+            &self.history_retention_days,
+        ]
+    }
+
+    // Saving stays disabled while any field shows an error
+    fn is_valid(&self, cx: &App) -> bool {
+        self.inputs().iter().all(|input| input.read(cx).is_valid())
+    }
+
+    fn parse(&self, cx: &App) -> Result<AssistantSettings, SharedString> {
+        let optional = |input: &Entity<InteractiveChatInput>| {
+            let text = input.read(cx).get_text();
+            let text = text.trim();
+            (!text.is_empty()).then(|| text.to_string())
+        };
+        let font_size = |input: &Entity<InteractiveChatInput>, label: &str| {
+            input
+                .read(cx)
+                .get_text()
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| SharedString::from(format!("{label} font size must be a number")))
+        };
+
+        let history_retention_days = match optional(&self.history_retention_days) {
+            None => None,
+            Some(days) => match days.parse::<u32>() {
+                Ok(days) => Some(days),
+                Err(_) => return Err("History retention must be a whole number of days".into()),
+            },
+        };
+
+        let settings = AssistantSettings {
+            default_model: optional(&self.default_model),
+            endpoints: ProviderEndpoints {
+                code_agent: optional(&self.code_agent_endpoint),
+                openai: optional(&self.openai_endpoint),
+                anthropic: optional(&self.anthropic_endpoint),
+                ollama: optional(&self.ollama_endpoint),
+            },
+            openai: OpenAiOptions {
+                api_key: optional(&self.openai_api_key),
+                model: optional(&self.openai_model),
+            },
+            send_keybinding: self.send_keybinding,
+            font_sizes: FontSizes {
+                messages: font_size(&self.message_font_size, "Message")?,
+                input: font_size(&self.input_font_size, "Input")?,
+            },
+            tool_approval: self.tool_approval,
+            history_retention_days,
+        };
+        settings
+            .validate()
+            .map_err(|error| SharedString::from(error.to_string()))?;
+        Ok(settings)
+    }
+
+    pub fn save(&mut self, cx: &mut Context<Self>) {
+        if !self.is_valid(cx) {
+            return;
+        }
+        match self.parse(cx) {
+            Ok(settings) => {
+                self.error = None;
+                cx.emit(AssistantSettingsEvent::Saved(settings));
+            }
+            Err(error) => self.error = Some(error),
+        }
+        cx.notify();
+    }
+
+    pub fn dismiss(&mut self, cx: &mut Context<Self>) {
+        cx.emit(AssistantSettingsEvent::Dismissed);
+    }
+
+    fn on_send(&mut self, _: &Send, _window: &mut Window, cx: &mut Context<Self>) {
+        self.save(cx);
+    }
+
+    fn render_field(label: &str, input: &Entity<InteractiveChatInput>) -> impl IntoElement {
+        div()
+            .flex()
+            .flex_col()
+            .gap_1()
+            .child(Self::render_label(label))
+            .child(input.clone())
+    }
+
+    fn render_label(label: &str) -> impl IntoElement {
+        div()
+            .text_color(rgb(0x374151))
+            .text_size(px(12.0))
+            .font_weight(FontWeight::MEDIUM)
+            .child(label.to_string())
+    }
+
+    fn render_choice(
+        id: (&'static str, usize),
+        label: &'static str,
+        selected: bool,
+    ) -> Stateful<Div> {
+        div()
+            .id(id)
+            .cursor_pointer()
+            .px_2()
+            .py_1()
+            .rounded_md()
+            .text_size(px(12.0))
+            .border_1()
+            .when(selected, |this| {
+                this.bg(rgb(0xe3f2fd))
+                    .border_color(rgb(0x2196f3))
+                    .text_color(rgb(0x1976d2))
+            })
+            .when(!selected, |this| {
+                this.bg(rgb(0xffffff))
+                    .border_color(rgb(0xd1d5db))
+                    .text_color(rgb(0x374151))
+            })
+            .child(label)
+    }
+}
+
+impl Focusable for AssistantSettingsView {
+    fn focus_handle(&self, _: &App) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Render for AssistantSettingsView {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let valid = self.is_valid(cx);
+
+        div()
+            .key_context("AssistantSettingsView")
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::on_send))
+            .flex()
+            .flex_col()
+            .gap_3()
+            .p_4()
+            .bg(rgb(0xffffff))
+            .border_b_1()
+            .border_color(rgb(0xe5e7eb))
+            .child(
+                div()
+                    .flex()
+                    .items_baseline()
+                    .justify_between()
+                    .child(
+                        div()
+                            .text_color(rgb(0x111827))
+                            .text_size(px(15.0))
+                            .font_weight(FontWeight::BOLD)
+                            .child("Assistant settings")
+                    )
+                    .child(
+                        div()
+                            .text_color(rgb(0x6b7280))
+                            .text_size(px(11.0))
+                            .child(self.path.display().to_string())
+                    )
+            )
+            .child(Self::render_field("Default model", &self.default_model))
+            .child(
+                div()
+                    .flex()
+                    .gap_3()
+                    .child(div().flex_1().child(Self::render_field("Code agent endpoint", &self.code_agent_endpoint)))
+                    .child(div().flex_1().child(Self::render_field("OpenAI endpoint", &self.openai_endpoint)))
+            )
+            .child(
+                div()
+                    .flex()
+                    .gap_3()
+                    .child(div().flex_1().child(Self::render_field("OpenAI API key", &self.openai_api_key)))
+                    .child(div().flex_1().child(Self::render_field("OpenAI model", &self.openai_model)))
+            )
+            .child(
+                div()
+                    .flex()
+                    .gap_3()
+                    .child(div().flex_1().child(Self::render_field("Anthropic endpoint", &self.anthropic_endpoint)))
+                    .child(div().flex_1().child(Self::render_field("Ollama host", &self.ollama_endpoint)))
+            )
+            .child(
+                div()
+                    .flex()
+                    .gap_3()
+                    .child(div().flex_1().child(Self::render_field("Message font size", &self.message_font_size)))
+                    .child(div().flex_1().child(Self::render_field("Input font size", &self.input_font_size)))
+                    .child(div().flex_1().child(Self::render_field("Keep history (days)", &self.history_retention_days)))
+            )
+            .child(
+                div()
+                    .flex()
+                    .gap_6()
+                    .child(
+                        div()
+                            .flex()
+                            .flex_col()
+                            .gap_1()
+                            .child(Self::render_label("Send with"))
+                            .child(
+                                div().flex().gap_1().children(SendKeybinding::ALL.into_iter().enumerate().map(
+                                    |(ix, keybinding)| {
+                                        Self::render_choice(
+                                            ("send-keybinding", ix),
+                                            keybinding.label(),
+                                            keybinding == self.send_keybinding,
+                                        )
+                                        .on_click(cx.listener(move |this, _, _, cx| {
+                                            this.send_keybinding = keybinding;
+                                            cx.notify();
+                                        }))
+                                    },
+                                ))
+                            )
+                    )
+                    .child(
+                        div()
+                            .flex()
+                            .flex_col()
+                            .gap_1()
+                            .child(Self::render_label("Tools that need approval"))
+                            .child(
+                                div().flex().gap_1().children(ToolApproval::ALL.into_iter().enumerate().map(
+                                    |(ix, approval)| {
+                                        Self::render_choice(
+                                            ("tool-approval", ix),
+                                            approval.label(),
+                                            approval == self.tool_approval,
+                                        )
+                                        .on_click(cx.listener(move |this, _, _, cx| {
+                                            this.tool_approval = approval;
+                                            cx.notify();
+                                        }))
+                                    },
+                                ))
+                            )
+                    )
+            )
+            .when_some(self.error.clone(), |this, error| {
+                this.child(
+                    div()
+                        .text_color(rgb(0xdc2626))
+                        .text_size(px(12.0))
+                        .child(error)
+                )
+            })
+            .child(
+                div()
+                    .flex()
+                    .justify_end()
+                    .gap_2()
+                    .child(
+                        div()
+                            .px_3()
+                            .py_1()
+                            .bg(rgb(0x6c757d))
+                            .text_color(rgb(0xffffff))
+                            .rounded_sm()
+                            .text_sm()
+                            .cursor_pointer()
+                            .on_mouse_up(MouseButton::Left, cx.listener(|this, _, _, cx| this.dismiss(cx)))
+                            .child("Cancel")
+                    )
+                    .child(
+                        div()
+                            .px_3()
+                            .py_1()
+                            .bg(if valid { rgb(0x2196f3) } else { rgb(0x90caf9) })
+                            .text_color(rgb(0xffffff))
+                            .rounded_sm()
+                            .text_sm()
+                            .when(valid, |this| {
+                                this.cursor_pointer().on_mouse_up(
+                                    MouseButton::Left,
+                                    cx.listener(|this, _, _, cx| this.save(cx)),
+                                )
+                            })
+                            .child("Save")
+                    )
+            )
+    }
+}
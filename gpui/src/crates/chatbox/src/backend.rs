@@ -0,0 +1,466 @@
+// Agent backends
+// A backend turns a `ChatRequest` into a stream of completion events. The
+// default backend talks to the code-agent service's `/chat/stream` and `/chat`
+// endpoints, the same ones used by the mac client. `ProviderRouter` sends
+// models of providers we speak to natively (e.g. `openai/…`) to their own
+// backend instead.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use futures::{future::BoxFuture, stream::BoxStream, AsyncReadExt, FutureExt, StreamExt};
+use gpui::{AsyncApp, SharedString};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::conversation::{RequestMessage, Role, ToolCall};
+use crate::model_catalog::parse_model_id;
+use crate::sse::{sse_events, SseEvent};
+use crate::tools::ToolDefinition;
+
+pub const BASE_URL_ENV_VAR: &str = "CODE_AGENT_BASE_URL";
+pub const API_KEY_ENV_VAR: &str = "CODE_AGENT_API_KEY";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatRequest {
+    /// `provider/model` id.
+    pub model: String,
+    pub messages: Vec<RequestMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// Tools the model may call.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolDefinition>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    EndTurn,
+    MaxTokens,
+    ToolUse,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CompletionEvent {
+    Text(String),
+    ToolUse(ToolCall),
+    /// Token counts reported by the provider for this completion.
+    Usage(TokenUsage),
+    Stop(StopReason),
+}
+
+/// Turns a provider's server-sent events into completion events.
+pub trait SseEventMapper: Send + 'static {
+    fn map_event(&mut self, event: SseEvent) -> Result<Vec<CompletionEvent>>;
+
+    /// Called when the body ends without the mapper having produced a stop.
+    fn finish(&mut self) -> Result<Vec<CompletionEvent>>;
+}
+
+/// Decodes an SSE body through `mapper`. The stream ends after the first
+/// stop event or error.
+pub fn sse_completion_events(
+    body: impl futures::AsyncRead + Unpin + Send + 'static,
+    mapper: impl SseEventMapper,
+) -> BoxStream<'static, Result<CompletionEvent>> {
+    let state = (sse_events(body), mapper, VecDeque::new(), false);
+    futures::stream::unfold(state, |state| async move {
+        let (mut events, mut mapper, mut queue, mut done) = state;
+        loop {
+            if let Some(event) = queue.pop_front() {
+                let stopped = matches!(event, CompletionEvent::Stop(_));
+                return Some((Ok(event), (events, mapper, queue, done || stopped)));
+            }
+            if done {
+                return None;
+            }
+            let batch = match events.next().await {
+                Some(Ok(event)) => mapper.map_event(event),
+                Some(Err(error)) => Err(error),
+                None => {
+                    done = true;
+                    mapper.finish()
+                }
+            };
+            match batch {
+                Ok(batch) => queue.extend(batch),
+                Err(error) => return Some((Err(error), (events, mapper, queue, true))),
+            }
+        }
+    })
+    .boxed()
+}
+
+pub trait AgentBackend: Send + Sync + 'static {
+    fn name(&self) -> SharedString;
+
+    fn stream_completion(
+        &self,
+        request: ChatRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>>;
+}
+
+// Backend for the code-agent service. Replies stream from `/chat/stream`;
+// servers without it get the same request on `/chat`.
+pub struct CodeAgentBackend {
+    base_url: Option<String>,
+    api_key: Option<String>,
+    http_client: Arc<dyn HttpClient>,
+}
+
+#[derive(Serialize)]
+struct CodeAgentRequest<'a> {
+    model: &'a str,
+    messages: Vec<CodeAgentMessage<'a>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'a [String],
+    #[serde(skip_serializing_if = "<[ToolDefinition]>::is_empty")]
+    tools: &'a [ToolDefinition],
+}
+
+// The service reads `role` and `content`, so attachments go inline in the
+// content
+#[derive(Serialize)]
+struct CodeAgentMessage<'a> {
+    role: Role,
+    content: Cow<'a, str>,
+    #[serde(skip_serializing_if = "<[ToolCall]>::is_empty")]
+    tool_calls: &'a [ToolCall],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<&'a str>,
+}
+
+impl<'a> From<&'a RequestMessage> for CodeAgentMessage<'a> {
+    fn from(message: &'a RequestMessage) -> Self {
+        Self {
+            role: message.role,
+            content: message.text(),
+            tool_calls: &message.tool_calls,
+            tool_call_id: message.tool_call_id.as_deref(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CodeAgentResponse {
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<CodeAgentToolCall>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CodeAgentToolCall {
+    id: String,
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+impl From<CodeAgentToolCall> for ToolCall {
+    fn from(call: CodeAgentToolCall) -> Self {
+        ToolCall {
+            id: call.id,
+            name: call.name,
+            arguments: call.arguments,
+            status: Default::default(),
+        }
+    }
+}
+
+// `/chat/stream` sends the reply text as plain `data:` payloads, as the mac
+// client reads them. A tool call comes as a `tool_call` event holding the
+// same object `/chat` puts in `toolCalls`.
+#[derive(Default)]
+struct CodeAgentStreamMapper {
+    called_tools: bool,
+}
+
+impl SseEventMapper for CodeAgentStreamMapper {
+    fn map_event(&mut self, event: SseEvent) -> Result<Vec<CompletionEvent>> {
+        if event.data == "[DONE]" {
+            return self.finish();
+        }
+        match event.event.as_deref() {
+            Some("tool_call") => {
+                let call: CodeAgentToolCall = serde_json::from_str(&event.data)?;
+                self.called_tools = true;
+                Ok(vec![CompletionEvent::ToolUse(call.into())])
+            }
+            _ => Ok(vec![CompletionEvent::Text(event.data)]),
+        }
+    }
+
+    fn finish(&mut self) -> Result<Vec<CompletionEvent>> {
+        let stop_reason = if self.called_tools {
+            StopReason::ToolUse
+        } else {
+            StopReason::EndTurn
+        };
+        Ok(vec![CompletionEvent::Stop(stop_reason)])
+    }
+}
+
+impl CodeAgentBackend {
+    pub fn new(
+        base_url: Option<String>,
+        api_key: Option<String>,
+        http_client: Arc<dyn HttpClient>,
+    ) -> Self {
+        Self {
+            base_url: base_url.map(|url| url.trim_end_matches('/').to_string()),
+            api_key,
+            http_client,
+        }
+    }
+
+    pub fn from_env(http_client: Arc<dyn HttpClient>) -> Self {
+        Self::new(
+            std::env::var(BASE_URL_ENV_VAR).ok(),
+            std::env::var(API_KEY_ENV_VAR).ok(),
+            http_client,
+        )
+    }
+}
+
+fn code_agent_request(
+    url: String,
+    accept: &str,
+    api_key: Option<&str>,
+    body: String,
+) -> Result<HttpRequest<AsyncBody>> {
+    let mut request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(url)
+        .header("Content-Type", "application/json")
+        .header("Accept", accept);
+    if let Some(api_key) = api_key {
+        request_builder = request_builder.header("Authorization", format!("Bearer {api_key}"));
+    }
+    Ok(request_builder.body(AsyncBody::from(body))?)
+}
+
+impl AgentBackend for CodeAgentBackend {
+    fn name(&self) -> SharedString {
+        "code-agent".into()
+    }
+
+    fn stream_completion(
+        &self,
+        request: ChatRequest,
+        _cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        // Without a configured backend, reply with a stub like the mac client
+        let Some(base_url) = self.base_url.clone() else {
+            let content = format!(
+                "Stub response: configure {BASE_URL_ENV_VAR} to integrate with code-agent backend. Model: {}",
+                request.model
+            );
+            let events = vec![
+                Ok(CompletionEvent::Text(content)),
+                Ok(CompletionEvent::Stop(StopReason::EndTurn)),
+            ];
+            return futures::future::ready(Ok(futures::stream::iter(events).boxed())).boxed();
+        };
+
+        let api_key = self.api_key.clone();
+        let http_client = self.http_client.clone();
+        async move {
+            let request_body = |stream| {
+                serde_json::to_string(&CodeAgentRequest {
+                    model: &request.model,
+                    messages: request.messages.iter().map(Into::into).collect(),
+                    stream,
+                    temperature: request.temperature,
+                    max_tokens: request.max_tokens,
+                    stop: &request.stop,
+                    tools: &request.tools,
+                })
+            };
+            let mut response = http_client
+                .send(code_agent_request(
+                    format!("{base_url}/chat/stream"),
+                    "text/event-stream",
+                    api_key.as_deref(),
+                    request_body(true)?,
+                )?)
+                .await?;
+            if response.status().is_success() {
+                return Ok(sse_completion_events(
+                    response.into_body(),
+                    CodeAgentStreamMapper::default(),
+                ));
+            }
+            // Servers that don't stream get the whole reply from `/chat`
+            if matches!(response.status().as_u16(), 404 | 405 | 501) {
+                response = http_client
+                    .send(code_agent_request(
+                        format!("{base_url}/chat"),
+                        "application/json",
+                        api_key.as_deref(),
+                        request_body(false)?,
+                    )?)
+                    .await?;
+            }
+            let mut body = String::new();
+            response.body_mut().read_to_string(&mut body).await?;
+            if !response.status().is_success() {
+                return Err(anyhow!("code-agent request failed: {} {}", response.status(), body));
+            }
+
+            let response: CodeAgentResponse = serde_json::from_str(&body)?;
+            let stop_reason = match response.finish_reason.as_deref() {
+                Some("length") => StopReason::MaxTokens,
+                Some("tool_calls") => StopReason::ToolUse,
+                _ => StopReason::EndTurn,
+            };
+            let mut events = vec![Ok(CompletionEvent::Text(response.content))];
+            events.extend(
+                response
+                    .tool_calls
+                    .into_iter()
+                    .map(|call| Ok(CompletionEvent::ToolUse(call.into()))),
+            );
+            events.push(Ok(CompletionEvent::Stop(stop_reason)));
+            Ok(futures::stream::iter(events).boxed())
+        }
+        .boxed()
+    }
+}
+
+/// Routes each request by the provider half of its `provider/model` id,
+/// falling back to the default backend for unregistered providers.
+pub struct ProviderRouter {
+    providers: BTreeMap<String, Arc<dyn AgentBackend>>,
+    fallback: Arc<dyn AgentBackend>,
+}
+
+impl ProviderRouter {
+    pub fn new(fallback: Arc<dyn AgentBackend>) -> Self {
+        Self {
+            providers: BTreeMap::new(),
+            fallback,
+        }
+    }
+
+    pub fn register(&mut self, provider: impl Into<String>, backend: Arc<dyn AgentBackend>) {
+        self.providers.insert(provider.into(), backend);
+    }
+
+    pub fn backend_for(&self, model: &str) -> &Arc<dyn AgentBackend> {
+        parse_model_id(model)
+            .ok()
+            .and_then(|(provider, _)| self.providers.get(provider))
+            .unwrap_or(&self.fallback)
+    }
+}
+
+impl AgentBackend for ProviderRouter {
+    fn name(&self) -> SharedString {
+        "router".into()
+    }
+
+    fn stream_completion(
+        &self,
+        request: ChatRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        self.backend_for(&request.model).stream_completion(request, cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::Attachment;
+
+    fn stream_events(body: &'static str) -> Vec<CompletionEvent> {
+        let events = sse_completion_events(body.as_bytes(), CodeAgentStreamMapper::default());
+        futures::executor::block_on(events.map(Result::unwrap).collect())
+    }
+
+    #[test]
+    fn stream_maps_text_and_tool_calls() {
+        assert_eq!(
+            stream_events("data: Hello\n\ndata:  world\n\ndata: [DONE]\n\n"),
+            vec![
+                CompletionEvent::Text("Hello".into()),
+                CompletionEvent::Text(" world".into()),
+                CompletionEvent::Stop(StopReason::EndTurn),
+            ]
+        );
+        assert_eq!(
+            stream_events(concat!(
+                "data: Reading\n\n",
+                "event: tool_call\n",
+                "data: {\"id\":\"call_1\",\"name\":\"read_file\",",
+                "\"arguments\":{\"path\":\"a\"}}\n\n",
+            )),
+            vec![
+                CompletionEvent::Text("Reading".into()),
+                CompletionEvent::ToolUse(ToolCall {
+                    id: "call_1".into(),
+                    name: "read_file".into(),
+                    arguments: serde_json::json!({ "path": "a" }),
+                    status: Default::default(),
+                }),
+                CompletionEvent::Stop(StopReason::ToolUse),
+            ]
+        );
+    }
+
+    #[test]
+    fn request_sends_attachments_in_the_content() {
+        let mut message = RequestMessage::new(Role::User, "Explain this");
+        message.attachments.push(Attachment {
+            path: "src/main.rs".into(),
+            content: "fn main() {}\n".into(),
+        });
+        let messages = [RequestMessage::new(Role::System, "Be brief"), message];
+        let body = serde_json::to_value(CodeAgentRequest {
+            model: "code-agent/default",
+            messages: messages.iter().map(Into::into).collect(),
+            stream: true,
+            temperature: None,
+            max_tokens: None,
+            stop: &[],
+            tools: &[],
+        })
+        .unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "model": "code-agent/default",
+                "messages": [
+                    { "role": "system", "content": "Be brief" },
+                    {
+                        "role": "user",
+                        "content": "Explain this\n\n```src/main.rs\nfn main() {}\n```"
+                    },
+                ],
+                "stream": true,
+            })
+        );
+    }
+}
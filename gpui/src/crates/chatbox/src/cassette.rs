@@ -0,0 +1,446 @@
+// Record-and-replay ("cassette") backends
+// `RecordingBackend` wraps a real backend and appends every request with its
+// streamed events and their timing to a JSON cassette. `ReplayBackend` plays a
+// cassette back without any network, at the original pace or faster, so demos
+// and UI tests see the same stream every time.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
+use gpui::{AsyncApp, BackgroundExecutor, SharedString};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{AgentBackend, ChatRequest, CompletionEvent};
+
+pub const CASSETTE_ENV_VAR: &str = "CODE_AGENT_CASSETTE";
+// `record` or `replay` (the default)
+pub const CASSETTE_MODE_ENV_VAR: &str = "CODE_AGENT_CASSETTE_MODE";
+// Replay speed multiplier; 0 replays instantly
+pub const REPLAY_SPEED_ENV_VAR: &str = "CODE_AGENT_REPLAY_SPEED";
+// Set to 1 to replay the next recording when none matches the request
+pub const REPLAY_LENIENT_ENV_VAR: &str = "CODE_AGENT_REPLAY_LENIENT";
+
+const CASSETTE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedOutcome {
+    Event(CompletionEvent),
+    Error(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Milliseconds since the request was sent.
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub outcome: RecordedOutcome,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: ChatRequest,
+    pub events: Vec<RecordedEvent>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cassette {
+    pub version: u32,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Default for Cassette {
+    fn default() -> Self {
+        Self {
+            version: CASSETTE_VERSION,
+            interactions: Vec::new(),
+        }
+    }
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json =
+            fs::read_to_string(path).with_context(|| format!("reading cassette {}", path.display()))?;
+        let cassette: Self = serde_json::from_str(&json)
+            .with_context(|| format!("parsing cassette {}", path.display()))?;
+        if cassette.version != CASSETTE_VERSION {
+            return Err(anyhow!(
+                "cassette {} has version {}, expected {CASSETTE_VERSION}",
+                path.display(),
+                cassette.version
+            ));
+        }
+        Ok(cassette)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, json).with_context(|| format!("writing {}", temp_path.display()))?;
+        fs::rename(&temp_path, path).with_context(|| format!("writing {}", path.display()))
+    }
+}
+
+pub struct RecordingBackend {
+    inner: Arc<dyn AgentBackend>,
+    path: PathBuf,
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+impl RecordingBackend {
+    /// Appends to the cassette at `path` if it exists.
+    pub fn new(inner: Arc<dyn AgentBackend>, path: PathBuf) -> Result<Self> {
+        let cassette = if path.exists() {
+            Cassette::load(&path)?
+        } else {
+            Cassette::default()
+        };
+        Ok(Self {
+            inner,
+            path,
+            cassette: Arc::new(Mutex::new(cassette)),
+        })
+    }
+}
+
+// Saves the interaction once the stream is dropped, which covers streams
+// that run to completion as well as ones the view stops reading at `Stop`
+struct PendingRecording {
+    interaction: Interaction,
+    cassette: Arc<Mutex<Cassette>>,
+    path: PathBuf,
+    executor: BackgroundExecutor,
+}
+
+impl Drop for PendingRecording {
+    fn drop(&mut self) {
+        if self.interaction.events.is_empty() {
+            return;
+        }
+        self.cassette
+            .lock()
+            .unwrap()
+            .interactions
+            .push(self.interaction.clone());
+        // Saving under the lock keeps concurrent saves from writing an older
+        // snapshot over a newer one
+        let cassette = self.cassette.clone();
+        let path = self.path.clone();
+        self.executor
+            .spawn(async move {
+                if let Err(error) = cassette.lock().unwrap().save(&path) {
+                    log::error!("Failed to save cassette: {error:#}");
+                }
+            })
+            .detach();
+    }
+}
+
+impl AgentBackend for RecordingBackend {
+    fn name(&self) -> SharedString {
+        format!("{} (recording)", self.inner.name()).into()
+    }
+
+    fn stream_completion(
+        &self,
+        request: ChatRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let started_at = Instant::now();
+        let mut recording = PendingRecording {
+            interaction: Interaction {
+                request: request.clone(),
+                events: Vec::new(),
+            },
+            cassette: self.cassette.clone(),
+            path: self.path.clone(),
+            executor: cx.background_executor().clone(),
+        };
+        let response = self.inner.stream_completion(request, cx);
+        async move {
+            let events = match response.await {
+                Ok(events) => events,
+                Err(error) => {
+                    recording.interaction.events.push(RecordedEvent {
+                        at_ms: started_at.elapsed().as_millis() as u64,
+                        outcome: RecordedOutcome::Error(format!("{error:#}")),
+                    });
+                    return Err(error);
+                }
+            };
+            let events = events.map(move |event| {
+                let outcome = match &event {
+                    Ok(event) => RecordedOutcome::Event(event.clone()),
+                    Err(error) => RecordedOutcome::Error(format!("{error:#}")),
+                };
+                recording.interaction.events.push(RecordedEvent {
+                    at_ms: started_at.elapsed().as_millis() as u64,
+                    outcome,
+                });
+                event
+            });
+            Ok(events.boxed())
+        }
+        .boxed()
+    }
+}
+
+pub struct ReplayBackend {
+    cassette: Cassette,
+    speed: f32,
+    lenient: bool,
+    // Which interactions have been played, so repeated requests replay in
+    // recorded order
+    played: Mutex<Vec<bool>>,
+}
+
+impl ReplayBackend {
+    /// `speed` scales the recorded timing; 2.0 plays twice as fast and 0.0
+    /// skips all delays.
+    pub fn new(cassette: Cassette, speed: f32) -> Self {
+        let played = vec![false; cassette.interactions.len()];
+        Self {
+            cassette,
+            speed: speed.max(0.0),
+            lenient: false,
+            played: Mutex::new(played),
+        }
+    }
+
+    /// When set, a request that matches no recording gets the next unplayed
+    /// one instead of an error.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    pub fn load(path: &Path, speed: f32) -> Result<Self> {
+        Ok(Self::new(Cassette::load(path)?, speed))
+    }
+
+    // Finds the first unplayed recording of the same model and messages. A
+    // request the cassette doesn't cover is an error, since replaying some
+    // other reply would hide that the app now sends something different.
+    fn next_interaction(&self, request: &ChatRequest) -> Result<Interaction> {
+        let mut played = self.played.lock().unwrap();
+        let interactions = &self.cassette.interactions;
+        let mut unplayed = (0..interactions.len()).filter(|&ix| !played[ix]).peekable();
+        let Some(&next) = unplayed.peek() else {
+            return Err(anyhow!("the cassette has no more recorded replies"));
+        };
+        let matching = unplayed.find(|&ix| {
+            let recorded = &interactions[ix].request;
+            recorded.model == request.model && recorded.messages == request.messages
+        });
+        let ix = match matching {
+            Some(ix) => ix,
+            None if self.lenient => next,
+            None => {
+                let last_message = request
+                    .messages
+                    .last()
+                    .map(|message| format!("{:?}", message.content))
+                    .unwrap_or_default();
+                return Err(anyhow!(
+                    "no recorded request matches {} with {} messages, the last being {}; \
+                     set {REPLAY_LENIENT_ENV_VAR}=1 to replay recordings in order",
+                    request.model,
+                    request.messages.len(),
+                    last_message
+                ));
+            }
+        };
+        played[ix] = true;
+        Ok(interactions[ix].clone())
+    }
+}
+
+impl AgentBackend for ReplayBackend {
+    fn name(&self) -> SharedString {
+        "replay".into()
+    }
+
+    fn stream_completion(
+        &self,
+        request: ChatRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let interaction = match self.next_interaction(&request) {
+            Ok(interaction) => interaction,
+            Err(error) => return futures::future::ready(Err(error)).boxed(),
+        };
+        let executor = cx.background_executor().clone();
+        let speed = self.speed;
+        let delay = move |from_ms: u64, to_ms: u64| {
+            let delay = to_ms.saturating_sub(from_ms) as f32;
+            if speed == 0.0 {
+                Duration::ZERO
+            } else {
+                Duration::from_millis((delay / speed) as u64)
+            }
+        };
+
+        async move {
+            let mut events = interaction.events.into_iter().peekable();
+            // A request that failed outright fails again the same way
+            if let Some(RecordedEvent {
+                at_ms,
+                outcome: RecordedOutcome::Error(error),
+            }) = events.peek().cloned()
+            {
+                executor.timer(delay(0, at_ms)).await;
+                return Err(anyhow!(error));
+            }
+
+            let state = (events, 0, executor);
+            let events = futures::stream::unfold(state, move |state| async move {
+                let (mut events, last_ms, executor) = state;
+                let event = events.next()?;
+                let wait = delay(last_ms, event.at_ms);
+                if !wait.is_zero() {
+                    executor.timer(wait).await;
+                }
+                let outcome = match event.outcome {
+                    RecordedOutcome::Event(event) => Ok(event),
+                    RecordedOutcome::Error(error) => Err(anyhow!(error)),
+                };
+                Some((outcome, (events, event.at_ms, executor)))
+            });
+            Ok(events.boxed())
+        }
+        .boxed()
+    }
+}
+
+/// Wraps `backend` for recording, or replaces it with a replay, when
+/// `CODE_AGENT_CASSETTE` names a cassette.
+pub fn backend_from_env(backend: Arc<dyn AgentBackend>) -> Result<Arc<dyn AgentBackend>> {
+    let Some(path) = std::env::var_os(CASSETTE_ENV_VAR).map(PathBuf::from) else {
+        return Ok(backend);
+    };
+    match std::env::var(CASSETTE_MODE_ENV_VAR).as_deref() {
+        Ok("record") => Ok(Arc::new(RecordingBackend::new(backend, path)?)),
+        Ok("replay") | Err(_) => {
+            let speed = std::env::var(REPLAY_SPEED_ENV_VAR)
+                .ok()
+                .and_then(|speed| speed.parse().ok())
+                .unwrap_or(1.0);
+            let lenient = std::env::var(REPLAY_LENIENT_ENV_VAR).is_ok_and(|value| value == "1");
+            Ok(Arc::new(ReplayBackend::load(&path, speed)?.lenient(lenient)))
+        }
+        Ok(mode) => Err(anyhow!("unknown {CASSETTE_MODE_ENV_VAR} {mode:?}, expected record or replay")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::TokenUsage;
+    use crate::conversation::{RequestMessage, Role};
+
+    fn fixture() -> Cassette {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay.json");
+        Cassette::load(&path).unwrap()
+    }
+
+    fn request(model: &str, messages: &[(Role, &str)]) -> ChatRequest {
+        ChatRequest {
+            model: model.to_string(),
+            messages: messages
+                .iter()
+                .map(|(role, content)| RequestMessage::new(*role, *content))
+                .collect(),
+            temperature: None,
+            max_tokens: None,
+            stop: Vec::new(),
+            tools: Vec::new(),
+        }
+    }
+
+    fn hello() -> ChatRequest {
+        request("openai/gpt-4o", &[(Role::User, "Hello")])
+    }
+
+    fn follow_up() -> ChatRequest {
+        request(
+            "openai/gpt-4o",
+            &[
+                (Role::User, "Hello"),
+                (Role::Assistant, "Hi there!"),
+                (Role::User, "What's 2 + 2?"),
+            ],
+        )
+    }
+
+    fn first_text(interaction: &Interaction) -> Option<&str> {
+        interaction.events.iter().find_map(|event| match &event.outcome {
+            RecordedOutcome::Event(CompletionEvent::Text(text)) => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn loads_the_fixture() {
+        let cassette = fixture();
+        assert_eq!(cassette.interactions.len(), 3);
+        assert_eq!(
+            cassette.interactions[1].events[1].outcome,
+            RecordedOutcome::Event(CompletionEvent::Usage(TokenUsage {
+                input_tokens: 21,
+                output_tokens: 1,
+            }))
+        );
+        assert_eq!(
+            cassette.interactions[2].events[0].outcome,
+            RecordedOutcome::Error("rate limited".to_string())
+        );
+    }
+
+    #[test]
+    fn replays_the_recording_that_matches() {
+        let replay = ReplayBackend::new(fixture(), 0.0);
+        let interaction = replay.next_interaction(&follow_up()).unwrap();
+        assert_eq!(first_text(&interaction), Some("4"));
+        let interaction = replay.next_interaction(&hello()).unwrap();
+        assert_eq!(first_text(&interaction), Some("Hi there!"));
+        let claude = request("anthropic/claude-sonnet-4", &[(Role::User, "Hello")]);
+        let interaction = replay.next_interaction(&claude).unwrap();
+        assert_eq!(interaction.request.model, "anthropic/claude-sonnet-4");
+    }
+
+    #[test]
+    fn a_request_without_a_recording_is_an_error() {
+        let replay = ReplayBackend::new(fixture(), 0.0);
+        let goodbye = request("openai/gpt-4o", &[(Role::User, "Goodbye")]);
+        let error = replay.next_interaction(&goodbye).unwrap_err().to_string();
+        assert!(error.contains("no recorded request matches openai/gpt-4o"), "{error}");
+        assert!(error.contains(REPLAY_LENIENT_ENV_VAR), "{error}");
+
+        // The failed lookup plays nothing
+        let interaction = replay.next_interaction(&hello()).unwrap();
+        assert_eq!(first_text(&interaction), Some("Hi there!"));
+        assert!(replay.next_interaction(&hello()).is_err());
+    }
+
+    #[test]
+    fn lenient_replay_falls_back_to_the_next_recording() {
+        let replay = ReplayBackend::new(fixture(), 0.0).lenient(true);
+        let goodbye = request("openai/gpt-4o", &[(Role::User, "Goodbye")]);
+        let interaction = replay.next_interaction(&goodbye).unwrap();
+        assert_eq!(first_text(&interaction), Some("Hi there!"));
+        let interaction = replay.next_interaction(&goodbye).unwrap();
+        assert_eq!(first_text(&interaction), Some("4"));
+        replay.next_interaction(&goodbye).unwrap();
+
+        let error = replay.next_interaction(&goodbye).unwrap_err().to_string();
+        assert_eq!(error, "the cassette has no more recorded replies");
+    }
+}
@@ -0,0 +1,457 @@
+// Shell command tool
+// Runs a command through the platform shell inside the workspace, streaming
+// its output to the tool card. The environment is cleared down to a small
+// allowlist plus the variables the model asks for, and the output returned to
+// the model is capped.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context as _, Result};
+use futures::stream::{self, BoxStream};
+use futures::{future::BoxFuture, AsyncRead, AsyncReadExt, FutureExt, StreamExt};
+use gpui::{AsyncApp, SharedString};
+use serde::Deserialize;
+use serde_json::json;
+use smol::process::Command;
+
+use crate::ansi::strip_ansi;
+use crate::tools::{Tool, ToolActivity};
+use crate::workspace_tools::{parse_arguments, resolve_in_workspace};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 120;
+const MAX_TIMEOUT_SECS: u64 = 600;
+// Output sent back to the model keeps its start and, mostly, its end
+const MAX_MODEL_OUTPUT_HEAD_BYTES: usize = 4 * 1024;
+const MAX_MODEL_OUTPUT_TAIL_BYTES: usize = 12 * 1024;
+// Variables passed through from our own environment
+const INHERITED_ENV_VARS: &[&str] = &[
+    "PATH", "HOME", "USER", "LOGNAME", "LANG", "LC_ALL", "TMPDIR", "TEMP", "TMP", "SHELL",
+    "SYSTEMROOT", "COMSPEC", "PATHEXT", "USERPROFILE",
+];
+
+pub struct RunCommandTool {
+    root: Arc<PathBuf>,
+}
+
+impl RunCommandTool {
+    pub fn new(workspace_root: PathBuf) -> Self {
+        Self {
+            root: Arc::new(workspace_root),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RunCommandArguments {
+    command: String,
+    #[serde(default)]
+    cwd: Option<String>,
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+enum Outcome {
+    Exited,
+    TimedOut,
+    Killed,
+}
+
+// The shell runs in a process group of its own so that stopping it also
+// stops whatever it started
+fn shell_command(command: &str) -> Command {
+    #[cfg(windows)]
+    let shell = {
+        use std::os::windows::process::CommandExt as _;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        let mut shell = std::process::Command::new("cmd");
+        shell.arg("/C").arg(command).creation_flags(CREATE_NEW_PROCESS_GROUP);
+        shell
+    };
+    #[cfg(unix)]
+    let shell = {
+        use std::os::unix::process::CommandExt as _;
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c").arg(command).process_group(0);
+        shell
+    };
+    Command::from(shell)
+}
+
+// Kills a command's process group when dropped, unless it exited on its own.
+// `kill_on_drop` alone would only reach the shell.
+struct ProcessGroup {
+    pid: Option<u32>,
+}
+
+impl ProcessGroup {
+    fn kill(&mut self) {
+        let Some(pid) = self.pid.take() else {
+            return;
+        };
+        #[cfg(unix)]
+        // SAFETY: `kill` has no memory safety requirements. The group was
+        // created for this command, so a negative pid only reaches it.
+        unsafe {
+            libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+        }
+        #[cfg(windows)]
+        std::process::Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &pid.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .ok();
+    }
+
+    fn disarm(&mut self) {
+        self.pid = None;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+// Yields decoded text as it arrives, holding back UTF-8 sequences that are
+// split across reads
+fn read_chunks(reader: impl AsyncRead + Unpin + Send + 'static) -> BoxStream<'static, String> {
+    stream::unfold(
+        (reader, Vec::new(), false),
+        |(mut reader, mut pending, done)| async move {
+            if done {
+                return None;
+            }
+            let mut buffer = [0; 4096];
+            loop {
+                let read = reader.read(&mut buffer).await.unwrap_or(0);
+                if read == 0 {
+                    let rest = String::from_utf8_lossy(&pending).into_owned();
+                    return Some((rest, (reader, Vec::new(), true)));
+                }
+                pending.extend_from_slice(&buffer[..read]);
+                let valid = match std::str::from_utf8(&pending) {
+                    Ok(_) => pending.len(),
+                    Err(error) if error.error_len().is_none() => error.valid_up_to(),
+                    // Invalid bytes rather than a split sequence
+                    Err(_) => pending.len(),
+                };
+                if valid > 0 {
+                    let rest = pending.split_off(valid);
+                    let text = String::from_utf8_lossy(&pending).into_owned();
+                    return Some((text, (reader, rest, false)));
+                }
+            }
+        },
+    )
+    .filter(|text| futures::future::ready(!text.is_empty()))
+    .boxed()
+}
+
+fn floor_char_boundary(text: &str, mut ix: usize) -> usize {
+    while !text.is_char_boundary(ix) {
+        ix -= 1;
+    }
+    ix
+}
+
+fn ceil_char_boundary(text: &str, mut ix: usize) -> usize {
+    while !text.is_char_boundary(ix) {
+        ix += 1;
+    }
+    ix
+}
+
+// Raw output kept while a command runs, bounded however much it prints:
+// the head, then only the latest output. Cuts fall on line starts where
+// possible so no escape sequence is split before stripping.
+#[derive(Default)]
+struct RunOutput {
+    head: String,
+    tail: String,
+    // Bytes dropped between `head` and `tail`
+    omitted: usize,
+}
+
+impl RunOutput {
+    fn push(&mut self, mut text: &str) {
+        if self.tail.is_empty() && self.head.len() < MAX_MODEL_OUTPUT_HEAD_BYTES {
+            let room = MAX_MODEL_OUTPUT_HEAD_BYTES - self.head.len();
+            if text.len() <= room {
+                self.head.push_str(text);
+                return;
+            }
+            let end = match text[..floor_char_boundary(text, room)].rfind('\n') {
+                Some(ix) => ix + 1,
+                None if self.head.is_empty() => floor_char_boundary(text, room),
+                None => 0,
+            };
+            self.head.push_str(&text[..end]);
+            text = &text[end..];
+        }
+        self.tail.push_str(text);
+        // Trimmed in batches rather than on every chunk
+        if self.tail.len() > 2 * MAX_MODEL_OUTPUT_TAIL_BYTES {
+            let cut = ceil_char_boundary(&self.tail, self.tail.len() - MAX_MODEL_OUTPUT_TAIL_BYTES);
+            // Keeps a little more rather than start mid-line
+            let start = self.tail[..cut].rfind('\n').map_or(cut, |ix| ix + 1);
+            self.omitted += start;
+            self.tail.drain(..start);
+        }
+    }
+
+    // The output for the model, without escapes and capped
+    fn finish(self) -> String {
+        if self.omitted == 0 {
+            return cap_output(&strip_ansi(&(self.head + &self.tail)));
+        }
+        let head = strip_ansi(&self.head);
+        let tail = strip_ansi(&self.tail);
+        let start =
+            ceil_char_boundary(&tail, tail.len().saturating_sub(MAX_MODEL_OUTPUT_TAIL_BYTES));
+        format!(
+            "{}\n[… {} bytes of output omitted …]\n{}",
+            head.trim_end_matches('\n'),
+            self.omitted + start,
+            &tail[start..]
+        )
+    }
+}
+
+/// Keeps the head and tail of long output, noting how much was dropped.
+pub fn cap_output(output: &str) -> String {
+    if output.len() <= MAX_MODEL_OUTPUT_HEAD_BYTES + MAX_MODEL_OUTPUT_TAIL_BYTES {
+        return output.to_string();
+    }
+    let head_end = floor_char_boundary(output, MAX_MODEL_OUTPUT_HEAD_BYTES);
+    let tail_start = ceil_char_boundary(output, output.len() - MAX_MODEL_OUTPUT_TAIL_BYTES);
+    format!(
+        "{}\n[… {} bytes of output omitted …]\n{}",
+        &output[..head_end],
+        tail_start - head_end,
+        &output[tail_start..]
+    )
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs < 60.0 {
+        format!("{secs:.2}s")
+    } else {
+        format!("{}m {:02}s", duration.as_secs() / 60, duration.as_secs() % 60)
+    }
+}
+
+impl Tool for RunCommandTool {
+    fn name(&self) -> SharedString {
+        "run_command".into()
+    }
+
+    fn description(&self) -> SharedString {
+        "Runs a shell command in the workspace and returns its combined stdout and stderr \
+         with the exit code. Long output is truncated in the middle."
+            .into()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "command": { "type": "string", "description": "Command line run through the shell" },
+                "cwd": { "type": "string", "description": "Working directory relative to the workspace root" },
+                "timeout_secs": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_TIMEOUT_SECS,
+                    "description": format!("Defaults to {DEFAULT_TIMEOUT_SECS}")
+                },
+                "env": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "Extra environment variables"
+                }
+            },
+            "required": ["command"]
+        })
+    }
+
+    fn needs_approval(&self) -> bool {
+        true
+    }
+
+    fn run(
+        &self,
+        arguments: serde_json::Value,
+        mut activity: ToolActivity,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>> {
+        let root = self.root.clone();
+        let executor = cx.background_executor().clone();
+        async move {
+            let arguments: RunCommandArguments = parse_arguments(arguments)?;
+            let cwd = resolve_in_workspace(&root, arguments.cwd.as_deref().unwrap_or("."))?;
+            let timeout = Duration::from_secs(
+                arguments
+                    .timeout_secs
+                    .unwrap_or(DEFAULT_TIMEOUT_SECS)
+                    .clamp(1, MAX_TIMEOUT_SECS),
+            );
+
+            let mut command = shell_command(&arguments.command);
+            command
+                .current_dir(&cwd)
+                .env_clear()
+                .envs(INHERITED_ENV_VARS.iter().filter_map(|name| {
+                    std::env::var_os(name).map(|value| (*name, value))
+                }))
+                // Ask tools for color since the card renders it
+                .env("TERM", "xterm-256color")
+                .env("CLICOLOR_FORCE", "1")
+                .env("FORCE_COLOR", "1")
+                .envs(&arguments.env)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true);
+
+            let started_at = Instant::now();
+            let mut child = command
+                .spawn()
+                .with_context(|| format!("failed to run `{}`", arguments.command))?;
+            let mut process_group = ProcessGroup {
+                pid: Some(child.id()),
+            };
+            let stdout = read_chunks(child.stdout.take().context("no stdout")?);
+            let stderr = read_chunks(child.stderr.take().context("no stderr")?);
+            let mut chunks = stream::select(stdout, stderr);
+            let mut timer = executor.timer(timeout).fuse();
+            let mut killed = activity.take_kill_signal().fuse();
+
+            let mut output = RunOutput::default();
+            let outcome = loop {
+                futures::select! {
+                    chunk = chunks.next() => match chunk {
+                        Some(text) => {
+                            output.push(&text);
+                            activity.output(text);
+                        }
+                        None => break Outcome::Exited,
+                    },
+                    _ = timer => break Outcome::TimedOut,
+                    _ = killed => break Outcome::Killed,
+                }
+            };
+            // Output can close before the command ends (`exec >/dev/null`), so
+            // waiting for its status still honors the timeout and kill button
+            let exited = match outcome {
+                Outcome::Exited => futures::select! {
+                    status = child.status().fuse() => Ok(status?),
+                    _ = timer => Err(Outcome::TimedOut),
+                    _ = killed => Err(Outcome::Killed),
+                },
+                outcome => Err(outcome),
+            };
+            let (outcome, status) = match exited {
+                Ok(status) => {
+                    process_group.disarm();
+                    (Outcome::Exited, status)
+                }
+                Err(outcome) => {
+                    process_group.kill();
+                    (outcome, child.status().await?)
+                }
+            };
+            let elapsed = format_duration(started_at.elapsed());
+
+            let summary = match outcome {
+                Outcome::Exited => match status.code() {
+                    Some(code) => format!("exit code {code} · {elapsed}"),
+                    None => format!("terminated by signal · {elapsed}"),
+                },
+                Outcome::TimedOut => format!("timed out after {elapsed}"),
+                Outcome::Killed => format!("killed after {elapsed}"),
+            };
+            activity.summary(summary.clone());
+
+            let output = output.finish();
+            Ok(format!("$ {}\n{}\n[{summary}]", arguments.command, output.trim_end()))
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: usize = MAX_MODEL_OUTPUT_HEAD_BYTES + MAX_MODEL_OUTPUT_TAIL_BYTES;
+
+    #[test]
+    fn cap_output_keeps_short_output() {
+        let output = "line\n".repeat(LIMIT / 5);
+        assert_eq!(cap_output(&output), output);
+    }
+
+    #[test]
+    fn cap_output_keeps_head_and_tail() {
+        let output = format!("{}{}{}", "h".repeat(LIMIT), "m".repeat(100), "t".repeat(LIMIT));
+        let capped = cap_output(&output);
+        let omitted = output.len() - LIMIT;
+        let marker = format!("\n[… {omitted} bytes of output omitted …]\n");
+        assert_eq!(
+            capped,
+            format!(
+                "{}{marker}{}",
+                "h".repeat(MAX_MODEL_OUTPUT_HEAD_BYTES),
+                "t".repeat(MAX_MODEL_OUTPUT_TAIL_BYTES)
+            )
+        );
+    }
+
+    #[test]
+    fn cap_output_cuts_on_char_boundaries() {
+        let output = "é".repeat(LIMIT);
+        let capped = cap_output(&output);
+        let (head, rest) = capped.split_once('\n').unwrap();
+        let (_, tail) = rest.split_once('\n').unwrap();
+        assert!(head.chars().all(|ch| ch == 'é'));
+        assert!(tail.chars().all(|ch| ch == 'é'));
+        assert_eq!(head.len(), MAX_MODEL_OUTPUT_HEAD_BYTES);
+        assert_eq!(tail.len(), MAX_MODEL_OUTPUT_TAIL_BYTES);
+    }
+
+    #[test]
+    fn run_output_strips_escapes_split_across_chunks() {
+        let mut output = RunOutput::default();
+        for chunk in ["\x1b[3", "1mred\x1b", "[0m\n", "plain\n"] {
+            output.push(chunk);
+        }
+        assert_eq!(output.finish(), "red\nplain\n");
+    }
+
+    #[test]
+    fn run_output_stays_bounded() {
+        let mut output = RunOutput::default();
+        let line = format!("\x1b[32m{}\x1b[0m\n", "x".repeat(70));
+        for _ in 0..10_000 {
+            output.push(&line);
+            assert!(output.head.len() <= MAX_MODEL_OUTPUT_HEAD_BYTES);
+            assert!(output.tail.len() <= 2 * MAX_MODEL_OUTPUT_TAIL_BYTES + line.len());
+        }
+        assert!(output.head.ends_with('\n'));
+        assert!(output.tail.starts_with("\x1b[32m"));
+
+        let finished = output.finish();
+        assert!(finished.len() <= LIMIT + 100);
+        assert!(finished.contains("bytes of output omitted"));
+        assert!(!finished.contains('\x1b'));
+        assert!(finished.ends_with(&format!("{}\n", "x".repeat(70))));
+    }
+}
@@ -0,0 +1,384 @@
+// Context-window budgeting
+// Estimates token usage and fits the conversation history into the model's
+// context window before each request.
+
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::conversation::{ChatMessage, MessageId, RequestMessage, Role};
+
+// Fixed per-message cost for role markers and separators
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+// Upper bound on the number of lines kept in a synthetic summary
+const MAX_SUMMARY_LINES: usize = 20;
+const SUMMARY_LINE_CHARS: usize = 80;
+
+/// Rough token estimate: ~4 ASCII characters per token, one token per
+/// CJK character and half a token per other non-ASCII character.
+pub fn estimate_tokens(text: &str) -> usize {
+    let quarters: usize = text
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii() {
+                1
+            } else if ('\u{2e80}'..='\u{9fff}').contains(&ch)
+                || ('\u{ac00}'..='\u{d7af}').contains(&ch)
+            {
+                4
+            } else {
+                2
+            }
+        })
+        .sum();
+    quarters.div_ceil(4)
+}
+
+pub fn estimate_message_tokens(role: Role, content: &str) -> usize {
+    MESSAGE_OVERHEAD_TOKENS + estimate_tokens(role.as_str()) + estimate_tokens(content)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruncationStrategy {
+    /// Drop the oldest messages until the history fits.
+    #[default]
+    DropOldest,
+    /// Like `DropOldest`, but pinned messages are never dropped.
+    KeepPinned,
+    /// Replace the dropped messages with a synthetic summary message.
+    Summarize,
+}
+
+impl TruncationStrategy {
+    pub const ALL: [TruncationStrategy; 3] = [
+        TruncationStrategy::DropOldest,
+        TruncationStrategy::KeepPinned,
+        TruncationStrategy::Summarize,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TruncationStrategy::DropOldest => "Drop oldest",
+            TruncationStrategy::KeepPinned => "Keep pinned",
+            TruncationStrategy::Summarize => "Summarize",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let ix = Self::ALL.iter().position(|s| s == self).unwrap_or(0);
+        Self::ALL[(ix + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextBudget {
+    pub max_tokens: usize,
+    /// Tokens kept free for the model's reply.
+    pub reserved_output_tokens: usize,
+    pub strategy: TruncationStrategy,
+}
+
+impl Default for ContextBudget {
+    fn default() -> Self {
+        Self {
+            max_tokens: 8_192,
+            reserved_output_tokens: 1_024,
+            strategy: TruncationStrategy::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FittedHistory {
+    pub messages: Vec<RequestMessage>,
+    pub excluded: BTreeSet<MessageId>,
+    pub token_count: usize,
+}
+
+impl FittedHistory {
+    pub fn is_excluded(&self, id: MessageId) -> bool {
+        self.excluded.contains(&id)
+    }
+}
+
+impl ContextBudget {
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens,
+            ..Default::default()
+        }
+    }
+
+    pub fn input_limit(&self) -> usize {
+        self.max_tokens.saturating_sub(self.reserved_output_tokens)
+    }
+
+    /// Selects the messages to send. The system prompt, system messages and
+    /// the most recent message are always kept, even if they alone exceed
+    /// the budget. An assistant message and the tool results that follow it
+    /// are kept or dropped together.
+    pub fn fit(&self, system_prompt: Option<&str>, messages: &[ChatMessage]) -> FittedHistory {
+        let system_prompt_cost =
+            system_prompt.map_or(0, |prompt| estimate_message_tokens(Role::System, prompt));
+        let limit = self.input_limit().saturating_sub(system_prompt_cost);
+        let costs: Vec<usize> = messages
+            .iter()
+            .map(|message| estimate_message_tokens(message.role, &message.text()))
+            .collect();
+
+        let last_ix = messages.len().checked_sub(1);
+        let is_protected = |ix: usize| {
+            let message = &messages[ix];
+            message.role == Role::System
+                || Some(ix) == last_ix
+                || (self.strategy == TruncationStrategy::KeepPinned && message.pinned)
+        };
+
+        // Leave room for at least the summary header when summarizing
+        let summary_reserve = if self.strategy == TruncationStrategy::Summarize {
+            estimate_message_tokens(Role::System, &summary_header(messages.len()))
+        } else {
+            0
+        };
+
+        // Providers reject a tool result without the call it answers and a
+        // call without its results, so tool results stay with the message
+        // before them
+        let mut units: Vec<Range<usize>> = Vec::new();
+        for (ix, message) in messages.iter().enumerate() {
+            match units.last_mut() {
+                Some(unit) if message.role == Role::Tool => unit.end = ix + 1,
+                _ => units.push(ix..ix + 1),
+            }
+        }
+
+        let mut included = vec![true; messages.len()];
+        let mut total: usize = costs.iter().sum();
+        let mut dropped_any = false;
+        for unit in units {
+            let reserve = if dropped_any { summary_reserve } else { 0 };
+            if total + reserve <= limit {
+                break;
+            }
+            if unit.clone().any(&is_protected) {
+                continue;
+            }
+            for ix in unit {
+                included[ix] = false;
+                total -= costs[ix];
+            }
+            dropped_any = true;
+        }
+
+        let mut summary = None;
+        if self.strategy == TruncationStrategy::Summarize && dropped_any {
+            summary = Some(summarize(messages, &included, limit.saturating_sub(total)));
+        }
+
+        let mut fitted = FittedHistory::default();
+        if let Some(prompt) = system_prompt {
+            fitted.token_count += system_prompt_cost;
+            fitted.messages.push(RequestMessage::new(Role::System, prompt));
+        }
+        let mut summary_inserted = false;
+        for (ix, message) in messages.iter().enumerate() {
+            if !included[ix] {
+                fitted.excluded.insert(message.id);
+                continue;
+            }
+            if !summary_inserted && message.role != Role::System {
+                if let Some(summary) = summary.take() {
+                    fitted.token_count += estimate_message_tokens(Role::System, &summary);
+                    fitted.messages.push(RequestMessage::new(Role::System, summary));
+                }
+                summary_inserted = true;
+            }
+            fitted.token_count += costs[ix];
+            fitted.messages.push(RequestMessage::from(message));
+        }
+        fitted
+    }
+}
+
+fn summary_header(dropped: usize) -> String {
+    format!(
+        "Summary of {} earlier message{} omitted to fit the context window:",
+        dropped,
+        if dropped == 1 { "" } else { "s" }
+    )
+}
+
+// Builds an extractive summary of the excluded messages within `budget`
+// tokens, preferring the most recent ones when not all of them fit.
+fn summarize(messages: &[ChatMessage], included: &[bool], budget: usize) -> String {
+    let dropped: Vec<&ChatMessage> = messages
+        .iter()
+        .zip(included)
+        .filter(|(_, included)| !**included)
+        .map(|(message, _)| message)
+        .collect();
+
+    let header = summary_header(dropped.len());
+    let mut used = estimate_message_tokens(Role::System, &header);
+    let mut lines = Vec::new();
+    for message in dropped.iter().rev().take(MAX_SUMMARY_LINES) {
+        let first_line = message.content.lines().next().unwrap_or_default().trim();
+        let mut line: String = first_line.chars().take(SUMMARY_LINE_CHARS).collect();
+        if line.len() < first_line.len() {
+            line.push('…');
+        }
+        let line = format!("\n- {}: {}", message.role.as_str(), line);
+        let cost = estimate_tokens(&line);
+        if used + cost > budget {
+            break;
+        }
+        used += cost;
+        lines.push(line);
+    }
+
+    let mut summary = header;
+    for line in lines.into_iter().rev() {
+        summary.push_str(&line);
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::Conversation;
+
+    // Each filler message costs about `tokens` tokens of content
+    fn filler(name: &str, tokens: usize) -> String {
+        format!("{name}\n{}", "x".repeat(tokens * 4 - name.len() - 1))
+    }
+
+    fn conversation(messages: &[(Role, String)]) -> Conversation {
+        let mut conversation = Conversation::new();
+        for (role, content) in messages {
+            conversation.push(*role, content.clone());
+        }
+        conversation
+    }
+
+    fn budget(strategy: TruncationStrategy, max_tokens: usize) -> ContextBudget {
+        ContextBudget {
+            max_tokens,
+            reserved_output_tokens: 0,
+            strategy,
+        }
+    }
+
+    // The first line of each message sent
+    fn sent(fitted: &FittedHistory) -> Vec<&str> {
+        fitted
+            .messages
+            .iter()
+            .map(|message| message.content.lines().next().unwrap_or_default())
+            .collect()
+    }
+
+    fn chat() -> Conversation {
+        conversation(&[
+            (Role::System, "Be brief".to_string()),
+            (Role::User, filler("u1", 100)),
+            (Role::Assistant, filler("a1", 100)),
+            (Role::User, filler("u2", 100)),
+            (Role::Assistant, filler("a2", 100)),
+            (Role::User, filler("u3", 100)),
+        ])
+    }
+
+    #[test]
+    fn drop_oldest_keeps_system_messages_and_the_latest_one() {
+        let chat = chat();
+        let fitted = budget(TruncationStrategy::DropOldest, 250).fit(None, chat.messages());
+        assert_eq!(sent(&fitted), ["Be brief", "a2", "u3"]);
+        assert!(fitted.token_count <= 250);
+        assert_eq!(fitted.excluded.len(), 3);
+
+        // Even when they alone are over budget
+        let fitted =
+            budget(TruncationStrategy::DropOldest, 10).fit(Some("Prompt"), chat.messages());
+        assert_eq!(sent(&fitted), ["Prompt", "Be brief", "u3"]);
+    }
+
+    #[test]
+    fn keep_pinned_never_drops_pinned_messages() {
+        let mut chat = chat();
+        let u1 = chat.messages()[1].id;
+        chat.set_pinned(u1, true);
+
+        let fitted = budget(TruncationStrategy::KeepPinned, 250).fit(None, chat.messages());
+        assert_eq!(sent(&fitted), ["Be brief", "u1", "u3"]);
+        assert!(!fitted.is_excluded(u1));
+
+        let fitted = budget(TruncationStrategy::DropOldest, 250).fit(None, chat.messages());
+        assert_eq!(sent(&fitted), ["Be brief", "a2", "u3"]);
+        assert!(fitted.is_excluded(u1));
+    }
+
+    #[test]
+    fn tool_calls_and_their_results_stay_together() {
+        let messages = [
+            (Role::User, filler("u1", 100)),
+            (Role::Assistant, "calling".to_string()),
+            (Role::Tool, filler("t1", 100)),
+            (Role::Tool, filler("t2", 100)),
+            (Role::Assistant, filler("a2", 100)),
+            (Role::User, filler("u2", 100)),
+        ];
+        // Dropping the call alone would fit, but its results must go too
+        let chat = conversation(&messages);
+        for strategy in TruncationStrategy::ALL {
+            let fitted = budget(strategy, 425).fit(None, chat.messages());
+            let sent = sent(&fitted);
+            assert!(
+                !sent.contains(&"t1") && !sent.contains(&"t2"),
+                "{strategy:?}: {sent:?}"
+            );
+            assert!(sent.ends_with(&["a2", "u2"]), "{strategy:?}: {sent:?}");
+        }
+
+        // A call whose result is the latest message is kept with it
+        let chat = conversation(&messages[..3]);
+        let fitted = budget(TruncationStrategy::DropOldest, 10).fit(None, chat.messages());
+        assert_eq!(sent(&fitted), ["calling", "t1"]);
+    }
+
+    #[test]
+    fn summary_replaces_dropped_messages_within_budget() {
+        let mut messages = vec![(Role::System, "Be brief".to_string())];
+        for turn in 0..30 {
+            messages.push((Role::User, filler(&format!("question {turn}"), 50)));
+            messages.push((Role::Assistant, filler(&format!("answer {turn}"), 50)));
+        }
+        let chat = conversation(&messages);
+
+        for limit in [200, 400, 1_000] {
+            let fitted = budget(TruncationStrategy::Summarize, limit).fit(None, chat.messages());
+            assert!(
+                fitted.token_count <= limit,
+                "{} > {limit}",
+                fitted.token_count
+            );
+            // The summary follows the system messages and stands in for the
+            // dropped ones
+            assert_eq!(fitted.messages[0].content, "Be brief");
+            let summary = &fitted.messages[1];
+            assert_eq!(summary.role, Role::System);
+            let header = summary_header(fitted.excluded.len());
+            assert!(summary.content.starts_with(&header), "{}", summary.content);
+            assert_eq!(fitted.messages.last().unwrap().content, messages[60].1);
+        }
+
+        // The most recent dropped messages are the ones summarized
+        let fitted = budget(TruncationStrategy::Summarize, 1_000).fit(None, chat.messages());
+        let summary = &fitted.messages[1].content;
+        let dropped = fitted.excluded.len();
+        let latest_dropped = messages[dropped].1.lines().next().unwrap();
+        assert!(summary.ends_with(latest_dropped), "{summary}");
+    }
+}
@@ -0,0 +1,876 @@
+// Conversation model shared by the chat views
+// Messages carry a stable id so views can refer to them across re-renders.
+// Conversations are trees: editing or regenerating starts a sibling branch
+// instead of discarding the old turns. The active path is kept as a flat
+// list, and messages on other branches are stored alongside it.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read as _;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ConversationId(pub String);
+
+impl ConversationId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl Default for ConversationId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for ConversationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct MessageId(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolStatus {
+    #[default]
+    Pending,
+    AwaitingApproval,
+    Running,
+    Completed,
+    Failed,
+    Denied,
+}
+
+impl ToolStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ToolStatus::Pending => "Pending",
+            ToolStatus::AwaitingApproval => "Awaiting approval",
+            ToolStatus::Running => "Running",
+            ToolStatus::Completed => "Completed",
+            ToolStatus::Failed => "Failed",
+            ToolStatus::Denied => "Denied",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, ToolStatus::Completed | ToolStatus::Failed | ToolStatus::Denied)
+    }
+
+    /// Whether the call ended without a usable result.
+    pub fn is_error(&self) -> bool {
+        matches!(self, ToolStatus::Failed | ToolStatus::Denied)
+    }
+}
+
+// A tool invocation requested by the model
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+    #[serde(default)]
+    pub status: ToolStatus,
+}
+
+/// What an inline chip in a user message stands for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChipPayload {
+    /// A file attached to the message.
+    Attachment { path: PathBuf },
+}
+
+impl ChipPayload {
+    /// The chip's text form, which is what copying it or sending it as
+    /// plain text produces.
+    pub fn text(&self) -> String {
+        match self {
+            ChipPayload::Attachment { path } => {
+                let name = path.file_name().unwrap_or(path.as_os_str());
+                format!("[{}]", name.to_string_lossy())
+            }
+        }
+    }
+
+    /// The file the chip refers to.
+    pub fn path(&self) -> &Path {
+        match self {
+            ChipPayload::Attachment { path } => path,
+        }
+    }
+}
+
+/// Attached files are cut off after this many bytes.
+pub const MAX_ATTACHMENT_BYTES: usize = 100 * 1024;
+
+/// A file attached to a user message, read when the message was sent so
+/// later turns send the same text.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+impl Attachment {
+    /// Reads `path` as text. Unreadable and binary files become a short note,
+    /// so the request still says what was attached.
+    pub fn read(path: &Path) -> Self {
+        let mut bytes = Vec::new();
+        let read = File::open(path).and_then(|file| {
+            file.take(MAX_ATTACHMENT_BYTES as u64 + 1)
+                .read_to_end(&mut bytes)
+        });
+        let content = match read {
+            Ok(_) => {
+                let truncated = bytes.len() > MAX_ATTACHMENT_BYTES;
+                bytes.truncate(MAX_ATTACHMENT_BYTES);
+                match String::from_utf8(bytes) {
+                    Ok(text) if truncated => format!("{text}\n[truncated]"),
+                    Ok(text) => text,
+                    // The cut split the last character
+                    Err(error) if truncated && error.utf8_error().error_len().is_none() => {
+                        let valid = error.utf8_error().valid_up_to();
+                        let mut bytes = error.into_bytes();
+                        bytes.truncate(valid);
+                        let text = String::from_utf8(bytes).unwrap_or_default();
+                        format!("{text}\n[truncated]")
+                    }
+                    Err(_) => "[binary file not included]".to_string(),
+                }
+            }
+            Err(error) => format!("[could not read file: {error}]"),
+        };
+        Self {
+            path: path.to_path_buf(),
+            content,
+        }
+    }
+}
+
+// The content followed by each attachment in a fenced block, which is how
+// text-only APIs receive attachments. The fence is longer than any run of
+// backticks in the file.
+fn text_with_attachments<'a>(content: &'a str, attachments: &[Attachment]) -> Cow<'a, str> {
+    if attachments.is_empty() {
+        return Cow::Borrowed(content);
+    }
+    let mut text = content.to_string();
+    for attachment in attachments {
+        let longest_run = attachment
+            .content
+            .split(|ch| ch != '`')
+            .map(str::len)
+            .max()
+            .unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+        text.push_str(&format!(
+            "\n\n{fence}{}\n{}\n{fence}",
+            attachment.path.display(),
+            attachment.content.trim_end_matches('\n'),
+        ));
+    }
+    Cow::Owned(text)
+}
+
+/// A chip and the byte range its text form covers in the message content.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageChip {
+    pub range: Range<usize>,
+    pub payload: ChipPayload,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub id: MessageId,
+    /// The message this one replies to; `None` for the first message.
+    #[serde(default)]
+    pub parent: Option<MessageId>,
+    pub role: Role,
+    pub content: String,
+    #[serde(default)]
+    pub pinned: bool,
+    /// Tools the assistant asked to run in this turn.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// For `Role::Tool` messages, the call this is the result of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// For `Role::Tool` messages, how the call ended.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_status: Option<ToolStatus>,
+    /// Attachments inserted into the message as chips.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chips: Vec<MessageChip>,
+    /// The files behind `chips`, as sent.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+}
+
+impl ChatMessage {
+    /// The content with its attachments, as a text-only request sends it.
+    pub fn text(&self) -> Cow<'_, str> {
+        text_with_attachments(&self.content, &self.attachments)
+    }
+}
+
+// The message fields that are actually sent to a backend
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RequestMessage {
+    pub role: Role,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_status: Option<ToolStatus>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+}
+
+impl RequestMessage {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            tool_status: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    /// The content with its attachments, for backends that only take text.
+    pub fn text(&self) -> Cow<'_, str> {
+        text_with_attachments(&self.content, &self.attachments)
+    }
+}
+
+impl From<&ChatMessage> for RequestMessage {
+    fn from(message: &ChatMessage) -> Self {
+        Self {
+            role: message.role,
+            content: message.content.clone(),
+            tool_calls: message.tool_calls.clone(),
+            tool_call_id: message.tool_call_id.clone(),
+            tool_status: message.tool_status,
+            attachments: message.attachments.clone(),
+        }
+    }
+}
+
+// Per-conversation system prompt and generation parameters
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConversationSettings {
+    pub system_prompt: String,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub stop_sequences: Vec<String>,
+}
+
+impl ConversationSettings {
+    pub fn system_prompt(&self) -> Option<&str> {
+        let prompt = self.system_prompt.trim();
+        (!prompt.is_empty()).then_some(prompt)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Conversation {
+    id: ConversationId,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    /// The active path, from the first message to the current leaf.
+    #[serde(deserialize_with = "deserialize_path")]
+    messages: Vec<ChatMessage>,
+    /// Messages on branches that are not currently shown.
+    #[serde(default)]
+    inactive: Vec<ChatMessage>,
+    next_message_id: usize,
+    /// `provider/model` id, or `None` to use the catalog default.
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    settings: ConversationSettings,
+}
+
+// Saves from before conversations were trees have no parent links, so the
+// active path is chained in order
+fn deserialize_path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<ChatMessage>, D::Error> {
+    let mut messages = Vec::<ChatMessage>::deserialize(deserializer)?;
+    for ix in 1..messages.len() {
+        if messages[ix].parent.is_none() {
+            messages[ix].parent = Some(messages[ix - 1].id);
+        }
+    }
+    Ok(messages)
+}
+
+/// Lookups across every branch of a conversation, for views that need them
+/// once per message. Build it once per pass with `Conversation::index`.
+pub struct ConversationIndex<'a> {
+    children: HashMap<Option<MessageId>, Vec<MessageId>>,
+    tool_calls: HashMap<&'a str, &'a ToolCall>,
+    tool_results: HashMap<&'a str, &'a ChatMessage>,
+}
+
+impl<'a> ConversationIndex<'a> {
+    /// The ids of the message and its alternatives, oldest first.
+    pub fn siblings(&self, message: &ChatMessage) -> &[MessageId] {
+        self.children
+            .get(&message.parent)
+            .map_or(&[], |children| children.as_slice())
+    }
+
+    pub fn tool_call(&self, call_id: &str) -> Option<&'a ToolCall> {
+        self.tool_calls.get(call_id).copied()
+    }
+
+    /// The `Role::Tool` message holding the result of a call.
+    pub fn tool_result(&self, call_id: &str) -> Option<&'a ChatMessage> {
+        self.tool_results.get(call_id).copied()
+    }
+}
+
+impl Default for Conversation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Conversation {
+    pub fn new() -> Self {
+        let now = Utc::now();
+        Self {
+            id: ConversationId::new(),
+            created_at: now,
+            updated_at: now,
+            messages: Vec::new(),
+            inactive: Vec::new(),
+            next_message_id: 0,
+            model: None,
+            settings: ConversationSettings::default(),
+        }
+    }
+
+    pub fn id(&self) -> &ConversationId {
+        &self.id
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    /// Marks the conversation as modified, e.g. before it is saved.
+    pub fn touch(&mut self) {
+        self.updated_at = Utc::now();
+    }
+
+    /// The first line of the first user message, used as a title.
+    pub fn title(&self) -> String {
+        self.messages
+            .iter()
+            .find(|message| message.role == Role::User)
+            .and_then(|message| message.content.lines().next())
+            .map(|line| line.chars().take(60).collect())
+            .unwrap_or_else(|| "New conversation".to_string())
+    }
+
+    pub fn push(&mut self, role: Role, content: impl Into<String>) -> MessageId {
+        let id = MessageId(self.next_message_id);
+        self.next_message_id += 1;
+        let parent = self.messages.last().map(|message| message.id);
+        self.messages.push(ChatMessage {
+            id,
+            parent,
+            role,
+            content: content.into(),
+            pinned: false,
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            tool_status: None,
+            chips: Vec::new(),
+            attachments: Vec::new(),
+        });
+        id
+    }
+
+    /// Appends a user message together with the chips typed into it.
+    pub fn push_with_chips(
+        &mut self,
+        content: impl Into<String>,
+        chips: Vec<MessageChip>,
+    ) -> MessageId {
+        let id = self.push(Role::User, content);
+        if let Some(message) = self.message_mut(id) {
+            message.chips = chips;
+        }
+        id
+    }
+
+    /// Sets the final status of a call made by `message_id` and records its
+    /// output as a `Role::Tool` message. Results follow the message's earlier
+    /// results on its own branch, even when another branch is active.
+    pub fn finish_tool_call(
+        &mut self,
+        message_id: MessageId,
+        call_id: &str,
+        status: ToolStatus,
+        content: impl Into<String>,
+    ) -> Option<MessageId> {
+        self.tool_call_mut(message_id, call_id)?.status = status;
+
+        let mut parent = message_id;
+        while let Some(result) = self
+            .all_messages()
+            .find(|message| message.parent == Some(parent) && message.role == Role::Tool)
+        {
+            parent = result.id;
+        }
+
+        let id = MessageId(self.next_message_id);
+        self.next_message_id += 1;
+        let message = ChatMessage {
+            id,
+            parent: Some(parent),
+            role: Role::Tool,
+            content: content.into(),
+            pinned: false,
+            tool_calls: Vec::new(),
+            tool_call_id: Some(call_id.to_string()),
+            tool_status: Some(status),
+            chips: Vec::new(),
+            attachments: Vec::new(),
+        };
+        if self.messages.last().map(|message| message.id) == Some(parent) {
+            self.messages.push(message);
+        } else {
+            self.inactive.push(message);
+        }
+        Some(id)
+    }
+
+    /// Whether any tool call in the conversation is still running.
+    pub fn has_running_tools(&self) -> bool {
+        self.all_messages()
+            .flat_map(|message| &message.tool_calls)
+            .any(|call| call.status == ToolStatus::Running)
+    }
+
+    pub fn tool_call_mut(&mut self, message_id: MessageId, call_id: &str) -> Option<&mut ToolCall> {
+        self.message_mut(message_id)?
+            .tool_calls
+            .iter_mut()
+            .find(|call| call.id == call_id)
+    }
+
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    /// Every message in the tree, active path first.
+    pub fn all_messages(&self) -> impl Iterator<Item = &ChatMessage> {
+        self.messages.iter().chain(&self.inactive)
+    }
+
+    pub fn message(&self, id: MessageId) -> Option<&ChatMessage> {
+        self.all_messages().find(|message| message.id == id)
+    }
+
+    pub fn message_mut(&mut self, id: MessageId) -> Option<&mut ChatMessage> {
+        self.messages
+            .iter_mut()
+            .chain(&mut self.inactive)
+            .find(|message| message.id == id)
+    }
+
+    /// Index of the message on the active path.
+    pub fn position(&self, id: MessageId) -> Option<usize> {
+        self.messages.iter().position(|message| message.id == id)
+    }
+
+    /// Moves the message with `id` and everything after it off the active
+    /// path. The next pushed message becomes its sibling, so the old turns
+    /// stay reachable as an alternative branch.
+    pub fn branch_before(&mut self, id: MessageId) {
+        if let Some(ix) = self.position(id) {
+            self.inactive.extend(self.messages.drain(ix..));
+            self.inactive.sort_by_key(|message| message.id);
+        }
+    }
+
+    /// Removes `id` if it ends the active path, such as a reply whose
+    /// request failed before any text arrived. The branch it replaced, if
+    /// any, becomes active again.
+    pub fn remove_last(&mut self, id: MessageId) {
+        if self.messages.last().map(|message| message.id) != Some(id) {
+            return;
+        }
+        let Some(removed) = self.messages.pop() else {
+            return;
+        };
+        let previous = self
+            .inactive
+            .iter()
+            .filter(|message| message.parent == removed.parent)
+            .map(|message| message.id)
+            .max();
+        if let Some(previous) = previous {
+            self.activate(previous);
+        }
+    }
+
+    pub fn index(&self) -> ConversationIndex<'_> {
+        let mut index = ConversationIndex {
+            children: HashMap::new(),
+            tool_calls: HashMap::new(),
+            tool_results: HashMap::new(),
+        };
+        for message in self.all_messages() {
+            index
+                .children
+                .entry(message.parent)
+                .or_default()
+                .push(message.id);
+            for call in &message.tool_calls {
+                index.tool_calls.insert(call.id.as_str(), call);
+            }
+            if let Some(call_id) = message.tool_call_id.as_deref() {
+                index.tool_results.insert(call_id, message);
+            }
+        }
+        for children in index.children.values_mut() {
+            children.sort();
+        }
+        index
+    }
+
+    /// The ids of the message and its alternatives, oldest first.
+    pub fn siblings(&self, id: MessageId) -> Vec<MessageId> {
+        let Some(message) = self.message(id) else {
+            return Vec::new();
+        };
+        let parent = message.parent;
+        let mut siblings: Vec<MessageId> = self
+            .all_messages()
+            .filter(|message| message.parent == parent)
+            .map(|message| message.id)
+            .collect();
+        siblings.sort();
+        siblings
+    }
+
+    /// Makes the branch containing `id` the active path. Below `id`, the
+    /// most recent reply is followed at each step.
+    pub fn activate(&mut self, id: MessageId) {
+        if self.position(id).is_some() || self.message(id).is_none() {
+            return;
+        }
+
+        let mut nodes: HashMap<MessageId, ChatMessage> = self
+            .messages
+            .drain(..)
+            .chain(self.inactive.drain(..))
+            .map(|message| (message.id, message))
+            .collect();
+
+        let mut path = vec![id];
+        while let Some(parent) = path.last().and_then(|id| nodes.get(id)?.parent) {
+            path.push(parent);
+        }
+        path.reverse();
+        loop {
+            let leaf = *path.last().unwrap();
+            let latest_child = nodes
+                .values()
+                .filter(|message| message.parent == Some(leaf))
+                .map(|message| message.id)
+                .max();
+            match latest_child {
+                Some(child) => path.push(child),
+                None => break,
+            }
+        }
+
+        self.messages = path.iter().filter_map(|id| nodes.remove(id)).collect();
+        self.inactive = nodes.into_values().collect();
+        self.inactive.sort_by_key(|message| message.id);
+    }
+
+    pub fn set_pinned(&mut self, id: MessageId, pinned: bool) {
+        if let Some(message) = self.message_mut(id) {
+            message.pinned = pinned;
+        }
+    }
+
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    pub fn set_model(&mut self, model: Option<String>) {
+        self.model = model;
+    }
+
+    pub fn settings(&self) -> &ConversationSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ConversationSettings) {
+        self.settings = settings;
+    }
+
+    /// A copy of this conversation containing only the messages in `range`
+    /// of the active path. Other branches are kept only when the whole path
+    /// is included.
+    pub fn subset(&self, range: std::ops::Range<usize>) -> Conversation {
+        let end = range.end.min(self.messages.len());
+        let start = range.start.min(end);
+        if start == 0 && end == self.messages.len() {
+            return self.clone();
+        }
+        let mut messages = self.messages[start..end].to_vec();
+        if let Some(first) = messages.first_mut() {
+            first.parent = None;
+        }
+        Conversation {
+            messages,
+            inactive: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.inactive.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attachments_follow_the_content_in_fenced_blocks() {
+        let mut message = RequestMessage::new(Role::User, "Explain this");
+        assert_eq!(message.text(), "Explain this");
+
+        message.attachments = vec![
+            Attachment {
+                path: PathBuf::from("src/main.rs"),
+                content: "fn main() {}\n".to_string(),
+            },
+            Attachment {
+                path: PathBuf::from("README.md"),
+                content: "```sh\ncargo run\n```".to_string(),
+            },
+        ];
+        assert_eq!(
+            message.text(),
+            "Explain this\n\n```src/main.rs\nfn main() {}\n```\
+             \n\n````README.md\n```sh\ncargo run\n```\n````"
+        );
+    }
+
+    #[test]
+    fn attachments_are_read_as_bounded_text() {
+        let dir = std::env::temp_dir().join(format!("attachment-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let small = dir.join("small.txt");
+        std::fs::write(&small, "hello").unwrap();
+        assert_eq!(Attachment::read(&small).content, "hello");
+
+        // A multi-byte character straddles the cut
+        let large = dir.join("large.txt");
+        let text = format!("{}é", "a".repeat(MAX_ATTACHMENT_BYTES - 1));
+        std::fs::write(&large, &text).unwrap();
+        let content = Attachment::read(&large).content;
+        assert_eq!(
+            content,
+            format!("{}\n[truncated]", "a".repeat(MAX_ATTACHMENT_BYTES - 1))
+        );
+
+        let binary = dir.join("image.png");
+        std::fs::write(&binary, [0x89, b'P', b'N', b'G', 0xff, 0xfe]).unwrap();
+        assert_eq!(
+            Attachment::read(&binary).content,
+            "[binary file not included]"
+        );
+
+        let missing = Attachment::read(&dir.join("missing.txt")).content;
+        assert!(missing.starts_with("[could not read file: "), "{missing}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn active_ids(conversation: &Conversation) -> Vec<MessageId> {
+        conversation
+            .messages()
+            .iter()
+            .map(|message| message.id)
+            .collect()
+    }
+
+    // q1 → a1 → q2 → a2
+    fn two_turns() -> (Conversation, [MessageId; 4]) {
+        let mut conversation = Conversation::new();
+        let q1 = conversation.push(Role::User, "q1");
+        let a1 = conversation.push(Role::Assistant, "a1");
+        let q2 = conversation.push(Role::User, "q2");
+        let a2 = conversation.push(Role::Assistant, "a2");
+        (conversation, [q1, a1, q2, a2])
+    }
+
+    #[test]
+    fn editing_starts_a_sibling_branch() {
+        let (mut conversation, [q1, a1, q2, a2]) = two_turns();
+        conversation.branch_before(q2);
+        assert_eq!(active_ids(&conversation), [q1, a1]);
+        let edited = conversation.push(Role::User, "q2, edited");
+        let reply = conversation.push(Role::Assistant, "a2, again");
+
+        assert_eq!(active_ids(&conversation), [q1, a1, edited, reply]);
+        assert_eq!(conversation.message(edited).unwrap().parent, Some(a1));
+        assert_eq!(conversation.siblings(edited), [q2, edited]);
+        assert_eq!(
+            conversation
+                .index()
+                .siblings(conversation.message(q2).unwrap()),
+            [q2, edited]
+        );
+        // The old turns are still there
+        assert_eq!(conversation.message(a2).unwrap().content, "a2");
+        assert_eq!(conversation.all_messages().count(), 6);
+    }
+
+    #[test]
+    fn activating_switches_branches() {
+        let (mut conversation, [q1, a1, q2, a2]) = two_turns();
+        conversation.branch_before(a2);
+        let regenerated = conversation.push(Role::Assistant, "a2, regenerated");
+        conversation.branch_before(q2);
+        let edited = conversation.push(Role::User, "q2, edited");
+        assert_eq!(active_ids(&conversation), [q1, a1, edited]);
+
+        // Below the activated message, the most recent reply is followed
+        conversation.activate(q2);
+        assert_eq!(active_ids(&conversation), [q1, a1, q2, regenerated]);
+        conversation.activate(a2);
+        assert_eq!(active_ids(&conversation), [q1, a1, q2, a2]);
+        conversation.activate(edited);
+        assert_eq!(active_ids(&conversation), [q1, a1, edited]);
+
+        // Messages on the active path and unknown ids change nothing
+        conversation.activate(a1);
+        conversation.activate(MessageId(99));
+        assert_eq!(active_ids(&conversation), [q1, a1, edited]);
+        assert_eq!(conversation.all_messages().count(), 6);
+    }
+
+    #[test]
+    fn removing_a_failed_reply_restores_the_old_branch() {
+        let (mut conversation, [q1, a1, q2, a2]) = two_turns();
+        conversation.branch_before(a2);
+        let failed = conversation.push(Role::Assistant, "");
+
+        // Only the end of the active path can be removed
+        conversation.remove_last(a1);
+        assert_eq!(active_ids(&conversation), [q1, a1, q2, failed]);
+
+        conversation.remove_last(failed);
+        assert_eq!(active_ids(&conversation), [q1, a1, q2, a2]);
+        assert_eq!(conversation.siblings(a2), [a2]);
+
+        let failed = conversation.push(Role::User, "q3");
+        conversation.remove_last(failed);
+        assert_eq!(active_ids(&conversation), [q1, a1, q2, a2]);
+        assert_eq!(conversation.all_messages().count(), 4);
+    }
+
+    #[test]
+    fn branches_survive_a_serde_round_trip() {
+        let (mut conversation, [q1, a1, q2, _]) = two_turns();
+        conversation.branch_before(q2);
+        let edited = conversation.push(Role::User, "q2, edited");
+
+        let json = serde_json::to_string(&conversation).unwrap();
+        let mut loaded: Conversation = serde_json::from_str(&json).unwrap();
+        assert_eq!(active_ids(&loaded), [q1, a1, edited]);
+        assert_eq!(loaded.siblings(edited), [q2, edited]);
+        // New messages keep getting fresh ids
+        let reply = loaded.push(Role::Assistant, "reply");
+        assert_eq!(
+            loaded
+                .all_messages()
+                .filter(|message| message.id == reply)
+                .count(),
+            1
+        );
+        loaded.activate(q2);
+        assert_eq!(active_ids(&loaded).len(), 4);
+    }
+
+    #[test]
+    fn loads_saves_from_before_branching() {
+        let json = r#"{
+            "id": "old",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "messages": [
+                { "id": 0, "role": "user", "content": "hi" },
+                { "id": 1, "role": "assistant", "content": "hello" }
+            ],
+            "next_message_id": 2
+        }"#;
+        let conversation: Conversation = serde_json::from_str(json).unwrap();
+        assert_eq!(active_ids(&conversation), [MessageId(0), MessageId(1)]);
+        assert_eq!(conversation.messages()[1].parent, Some(MessageId(0)));
+        assert_eq!(conversation.siblings(MessageId(1)), [MessageId(1)]);
+    }
+}
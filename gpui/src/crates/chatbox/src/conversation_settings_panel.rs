@@ -0,0 +1,227 @@
+// Conversation settings panel
+// Edits the system prompt and generation parameters of a single conversation
+
+use gpui::{
+    div, px, rgb, App, Context, Entity, EventEmitter, FocusHandle, Focusable, FontWeight,
+    IntoElement, MouseButton, ParentElement, Render, SharedString, Styled, Window, prelude::*,
+};
+
+use crate::conversation::ConversationSettings;
+use crate::interactive_chatbox::{InteractiveChatInput, Send};
+
+#[derive(Clone, Debug)]
+pub enum ConversationSettingsEvent {
+    Saved(ConversationSettings),
+    Dismissed,
+}
+
+pub struct ConversationSettingsPanel {
+    system_prompt: Entity<InteractiveChatInput>,
+    temperature: Entity<InteractiveChatInput>,
+    max_tokens: Entity<InteractiveChatInput>,
+    stop_sequences: Entity<InteractiveChatInput>,
+    error: Option<SharedString>,
+    focus_handle: FocusHandle,
+}
+
+impl EventEmitter<ConversationSettingsEvent> for ConversationSettingsPanel {}
+
+impl ConversationSettingsPanel {
+    pub fn new(settings: &ConversationSettings, cx: &mut Context<Self>) -> Self {
+        let system_prompt = Self::field(
+            "You are a helpful assistant.",
+            settings.system_prompt.clone(),
+            cx,
+        );
+        let temperature = Self::field(
+            "Model default (0.0 – 2.0)",
+            settings.temperature.map(|t| t.to_string()).unwrap_or_default(),
+            cx,
+        );
+        let max_tokens = Self::field(
+            "Model default",
+            settings.max_tokens.map(|t| t.to_string()).unwrap_or_default(),
+            cx,
+        );
+        let stop_sequences = Self::field(
+            "Comma-separated, use \\n for newlines",
+            settings
+                .stop_sequences
+                .iter()
+                .map(|stop| stop.replace('\n', "\\n"))
+                .collect::<Vec<_>>()
+                .join(", "),
+            cx,
+        );
+
+        Self {
+            system_prompt,
+            temperature,
+            max_tokens,
+            stop_sequences,
+            error: None,
+            focus_handle: cx.focus_handle(),
+        }
+    }
+
+    fn field(
+        placeholder: &str,
+        text: String,
+        cx: &mut Context<Self>,
+    ) -> Entity<InteractiveChatInput> {
+        let placeholder = SharedString::from(placeholder.to_string());
+        cx.new(|cx| {
+            let mut input = InteractiveChatInput::new(cx);
+            input.set_placeholder(placeholder);
+            input.set_text(text, cx);
+            input
+        })
+    }
+
+    fn parse(&self, cx: &App) -> Result<ConversationSettings, SharedString> {
+        let temperature = self.temperature.read(cx).get_text();
+        let temperature = match temperature.trim() {
+            "" => None,
+            value => match value.parse::<f32>() {
+                Ok(value) if (0.0..=2.0).contains(&value) => Some(value),
+                _ => return Err("Temperature must be a number between 0 and 2".into()),
+            },
+        };
+
+        let max_tokens = self.max_tokens.read(cx).get_text();
+        let max_tokens = match max_tokens.trim() {
+            "" => None,
+            value => match value.parse::<u32>() {
+                Ok(value) if value > 0 => Some(value),
+                _ => return Err("Max tokens must be a positive whole number".into()),
+            },
+        };
+
+        let stop_sequences = self
+            .stop_sequences
+            .read(cx)
+            .get_text()
+            .split(',')
+            .map(str::trim)
+            .filter(|stop| !stop.is_empty())
+            .map(|stop| stop.replace("\\n", "\n"))
+            .collect();
+
+        Ok(ConversationSettings {
+            system_prompt: self.system_prompt.read(cx).get_text(),
+            temperature,
+            max_tokens,
+            stop_sequences,
+        })
+    }
+
+    pub fn save(&mut self, cx: &mut Context<Self>) {
+        match self.parse(cx) {
+            Ok(settings) => {
+                self.error = None;
+                cx.emit(ConversationSettingsEvent::Saved(settings));
+            }
+            Err(error) => self.error = Some(error),
+        }
+        cx.notify();
+    }
+
+    pub fn dismiss(&mut self, cx: &mut Context<Self>) {
+        cx.emit(ConversationSettingsEvent::Dismissed);
+    }
+
+    fn on_send(&mut self, _: &Send, _window: &mut Window, cx: &mut Context<Self>) {
+        self.save(cx);
+    }
+
+    fn render_field(label: &str, input: &Entity<InteractiveChatInput>) -> impl IntoElement {
+        div()
+            .flex()
+            .flex_col()
+            .gap_1()
+            .child(
+                div()
+                    .text_color(rgb(0x374151))
+                    .text_size(px(12.0))
+                    .font_weight(FontWeight::MEDIUM)
+                    .child(label.to_string())
+            )
+            .child(input.clone())
+    }
+}
+
+impl Focusable for ConversationSettingsPanel {
+    fn focus_handle(&self, _: &App) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Render for ConversationSettingsPanel {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        div()
+            .key_context("ConversationSettingsPanel")
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::on_send))
+            .flex()
+            .flex_col()
+            .gap_3()
+            .p_4()
+            .bg(rgb(0xffffff))
+            .border_b_1()
+            .border_color(rgb(0xe5e7eb))
+            .child(
+                div()
+                    .text_color(rgb(0x111827))
+                    .text_size(px(15.0))
+                    .font_weight(FontWeight::BOLD)
+                    .child("Conversation settings")
+            )
+            .child(Self::render_field("System prompt", &self.system_prompt))
+            .child(
+                div()
+                    .flex()
+                    .gap_3()
+                    .child(div().flex_1().child(Self::render_field("Temperature", &self.temperature)))
+                    .child(div().flex_1().child(Self::render_field("Max tokens", &self.max_tokens)))
+            )
+            .child(Self::render_field("Stop sequences", &self.stop_sequences))
+            .when_some(self.error.clone(), |this, error| {
+                this.child(
+                    div()
+                        .text_color(rgb(0xdc2626))
+                        .text_size(px(12.0))
+                        .child(error)
+                )
+            })
+            .child(
+                div()
+                    .flex()
+                    .justify_end()
+                    .gap_2()
+                    .child(
+                        div()
+                            .px_3()
+                            .py_1()
+                            .bg(rgb(0x6c757d))
+                            .text_color(rgb(0xffffff))
+                            .rounded_sm()
+                            .text_sm()
+                            .cursor_pointer()
+                            .on_mouse_up(MouseButton::Left, cx.listener(|this, _, _, cx| this.dismiss(cx)))
+                            .child("Cancel")
+                    )
+                    .child(
+                        div()
+                            .px_3()
+                            .py_1()
+                            .bg(rgb(0x2196f3))
+                            .text_color(rgb(0xffffff))
+                            .rounded_sm()
+                            .text_sm()
+                            .cursor_pointer()
+                            .on_mouse_up(MouseButton::Left, cx.listener(|this, _, _, cx| this.save(cx)))
+                            .child("Save")
+                    )
+            )
+    }
+}
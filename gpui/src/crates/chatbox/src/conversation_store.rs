@@ -0,0 +1,113 @@
+// Conversation persistence
+// Each conversation is stored as a pretty-printed JSON file named after its
+// id. The methods here are blocking; views call them on the background
+// executor.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Utc};
+
+use crate::conversation::{Conversation, ConversationId};
+
+pub const DATA_DIR_ENV_VAR: &str = "CODE_AGENT_DATA_DIR";
+
+#[derive(Clone, Debug)]
+pub struct ConversationStore {
+    dir: PathBuf,
+}
+
+impl ConversationStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Uses `CODE_AGENT_DATA_DIR` if set, otherwise the platform data dir.
+    pub fn from_env() -> Self {
+        let root = std::env::var_os(DATA_DIR_ENV_VAR)
+            .map(PathBuf::from)
+            .or_else(|| dirs::data_dir().map(|dir| dir.join("agent-ui")))
+            .unwrap_or_else(|| PathBuf::from(".agent-ui"));
+        Self::new(root.join("conversations"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Directory for exported transcripts, next to the conversations.
+    pub fn exports_dir(&self) -> PathBuf {
+        self.dir
+            .parent()
+            .map_or_else(|| self.dir.join("exports"), |parent| parent.join("exports"))
+    }
+
+    fn path_for(&self, id: &ConversationId) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Writes the conversation. Saves of the same conversation share a
+    /// temporary file, so callers must not run them concurrently.
+    pub fn save(&self, conversation: &Conversation) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("creating {}", self.dir.display()))?;
+        let path = self.path_for(conversation.id());
+        let json = serde_json::to_string_pretty(conversation)?;
+
+        // Write to a temporary file first so a crash never leaves a torn file
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, json).with_context(|| format!("writing {}", temp_path.display()))?;
+        fs::rename(&temp_path, &path).with_context(|| format!("writing {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn load(&self, id: &ConversationId) -> Result<Conversation> {
+        let path = self.path_for(id);
+        let json = fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Loads every stored conversation, most recently updated first.
+    /// Unreadable files are skipped.
+    pub fn load_all(&self) -> Result<Vec<Conversation>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut conversations = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            match fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|json| Ok(serde_json::from_str::<Conversation>(&json)?))
+            {
+                Ok(conversation) => conversations.push(conversation),
+                Err(error) => log::warn!("Skipping conversation {}: {error:#}", path.display()),
+            }
+        }
+        conversations.sort_by(|a, b| b.updated_at().cmp(&a.updated_at()));
+        Ok(conversations)
+    }
+
+    pub fn delete(&self, id: &ConversationId) -> Result<()> {
+        let path = self.path_for(id);
+        fs::remove_file(&path).with_context(|| format!("deleting {}", path.display()))
+    }
+
+    /// Deletes conversations last updated before `cutoff`, except `keep`.
+    /// Returns how many were deleted.
+    pub fn delete_older_than(&self, cutoff: DateTime<Utc>, keep: &ConversationId) -> Result<usize> {
+        let mut deleted = 0;
+        for conversation in self.load_all()? {
+            if conversation.updated_at() < cutoff && conversation.id() != keep {
+                self.delete(conversation.id())?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,525 @@
+// Conversation export
+// Renders a conversation, or a range of its messages, as Markdown,
+// self-contained HTML or lossless JSON.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::conversation::{ChatMessage, Conversation, ConversationIndex, Role, ToolCall};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Markdown, ExportFormat::Html, ExportFormat::Json];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Json => "JSON",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// Exports the messages in `range` (all messages when `None`).
+pub fn export_conversation(
+    conversation: &Conversation,
+    range: Option<Range<usize>>,
+    format: ExportFormat,
+) -> Result<String> {
+    let range = range.unwrap_or(0..conversation.len());
+    let exported_at = Utc::now();
+    match format {
+        ExportFormat::Markdown => Ok(to_markdown(conversation, range, exported_at)),
+        ExportFormat::Html => Ok(to_html(conversation, range, exported_at)),
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(&conversation.subset(range))?),
+    }
+}
+
+pub(crate) fn role_heading(role: Role) -> &'static str {
+    match role {
+        Role::System => "System",
+        Role::User => "User",
+        Role::Assistant => "Assistant",
+        Role::Tool => "Tool",
+    }
+}
+
+// " (branch 2 of 3)" for messages that have alternatives
+fn branch_label(index: &ConversationIndex, message: &ChatMessage) -> String {
+    let siblings = index.siblings(message);
+    match siblings.iter().position(|id| *id == message.id) {
+        Some(ix) if siblings.len() > 1 => format!(" (branch {} of {})", ix + 1, siblings.len()),
+        _ => String::new(),
+    }
+}
+
+fn messages_in(conversation: &Conversation, range: Range<usize>) -> &[ChatMessage] {
+    let messages = conversation.messages();
+    let end = range.end.min(messages.len());
+    &messages[range.start.min(end)..end]
+}
+
+// Calls made by the exported messages. Their results are rendered with the
+// call instead of on their own.
+fn exported_calls(messages: &[ChatMessage]) -> HashSet<&str> {
+    messages
+        .iter()
+        .flat_map(|message| &message.tool_calls)
+        .map(|call| call.id.as_str())
+        .collect()
+}
+
+fn call_arguments(call: &ToolCall) -> String {
+    serde_json::to_string_pretty(&call.arguments).unwrap_or_else(|_| call.arguments.to_string())
+}
+
+fn call_summary(call: &ToolCall) -> String {
+    format!("Tool call: {} ({})", call.name, call.status.label().to_lowercase())
+}
+
+// Picks a fence longer than any backtick run in `text`
+fn fence_for(text: &str) -> String {
+    let longest_run = text
+        .split(|ch| ch != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest_run.max(2) + 1)
+}
+
+pub fn to_markdown(
+    conversation: &Conversation,
+    range: Range<usize>,
+    exported_at: DateTime<Utc>,
+) -> String {
+    let mut markdown = format!("# {}\n\n", conversation.title());
+    markdown.push_str(&format!(
+        "_Exported {}{}_\n\n",
+        exported_at.format("%Y-%m-%d %H:%M UTC"),
+        conversation
+            .model()
+            .map(|model| format!(" · {model}"))
+            .unwrap_or_default()
+    ));
+
+    if let Some(prompt) = conversation.settings().system_prompt() {
+        markdown.push_str("## System prompt\n\n");
+        for line in prompt.lines() {
+            markdown.push_str(&format!("> {line}\n"));
+        }
+        markdown.push('\n');
+    }
+
+    let index = conversation.index();
+    let messages = messages_in(conversation, range);
+    let calls = exported_calls(messages);
+    for message in messages {
+        if message.role == Role::Tool {
+            if message
+                .tool_call_id
+                .as_deref()
+                .is_some_and(|call_id| calls.contains(call_id))
+            {
+                continue;
+            }
+            let fence = fence_for(&message.content);
+            markdown.push_str(&format!(
+                "<details>\n<summary>Tool result</summary>\n\n{fence}\n{}\n{fence}\n\n</details>\n\n",
+                message.content.trim_end()
+            ));
+            continue;
+        }
+        markdown.push_str(&format!(
+            "### {}{}\n\n",
+            role_heading(message.role),
+            branch_label(&index, message)
+        ));
+        if !message.content.trim().is_empty() {
+            markdown.push_str(message.content.trim_end());
+            markdown.push_str("\n\n");
+        }
+        for call in &message.tool_calls {
+            let arguments = call_arguments(call);
+            let fence = fence_for(&arguments);
+            markdown.push_str(&format!(
+                "<details>\n<summary>{}</summary>\n\n{fence}json\n{arguments}\n{fence}\n\n",
+                escape_html(&call_summary(call))
+            ));
+            if let Some(result) = index.tool_result(&call.id) {
+                let fence = fence_for(&result.content);
+                markdown.push_str(&format!(
+                    "Result:\n\n{fence}\n{}\n{fence}\n\n",
+                    result.content.trim_end()
+                ));
+            }
+            markdown.push_str("</details>\n\n");
+        }
+    }
+    markdown
+}
+
+const HTML_STYLE: &str = r#"
+body { font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif; max-width: 820px; margin: 2rem auto; padding: 0 1rem; color: #111827; background: #f8fafc; }
+h1 { font-size: 1.5rem; }
+.meta { color: #6b7280; font-size: 0.85rem; }
+.message { border: 1px solid #e5e7eb; border-radius: 8px; padding: 0.75rem 1rem; margin: 0.75rem 0; background: #ffffff; }
+.message.user { background: #f1f5f9; border-color: #94a3b8; }
+.message.assistant { background: #e3f2fd; border-color: #2196f3; }
+.message.system { background: #f3f4f6; }
+.role { font-weight: 600; font-size: 0.8rem; text-transform: uppercase; color: #1976d2; margin-bottom: 0.25rem; }
+pre { background: #1f2937; color: #e5e7eb; padding: 0.75rem; border-radius: 6px; overflow-x: auto; }
+code { font-family: "SF Mono", Menlo, Consolas, monospace; font-size: 0.85rem; }
+p code { background: #e5e7eb; padding: 0 0.25rem; border-radius: 3px; }
+.lang { color: #9ca3af; font-size: 0.75rem; }
+.kw { color: #c084fc; } .str { color: #86efac; } .cm { color: #9ca3af; font-style: italic; } .num { color: #fdba74; }
+"#;
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "class", "const", "continue", "def", "else", "enum", "export",
+    "false", "fn", "for", "from", "func", "function", "if", "impl", "import", "in", "let", "match",
+    "mod", "mut", "new", "null", "pub", "return", "self", "static", "struct", "switch", "trait",
+    "true", "type", "use", "var", "where", "while",
+];
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn span(class: &str, text: &str) -> String {
+    format!("<span class=\"{class}\">{}</span>", escape_html(text))
+}
+
+// A small language-agnostic highlighter for keywords, strings, numbers and
+// line comments. It only needs to look reasonable in a pasted transcript.
+fn highlight_code(code: &str) -> String {
+    let mut html = String::new();
+    for (ix, line) in code.lines().enumerate() {
+        if ix > 0 {
+            html.push('\n');
+        }
+        let mut rest = line;
+        while let Some(ch) = rest.chars().next() {
+            if rest.starts_with("//") || rest.starts_with("# ") {
+                html.push_str(&span("cm", rest));
+                break;
+            }
+            let len = if ch == '"' || ch == '\'' {
+                // Up to the closing quote, or the end of an unterminated string
+                let mut end = rest.len();
+                let mut chars = rest.char_indices().skip(1);
+                while let Some((i, next)) = chars.next() {
+                    if next == '\\' {
+                        chars.next();
+                    } else if next == ch {
+                        end = i + next.len_utf8();
+                        break;
+                    }
+                }
+                html.push_str(&span("str", &rest[..end]));
+                end
+            } else if ch.is_ascii_digit() {
+                let end = token_len(rest, |ch| ch.is_ascii_alphanumeric() || ch == '.' || ch == '_');
+                html.push_str(&span("num", &rest[..end]));
+                end
+            } else if ch.is_alphabetic() || ch == '_' {
+                let end = token_len(rest, |ch| ch.is_alphanumeric() || ch == '_');
+                let word = &rest[..end];
+                if KEYWORDS.contains(&word) {
+                    html.push_str(&span("kw", word));
+                } else {
+                    html.push_str(&escape_html(word));
+                }
+                end
+            } else {
+                html.push_str(&escape_html(&rest[..ch.len_utf8()]));
+                ch.len_utf8()
+            };
+            rest = &rest[len..];
+        }
+    }
+    html
+}
+
+// Length of the run of characters at the start of `text` matching `f`
+fn token_len(text: &str, f: impl Fn(char) -> bool) -> usize {
+    text.find(|ch: char| !f(ch)).unwrap_or(text.len())
+}
+
+fn inline_markdown_to_html(text: &str) -> String {
+    let mut html = String::new();
+    for (ix, part) in text.split('`').enumerate() {
+        if ix % 2 == 1 {
+            html.push_str(&format!("<code>{}</code>", escape_html(part)));
+        } else {
+            html.push_str(&escape_html(part));
+        }
+    }
+    html
+}
+
+// Converts fenced code blocks and paragraphs; everything else stays plain text
+fn markdown_to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<(String, Vec<&str>)> = None;
+
+    let flush_paragraph = |paragraph: &mut Vec<&str>, html: &mut String| {
+        if !paragraph.is_empty() {
+            let lines: Vec<String> = paragraph.iter().map(|line| inline_markdown_to_html(line)).collect();
+            html.push_str(&format!("<p>{}</p>\n", lines.join("<br>")));
+            paragraph.clear();
+        }
+    };
+
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if let Some((language, lines)) = code.as_mut() {
+            if trimmed.starts_with("```") {
+                html.push_str(&format!(
+                    "<pre>{}<code>{}</code></pre>\n",
+                    if language.is_empty() {
+                        String::new()
+                    } else {
+                        format!("<div class=\"lang\">{}</div>", escape_html(language))
+                    },
+                    highlight_code(&lines.join("\n"))
+                ));
+                code = None;
+            } else {
+                lines.push(line);
+            }
+        } else if let Some(language) = trimmed.strip_prefix("```") {
+            flush_paragraph(&mut paragraph, &mut html);
+            code = Some((language.trim().to_string(), Vec::new()));
+        } else if trimmed.is_empty() {
+            flush_paragraph(&mut paragraph, &mut html);
+        } else {
+            paragraph.push(line);
+        }
+    }
+    if let Some((_, lines)) = code {
+        html.push_str(&format!("<pre><code>{}</code></pre>\n", highlight_code(&lines.join("\n"))));
+    }
+    flush_paragraph(&mut paragraph, &mut html);
+    html
+}
+
+pub fn to_html(
+    conversation: &Conversation,
+    range: Range<usize>,
+    exported_at: DateTime<Utc>,
+) -> String {
+    let title = escape_html(&conversation.title());
+    let mut body = format!("<h1>{title}</h1>\n");
+    body.push_str(&format!(
+        "<p class=\"meta\">Exported {}{}</p>\n",
+        exported_at.format("%Y-%m-%d %H:%M UTC"),
+        conversation
+            .model()
+            .map(|model| format!(" · {}", escape_html(model)))
+            .unwrap_or_default()
+    ));
+
+    if let Some(prompt) = conversation.settings().system_prompt() {
+        body.push_str(&format!(
+            "<div class=\"message system\"><div class=\"role\">System prompt</div>{}</div>\n",
+            markdown_to_html(prompt)
+        ));
+    }
+
+    let index = conversation.index();
+    let messages = messages_in(conversation, range);
+    let calls = exported_calls(messages);
+    for message in messages {
+        let role = message.role.as_str();
+        if message.role == Role::Tool {
+            if message
+                .tool_call_id
+                .as_deref()
+                .is_some_and(|call_id| calls.contains(call_id))
+            {
+                continue;
+            }
+            body.push_str(&format!(
+                "<details class=\"message tool\"><summary class=\"role\">Tool result</summary><pre><code>{}</code></pre></details>\n",
+                escape_html(&message.content)
+            ));
+            continue;
+        }
+        let mut tool_calls = String::new();
+        for call in &message.tool_calls {
+            tool_calls.push_str(&format!(
+                "<details class=\"tool\"><summary class=\"role\">{}</summary><pre><code>{}</code></pre>",
+                escape_html(&call_summary(call)),
+                escape_html(&call_arguments(call))
+            ));
+            if let Some(result) = index.tool_result(&call.id) {
+                tool_calls.push_str(&format!(
+                    "<div class=\"role\">Result</div><pre><code>{}</code></pre>",
+                    escape_html(&result.content)
+                ));
+            }
+            tool_calls.push_str("</details>");
+        }
+        body.push_str(&format!(
+            "<div class=\"message {role}\"><div class=\"role\">{}{}</div>{}{tool_calls}</div>\n",
+            role_heading(message.role),
+            branch_label(&index, message),
+            markdown_to_html(&message.content)
+        ));
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{HTML_STYLE}</style>\n</head>\n<body>\n{body}</body>\n</html>\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::ToolStatus;
+    use serde_json::json;
+
+    fn exported_at() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    // A user turn, then an assistant turn that called a tool
+    fn conversation_with_tool_call(result: &str) -> Conversation {
+        let mut conversation = Conversation::new();
+        conversation.push(Role::User, "List the files");
+        let reply = conversation.push(Role::Assistant, "Listing them now.");
+        conversation
+            .message_mut(reply)
+            .unwrap()
+            .tool_calls
+            .push(ToolCall {
+                id: "call_1".into(),
+                name: "list_directory".into(),
+                arguments: json!({ "path": "src" }),
+                status: ToolStatus::Pending,
+            });
+        conversation.finish_tool_call(reply, "call_1", ToolStatus::Completed, result);
+        conversation
+    }
+
+    #[test]
+    fn html_escapes_everything_from_the_conversation() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+
+        let mut conversation = Conversation::new();
+        conversation.set_model(Some("local/<model>".into()));
+        conversation.push(Role::User, "<script>alert(1)</script> & `<b>`");
+        conversation.push(
+            Role::Assistant,
+            "```html\n<img src=\"x\" onerror=\"y\">\n```",
+        );
+        let reply = conversation.push(Role::Assistant, "");
+        conversation
+            .message_mut(reply)
+            .unwrap()
+            .tool_calls
+            .push(ToolCall {
+                id: "call_1".into(),
+                name: "<tool>".into(),
+                arguments: json!({ "path": "</pre>" }),
+                status: ToolStatus::Pending,
+            });
+        conversation.finish_tool_call(reply, "call_1", ToolStatus::Completed, "</code><hr>");
+
+        let html = to_html(&conversation, 0..conversation.len(), exported_at());
+        let body = &html[html.find("<body>").unwrap()..];
+        for raw in [
+            "<script>", "<model>", "<b>", "<img", "<tool>", "</pre>\"", "<hr>",
+        ] {
+            assert!(!body.contains(raw), "{raw} is not escaped");
+        }
+        assert!(html.contains("<title>&lt;script&gt;alert(1)&lt;/script&gt; &amp; `&lt;b&gt;`"));
+        assert!(body.contains("&amp; <code>&lt;b&gt;</code>"));
+        assert!(body.contains("local/&lt;model&gt;"));
+        assert!(body.contains("&lt;/code&gt;&lt;hr&gt;"));
+    }
+
+    #[test]
+    fn markdown_fences_outlast_backticks_in_the_content() {
+        assert_eq!(fence_for("no backticks"), "```");
+        assert_eq!(fence_for("`inline` and ``double``"), "```");
+        assert_eq!(fence_for("```rust\nfn main() {}\n```"), "````");
+        assert_eq!(fence_for("a ````` b"), "``````");
+
+        let conversation = conversation_with_tool_call("```\nnested\n```");
+        let markdown = to_markdown(&conversation, 0..conversation.len(), exported_at());
+        assert!(
+            markdown.contains("Result:\n\n````\n```\nnested\n```\n````\n"),
+            "{markdown}"
+        );
+        assert!(
+            markdown.contains("```json\n{\n  \"path\": \"src\"\n}\n```"),
+            "{markdown}"
+        );
+        // The result is shown with its call, not again on its own
+        assert_eq!(markdown.matches("nested").count(), 1);
+    }
+
+    #[test]
+    fn ranges_are_clamped_to_the_conversation() {
+        let conversation = conversation_with_tool_call("main.rs");
+        assert_eq!(conversation.len(), 3);
+        let headings = |range: Range<usize>| {
+            to_markdown(&conversation, range, exported_at())
+                .matches("\n### ")
+                .count()
+        };
+        assert_eq!(headings(0..3), 2);
+        assert_eq!(headings(1..99), 1);
+        assert_eq!(headings(5..99), 0);
+        assert_eq!(headings(Range { start: 2, end: 1 }), 0);
+
+        // A result whose call is outside the range is still exported
+        let markdown = to_markdown(&conversation, 2..3, exported_at());
+        assert!(
+            markdown.contains("<summary>Tool result</summary>"),
+            "{markdown}"
+        );
+
+        let json = export_conversation(&conversation, Some(1..99), ExportFormat::Json).unwrap();
+        let exported: Conversation = serde_json::from_str(&json).unwrap();
+        assert_eq!(exported.len(), 2);
+        assert_eq!(exported.messages()[0].parent, None);
+        let json = export_conversation(&conversation, Some(7..9), ExportFormat::Json).unwrap();
+        assert!(serde_json::from_str::<Conversation>(&json)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+// Find-in-conversation matching
+
+use std::ops::Range;
+
+use anyhow::Result;
+use regex::{Regex, RegexBuilder};
+
+use crate::conversation::{ChatMessage, MessageId};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FindOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub regex: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FindMatch {
+    pub message_id: MessageId,
+    /// Byte range within the message content.
+    pub range: Range<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FindQuery {
+    regex: Regex,
+}
+
+impl FindQuery {
+    /// Returns `Ok(None)` for an empty query and an error for an invalid regex.
+    pub fn new(query: &str, options: FindOptions) -> Result<Option<Self>> {
+        if query.is_empty() {
+            return Ok(None);
+        }
+        let mut pattern = if options.regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        if options.whole_word {
+            pattern = format!(r"\b(?:{pattern})\b");
+        }
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!options.case_sensitive)
+            .multi_line(true)
+            .build()?;
+        Ok(Some(Self { regex }))
+    }
+
+    pub fn find_in(&self, text: &str) -> Vec<Range<usize>> {
+        self.regex
+            .find_iter(text)
+            .filter(|found| !found.is_empty())
+            .map(|found| found.range())
+            .collect()
+    }
+
+    pub fn find_in_messages<'a>(
+        &self,
+        messages: impl IntoIterator<Item = &'a ChatMessage>,
+    ) -> Vec<FindMatch> {
+        messages
+            .into_iter()
+            .flat_map(|message| {
+                self.find_in(&message.content)
+                    .into_iter()
+                    .map(|range| FindMatch {
+                        message_id: message.id,
+                        range,
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, options: FindOptions, text: &str) -> Vec<String> {
+        FindQuery::new(query, options)
+            .unwrap()
+            .unwrap()
+            .find_in(text)
+            .into_iter()
+            .map(|range| text[range].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn case_toggle() {
+        let text = "Rust, rust and RUST";
+        assert_eq!(
+            find("rust", FindOptions::default(), text),
+            ["Rust", "rust", "RUST"]
+        );
+        let case_sensitive = FindOptions {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert_eq!(find("Rust", case_sensitive, text), ["Rust"]);
+        assert_eq!(find("ÉTÉ", FindOptions::default(), "un été"), ["été"]);
+    }
+
+    #[test]
+    fn whole_word_toggle() {
+        let text = "cat concat cat's catalog";
+        assert_eq!(find("cat", FindOptions::default(), text).len(), 4);
+        let whole_word = FindOptions {
+            whole_word: true,
+            ..Default::default()
+        };
+        let ranges = FindQuery::new("cat", whole_word)
+            .unwrap()
+            .unwrap()
+            .find_in(text);
+        assert_eq!(ranges, [0..3, 11..14]);
+
+        // Alternatives are grouped, so each one must be a whole word
+        let regex_words = FindOptions {
+            whole_word: true,
+            regex: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            find("foo|bar", regex_words, "foobar bar foo"),
+            ["bar", "foo"]
+        );
+    }
+
+    #[test]
+    fn regex_toggle() {
+        let text = "a.b axb 42 and 7";
+        assert_eq!(find("a.b", FindOptions::default(), text), ["a.b"]);
+        let regex = FindOptions {
+            regex: true,
+            ..Default::default()
+        };
+        assert_eq!(find("a.b", regex, text), ["a.b", "axb"]);
+        assert_eq!(find(r"\d+", regex, text), ["42", "7"]);
+        assert_eq!(find("^and", regex, "x\nand y"), ["and"]);
+        // Empty matches are never reported
+        assert!(find("z*", regex, text).is_empty());
+
+        assert!(FindQuery::new("(", regex).is_err());
+        assert_eq!(find("(", FindOptions::default(), "f(x)"), ["("]);
+        assert!(FindQuery::new("", regex).unwrap().is_none());
+    }
+
+    #[test]
+    fn matches_carry_their_message() {
+        let mut conversation = crate::conversation::Conversation::new();
+        let first = conversation.push(crate::conversation::Role::User, "one two");
+        let second = conversation.push(crate::conversation::Role::Assistant, "two three two");
+        let query = FindQuery::new("two", FindOptions::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            query.find_in_messages(conversation.messages()),
+            [
+                FindMatch {
+                    message_id: first,
+                    range: 4..7
+                },
+                FindMatch {
+                    message_id: second,
+                    range: 0..3
+                },
+                FindMatch {
+                    message_id: second,
+                    range: 10..13
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,292 @@
+// Find bar
+// Holds the query and options; the chat view owns the matches and reports
+// the match count back through `set_match_status`.
+
+use std::ops::Range;
+
+use gpui::{
+    actions, div, px, rgb, App, Context, Entity, EventEmitter, FocusHandle, Focusable, Hsla,
+    IntoElement, ParentElement, Render, SharedString, StyledText, Styled, Subscription, TextRun,
+    Window, prelude::*,
+};
+
+use crate::find::{FindOptions, FindQuery};
+use crate::interactive_chatbox::{ChatInputEvent, InteractiveChatInput, Send};
+
+actions!(
+    find_bar,
+    [
+        Deploy,
+        SelectNextMatch,
+        SelectPreviousMatch,
+        Dismiss,
+        ToggleCaseSensitive,
+        ToggleWholeWord,
+        ToggleRegex,
+    ]
+);
+
+/// Builds text whose highlighted ranges get a background color. Ranges must
+/// be sorted and non-overlapping.
+pub fn highlighted_text(
+    text: impl Into<SharedString>,
+    color: Hsla,
+    highlights: impl IntoIterator<Item = (Range<usize>, Hsla)>,
+    window: &Window,
+) -> StyledText {
+    let text = text.into();
+    let run = TextRun {
+        len: 0,
+        font: window.text_style().font(),
+        color,
+        background_color: None,
+        underline: None,
+        strikethrough: None,
+    };
+
+    let mut runs = Vec::new();
+    let mut offset = 0;
+    for (range, background) in highlights {
+        if range.start < offset || range.end > text.len() {
+            continue;
+        }
+        if range.start > offset {
+            runs.push(TextRun {
+                len: range.start - offset,
+                ..run.clone()
+            });
+        }
+        runs.push(TextRun {
+            len: range.len(),
+            background_color: Some(background),
+            ..run.clone()
+        });
+        offset = range.end;
+    }
+    if offset < text.len() {
+        runs.push(TextRun {
+            len: text.len() - offset,
+            ..run
+        });
+    }
+
+    StyledText::new(text).with_runs(runs)
+}
+
+#[derive(Clone, Debug)]
+pub enum FindBarEvent {
+    QueryChanged,
+    SelectNext,
+    SelectPrevious,
+    Dismissed,
+}
+
+pub struct FindBar {
+    query_input: Entity<InteractiveChatInput>,
+    options: FindOptions,
+    // (active match index, total matches)
+    match_status: Option<(usize, usize)>,
+    focus_handle: FocusHandle,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl EventEmitter<FindBarEvent> for FindBar {}
+
+impl FindBar {
+    pub fn new(cx: &mut Context<Self>) -> Self {
+        let query_input = cx.new(|cx| {
+            let mut input = InteractiveChatInput::new(cx);
+            input.set_placeholder("Find in conversation...");
+            input
+        });
+        let subscriptions = vec![cx.subscribe(&query_input, |_, _, event, cx| {
+            if *event == ChatInputEvent::Edited {
+                cx.emit(FindBarEvent::QueryChanged);
+            }
+        })];
+
+        Self {
+            query_input,
+            options: FindOptions::default(),
+            match_status: None,
+            focus_handle: cx.focus_handle(),
+            _subscriptions: subscriptions,
+        }
+    }
+
+    pub fn query_input(&self) -> &Entity<InteractiveChatInput> {
+        &self.query_input
+    }
+
+    pub fn options(&self) -> FindOptions {
+        self.options
+    }
+
+    pub fn query_text(&self, cx: &App) -> String {
+        self.query_input.read(cx).get_text()
+    }
+
+    /// The compiled query, or the error message for an invalid regex.
+    pub fn query(&self, cx: &App) -> Result<Option<FindQuery>, SharedString> {
+        FindQuery::new(&self.query_text(cx), self.options).map_err(|error| error.to_string().into())
+    }
+
+    pub fn set_match_status(&mut self, status: Option<(usize, usize)>, cx: &mut Context<Self>) {
+        self.match_status = status;
+        cx.notify();
+    }
+
+    fn toggle_option(&mut self, update: impl FnOnce(&mut FindOptions), cx: &mut Context<Self>) {
+        update(&mut self.options);
+        cx.emit(FindBarEvent::QueryChanged);
+        cx.notify();
+    }
+
+    fn toggle_case_sensitive(&mut self, _: &ToggleCaseSensitive, _: &mut Window, cx: &mut Context<Self>) {
+        self.toggle_option(|options| options.case_sensitive = !options.case_sensitive, cx);
+    }
+
+    fn toggle_whole_word(&mut self, _: &ToggleWholeWord, _: &mut Window, cx: &mut Context<Self>) {
+        self.toggle_option(|options| options.whole_word = !options.whole_word, cx);
+    }
+
+    fn toggle_regex(&mut self, _: &ToggleRegex, _: &mut Window, cx: &mut Context<Self>) {
+        self.toggle_option(|options| options.regex = !options.regex, cx);
+    }
+
+    fn select_next(&mut self, _: &SelectNextMatch, _: &mut Window, cx: &mut Context<Self>) {
+        cx.emit(FindBarEvent::SelectNext);
+    }
+
+    fn select_previous(&mut self, _: &SelectPreviousMatch, _: &mut Window, cx: &mut Context<Self>) {
+        cx.emit(FindBarEvent::SelectPrevious);
+    }
+
+    fn on_send(&mut self, _: &Send, _: &mut Window, cx: &mut Context<Self>) {
+        cx.emit(FindBarEvent::SelectNext);
+    }
+
+    fn dismiss(&mut self, _: &Dismiss, _: &mut Window, cx: &mut Context<Self>) {
+        cx.emit(FindBarEvent::Dismissed);
+    }
+
+    fn render_toggle(
+        &self,
+        id: &'static str,
+        label: &'static str,
+        active: bool,
+        action: impl Fn(&mut Self, &mut Window, &mut Context<Self>) + 'static,
+        cx: &Context<Self>,
+    ) -> impl IntoElement {
+        div()
+            .id(id)
+            .cursor_pointer()
+            .px_1p5()
+            .py_0p5()
+            .rounded_sm()
+            .text_size(px(12.0))
+            .border_1()
+            .when(active, |this| {
+                this.bg(rgb(0xdbeafe)).border_color(rgb(0x2563eb)).text_color(rgb(0x1d4ed8))
+            })
+            .when(!active, |this| {
+                this.border_color(rgb(0xd1d5db)).text_color(rgb(0x6b7280))
+            })
+            .on_click(cx.listener(move |this, _, window, cx| action(this, window, cx)))
+            .child(label)
+    }
+}
+
+impl Focusable for FindBar {
+    fn focus_handle(&self, _: &App) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Render for FindBar {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let (status, status_color) = match (self.query(cx), self.match_status) {
+            (Err(error), _) => (error, rgb(0xdc2626)),
+            (Ok(None), _) => ("".into(), rgb(0x6b7280)),
+            (Ok(Some(_)), Some((active, total))) if total > 0 => {
+                (format!("{}/{}", active + 1, total).into(), rgb(0x6b7280))
+            }
+            (Ok(Some(_)), _) => ("No matches".into(), rgb(0x6b7280)),
+        };
+
+        div()
+            .key_context("FindBar")
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::on_send))
+            .on_action(cx.listener(Self::select_next))
+            .on_action(cx.listener(Self::select_previous))
+            .on_action(cx.listener(Self::dismiss))
+            .on_action(cx.listener(Self::toggle_case_sensitive))
+            .on_action(cx.listener(Self::toggle_whole_word))
+            .on_action(cx.listener(Self::toggle_regex))
+            .flex()
+            .items_center()
+            .gap_2()
+            .px_4()
+            .py_2()
+            .bg(rgb(0xffffff))
+            .border_b_1()
+            .border_color(rgb(0xe5e7eb))
+            .child(div().flex_1().child(self.query_input.clone()))
+            .child(self.render_toggle(
+                "find-case-sensitive",
+                "Aa",
+                self.options.case_sensitive,
+                |this, window, cx| this.toggle_case_sensitive(&ToggleCaseSensitive, window, cx),
+                cx,
+            ))
+            .child(self.render_toggle(
+                "find-whole-word",
+                "ab",
+                self.options.whole_word,
+                |this, window, cx| this.toggle_whole_word(&ToggleWholeWord, window, cx),
+                cx,
+            ))
+            .child(self.render_toggle(
+                "find-regex",
+                ".*",
+                self.options.regex,
+                |this, window, cx| this.toggle_regex(&ToggleRegex, window, cx),
+                cx,
+            ))
+            .child(
+                div()
+                    .min_w(px(72.0))
+                    .text_size(px(12.0))
+                    .text_color(status_color)
+                    .child(status)
+            )
+            .child(
+                div()
+                    .id("find-previous")
+                    .cursor_pointer()
+                    .px_1()
+                    .text_color(rgb(0x374151))
+                    .on_click(cx.listener(|_, _, _, cx| cx.emit(FindBarEvent::SelectPrevious)))
+                    .child("↑")
+            )
+            .child(
+                div()
+                    .id("find-next")
+                    .cursor_pointer()
+                    .px_1()
+                    .text_color(rgb(0x374151))
+                    .on_click(cx.listener(|_, _, _, cx| cx.emit(FindBarEvent::SelectNext)))
+                    .child("↓")
+            )
+            .child(
+                div()
+                    .id("find-dismiss")
+                    .cursor_pointer()
+                    .px_1()
+                    .text_color(rgb(0x6b7280))
+                    .on_click(cx.listener(|_, _, _, cx| cx.emit(FindBarEvent::Dismissed)))
+                    .child("✕")
+            )
+    }
+}
@@ -2,18 +2,63 @@
 // A complete, working text input component with blinking cursor functionality
 // Based on GPUI's official input example and Zed's cursor blinking implementation
 
+use std::collections::{HashMap, HashSet};
 use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
+use futures::channel::oneshot;
+use futures::StreamExt;
+use unicode_segmentation::UnicodeSegmentation;
+
 use gpui::{
-    actions, div, px, rgb, size, point, App, Application, Bounds, ClipboardItem, Context,
-    CursorStyle, ElementId, ElementInputHandler, Entity, EntityInputHandler, FocusHandle,
-    Focusable, GlobalElementId, KeyBinding, LayoutId, MouseButton, MouseDownEvent,
-    MouseMoveEvent, MouseUpEvent, PaintQuad, Pixels, Point, ShapedLine, SharedString,
-    Style, TextRun, UTF16Selection, UnderlineStyle, Window, WindowBounds, WindowOptions,
-    WindowKind, WindowBackgroundAppearance, WindowDecorations,
-    prelude::*, fill, hsla, relative, blue, FontWeight, Task, Timer,
+    actions, div, px, rgb, size, point, App, Application, AvailableSpace, Bounds, ClipboardItem,
+    ContentMask, Context, CursorStyle, ElementId, ElementInputHandler, Entity, EntityInputHandler,
+    EventEmitter,
+    ExternalPaths, FocusHandle, Focusable, GlobalElementId, KeyBinding, KeyContext, LayoutId,
+    MouseButton, MouseDownEvent, MouseMoveEvent, MouseUpEvent, PaintQuad, Pixels, Point,
+    ScrollWheelEvent, SharedString, Style, TextAlign, TextRun, TextStyle, UTF16Selection,
+    UnderlineStyle, Window, WindowBounds, WindowOptions, WindowKind, WindowBackgroundAppearance,
+    WindowDecorations, WrappedLine,
+    prelude::*, fill, hsla, Hsla, relative, blue, anchored, deferred, FontWeight, ScrollHandle,
+    Subscription, Task, TextLayout, Timer,
+};
+use settings::{Settings as _, SettingsStore};
+
+use crate::ansi::AnsiOutput;
+use crate::assistant_settings::{
+    self, save_settings, AssistantSettings, SendKeybinding, SettingsFile, ToolApproval,
+};
+use crate::assistant_settings_view::{AssistantSettingsEvent, AssistantSettingsView};
+use crate::backend::{
+    self, AgentBackend, ChatRequest, CodeAgentBackend, CompletionEvent, ProviderRouter,
+    TokenUsage,
 };
+use crate::cassette;
+use crate::context_budget::{estimate_tokens, ContextBudget, FittedHistory};
+use crate::conversation::{
+    Attachment, ChatMessage, ChipPayload, Conversation, ConversationId, ConversationIndex,
+    ConversationSettings, MessageChip, MessageId, Role, ToolCall, ToolStatus,
+};
+use crate::conversation_settings_panel::{ConversationSettingsEvent, ConversationSettingsPanel};
+use crate::conversation_store::ConversationStore;
+use crate::export::{export_conversation, ExportFormat};
+use crate::mcp::{McpClient, McpConfig};
+use crate::mcp_tool::register_mcp_tools;
+use crate::find::FindMatch;
+use crate::find_bar::{self, highlighted_text, FindBar, FindBarEvent};
+use crate::model_catalog::{ModelCatalog, ModelInfo};
+use crate::openai::{self, OpenAiBackend, OpenAiSettings};
+use crate::anthropic::{self, AnthropicBackend, AnthropicSettings};
+use crate::ollama::{self, OllamaBackend, OllamaSettings};
+use crate::tool_card::ToolCard;
+use crate::tool_permissions::{workspace_root_from_env, PermissionDecision, ToolPolicy};
+use crate::tools::{ToolActivity, ToolActivityEvent, ToolRegistry};
+use crate::command_tool::RunCommandTool;
+use crate::workspace_tools::register_workspace_tools;
+use crate::transcript_selection::{TranscriptPoint, TranscriptSelection};
+use crate::search_panel::{self, ConversationSearchPanel, SearchPanelEvent};
 
 // Actions for text input
 actions!(
@@ -32,6 +77,10 @@ actions!(
         Paste,
         Cut,
         Copy,
+        CopyAsMarkdown,
+        Newline,
+        Send,
+        CancelEdit,
     ]
 );
 
@@ -134,6 +183,12 @@ impl CursorBlinker {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatInputEvent {
+    /// The text changed; cursor moves and selection changes don't count.
+    Edited,
+}
+
 // Main chat input component
 pub struct InteractiveChatInput {
     focus_handle: FocusHandle,
@@ -142,12 +197,56 @@ pub struct InteractiveChatInput {
     selected_range: Range<usize>,
     selection_reversed: bool,
     marked_range: Option<Range<usize>>,
-    last_layout: Option<ShapedLine>,
+    last_layout: Option<InputLayout>,
     last_bounds: Option<Bounds<Pixels>>,
+    // The input grows from `min_lines` to `max_lines` as the text wraps,
+    // then scrolls; a single-line input doesn't wrap
+    min_lines: usize,
+    max_lines: usize,
+    scroll_top: Pixels,
+    // Where the cursor was when last painted, to scroll only when it moves
+    last_cursor: Option<usize>,
     is_selecting: bool,
     cursor_blinker: Entity<CursorBlinker>,
+    text_size: Pixels,
+    send_keybinding: SendKeybinding,
+    // Secret inputs draw a bullet per character and never put their text on
+    // the clipboard
+    secret: bool,
+    reveal_toggle: bool,
+    revealed: bool,
+    // Typed and pasted text is filtered before it is inserted; the validator
+    // runs on the whole text after every change
+    char_filter: Option<Box<dyn Fn(char) -> bool>>,
+    max_length: Option<usize>,
+    validator: Option<Box<dyn Fn(&str) -> Result<(), SharedString>>>,
+    error: Option<SharedString>,
+    // Attachment chips, sorted by position. Each covers its text form
+    // in `content` and is edited as a single unit.
+    chips: Vec<MessageChip>,
+}
+
+// Drawn in place of each character of a secret input
+const SECRET_MASK: char = '•';
+
+// Byte offset of the UTF-16 `offset` in `text`, clamped to its end
+fn utf8_offset_from_utf16(text: &str, offset: usize) -> usize {
+    let mut utf8_offset = 0;
+    let mut utf16_count = 0;
+
+    for ch in text.chars() {
+        if utf16_count >= offset {
+            break;
+        }
+        utf16_count += ch.len_utf16();
+        utf8_offset += ch.len_utf8();
+    }
+
+    utf8_offset
 }
 
+impl EventEmitter<ChatInputEvent> for InteractiveChatInput {}
+
 impl InteractiveChatInput {
     pub fn new(cx: &mut App) -> Self {
         let cursor_blinker = cx.new(|cx| CursorBlinker::new(cx));
@@ -161,8 +260,22 @@ impl InteractiveChatInput {
             marked_range: None,
             last_layout: None,
             last_bounds: None,
+            min_lines: 1,
+            max_lines: 1,
+            scroll_top: px(0.),
+            last_cursor: None,
             is_selecting: false,
             cursor_blinker,
+            text_size: px(16.),
+            send_keybinding: SendKeybinding::Enter,
+            secret: false,
+            reveal_toggle: false,
+            revealed: false,
+            char_filter: None,
+            max_length: None,
+            validator: None,
+            error: None,
+            chips: Vec::new(),
         }
     }
 
@@ -257,12 +370,18 @@ impl InteractiveChatInput {
 
     fn paste(&mut self, _: &Paste, window: &mut Window, cx: &mut Context<Self>) {
         if let Some(text) = cx.read_from_clipboard().and_then(|item| item.text()) {
-            self.replace_text_in_range(None, &text.replace("\n", " "), window, cx);
+            let text = text.replace("\r\n", "\n");
+            let text = if self.max_lines > 1 { text } else { text.replace('\n', " ") };
+            self.replace_text_in_range(None, &text, window, cx);
         }
     }
 
+    fn newline(&mut self, _: &Newline, window: &mut Window, cx: &mut Context<Self>) {
+        self.replace_text_in_range(None, "\n", window, cx);
+    }
+
     fn copy(&mut self, _: &Copy, _: &mut Window, cx: &mut Context<Self>) {
-        if !self.selected_range.is_empty() {
+        if !self.selected_range.is_empty() && !self.secret {
             cx.write_to_clipboard(ClipboardItem::new_string(
                 self.content[self.selected_range.clone()].to_string(),
             ));
@@ -270,7 +389,7 @@ impl InteractiveChatInput {
     }
 
     fn cut(&mut self, _: &Cut, window: &mut Window, cx: &mut Context<Self>) {
-        if !self.selected_range.is_empty() {
+        if !self.selected_range.is_empty() && !self.secret {
             cx.write_to_clipboard(ClipboardItem::new_string(
                 self.content[self.selected_range.clone()].to_string(),
             ));
@@ -298,17 +417,158 @@ impl InteractiveChatInput {
             return 0;
         }
 
-        let (Some(bounds), Some(line)) = (self.last_bounds.as_ref(), self.last_layout.as_ref())
+        let (Some(bounds), Some(layout)) = (self.last_bounds.as_ref(), self.last_layout.as_ref())
         else {
             return 0;
         };
-        if position.y < bounds.top() {
-            return 0;
+        let position = point(
+            position.x - bounds.left(),
+            position.y - bounds.top() + self.scroll_top,
+        );
+        let index = layout.closest_index_for_position(position, self.line_height());
+        // Clicking inside a chip lands on its nearer edge
+        let offset = self.content_offset(index);
+        match self.chip_containing(offset) {
+            Some(chip) if offset - chip.range.start < chip.range.end - offset => chip.range.start,
+            Some(chip) => chip.range.end,
+            None => offset,
+        }
+    }
+
+    fn on_scroll_wheel(
+        &mut self,
+        event: &ScrollWheelEvent,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        // Clamped to the text's height when painted
+        self.scroll_top -= event.delta.pixel_delta(self.line_height()).y;
+        cx.notify();
+    }
+
+    fn line_height(&self) -> Pixels {
+        self.text_size * 1.75
+    }
+
+    // Shapes the displayed text, or the placeholder when empty. Only inputs
+    // that can grow past one line wrap at `width`.
+    fn shape(&self, style: &TextStyle, width: Option<Pixels>, window: &Window) -> InputLayout {
+        let (display_text, text_color) = if self.content.is_empty() {
+            (self.placeholder.clone(), hsla(0., 0., 0., 0.3))
+        } else {
+            (self.display_text(), style.color)
+        };
+
+        let run = TextRun {
+            len: display_text.len(),
+            font: style.font(),
+            color: text_color,
+            background_color: None,
+            underline: None,
+            strikethrough: None,
+        };
+        // Split the text wherever the composition or a chip starts or ends,
+        // then style each piece by what covers it
+        let marked_range = self
+            .marked_range
+            .as_ref()
+            .filter(|_| !self.content.is_empty())
+            .map(|range| self.display_offset(range.start)..self.display_offset(range.end));
+        let chip_ranges: Vec<Range<usize>> = if self.content.is_empty() {
+            Vec::new()
+        } else {
+            self.chips
+                .iter()
+                .map(|chip| {
+                    self.display_offset(chip.range.start)..self.display_offset(chip.range.end)
+                })
+                .collect()
+        };
+        let mut boundaries = vec![0, display_text.len()];
+        for range in marked_range.iter().chain(&chip_ranges) {
+            boundaries.extend([range.start, range.end]);
+        }
+        boundaries.sort_unstable();
+        boundaries.dedup();
+        let runs = boundaries
+            .windows(2)
+            .map(|piece| {
+                let (start, end) = (piece[0], piece[1]);
+                let mut run = TextRun {
+                    len: end - start,
+                    ..run.clone()
+                };
+                if chip_ranges
+                    .iter()
+                    .any(|range| range.start <= start && end <= range.end)
+                {
+                    run.color = rgb(0x1d4ed8).into();
+                    run.background_color = Some(rgb(0xdbeafe).into());
+                }
+                if marked_range
+                    .as_ref()
+                    .is_some_and(|range| range.start <= start && end <= range.end)
+                {
+                    run.underline = Some(UnderlineStyle {
+                        color: Some(run.color),
+                        thickness: px(1.0),
+                        wavy: false,
+                    });
+                }
+                run
+            })
+            .collect::<Vec<_>>();
+
+        let font_size = style.font_size.to_pixels(window.rem_size());
+        let wrap_width = width.filter(|_| self.max_lines > 1);
+        window
+            .text_system()
+            .shape_text(display_text, font_size, &runs, wrap_width, None)
+            .map(|lines| InputLayout {
+                lines: lines.into_iter().collect(),
+            })
+            .unwrap_or_default()
+    }
+
+    fn is_masked(&self) -> bool {
+        self.secret && !self.revealed
+    }
+
+    // The text as laid out: the content, or one mask per character. A
+    // single-line input draws newlines from `set_text` as spaces; multiline
+    // inputs break the line there.
+    fn display_text(&self) -> SharedString {
+        if self.is_masked() {
+            SECRET_MASK
+                .to_string()
+                .repeat(self.content.chars().count())
+                .into()
+        } else if self.max_lines == 1 && self.content.contains('\n') {
+            self.content.replace('\n', " ").into()
+        } else {
+            self.content.clone()
         }
-        if position.y > bounds.bottom() {
-            return self.content.len();
+    }
+
+    // Maps a content offset to the matching offset in `display_text`
+    fn display_offset(&self, offset: usize) -> usize {
+        if self.is_masked() {
+            self.content[..offset].chars().count() * SECRET_MASK.len_utf8()
+        } else {
+            offset
+        }
+    }
+
+    // Maps an offset in `display_text` back to the content
+    fn content_offset(&self, display_offset: usize) -> usize {
+        if self.is_masked() {
+            self.content
+                .char_indices()
+                .nth(display_offset / SECRET_MASK.len_utf8())
+                .map_or(self.content.len(), |(offset, _)| offset)
+        } else {
+            display_offset
         }
-        line.closest_index_for_x(position.x - bounds.left())
     }
 
     fn select_to(&mut self, offset: usize, cx: &mut Context<Self>) {
@@ -326,18 +586,7 @@ impl InteractiveChatInput {
     }
 
     fn offset_from_utf16(&self, offset: usize) -> usize {
-        let mut utf8_offset = 0;
-        let mut utf16_count = 0;
-
-        for ch in self.content.chars() {
-            if utf16_count >= offset {
-                break;
-            }
-            utf16_count += ch.len_utf16();
-            utf8_offset += ch.len_utf8();
-        }
-
-        utf8_offset
+        utf8_offset_from_utf16(&self.content, offset)
     }
 
     fn offset_to_utf16(&self, offset: usize) -> usize {
@@ -371,15 +620,112 @@ impl InteractiveChatInput {
             }
             prev_offset = i;
         }
-        prev_offset
+        // Chips are stepped over whole
+        self.chip_containing(prev_offset)
+            .map_or(prev_offset, |chip| chip.range.start)
     }
 
     fn next_boundary(&self, offset: usize) -> usize {
-        self.content
+        let next_offset = self
+            .content
             .char_indices()
             .find(|(i, _)| *i > offset)
             .map(|(i, _)| i)
-            .unwrap_or(self.content.len())
+            .unwrap_or(self.content.len());
+        self.chip_containing(next_offset)
+            .map_or(next_offset, |chip| chip.range.end)
+    }
+
+    // The chip `offset` falls strictly inside of
+    fn chip_containing(&self, offset: usize) -> Option<&MessageChip> {
+        self.chips
+            .iter()
+            .find(|chip| chip.range.start < offset && offset < chip.range.end)
+    }
+
+    // Grows `range` to cover every chip it cuts into, so edits never leave
+    // part of a chip behind
+    fn expand_to_chips(&self, range: Range<usize>) -> Range<usize> {
+        let start = self
+            .chip_containing(range.start)
+            .map_or(range.start, |chip| chip.range.start);
+        let end = self
+            .chip_containing(range.end)
+            .map_or(range.end, |chip| chip.range.end);
+        start..end
+    }
+
+    // Drops chips the edit touches and moves the ones after it
+    fn update_chips_for_edit(&mut self, range: &Range<usize>, new_len: usize) {
+        self.chips.retain(|chip| {
+            let touched = if range.is_empty() {
+                chip.range.start < range.start && range.start < chip.range.end
+            } else {
+                chip.range.start < range.end && range.start < chip.range.end
+            };
+            !touched
+        });
+        for chip in &mut self.chips {
+            if chip.range.start >= range.end {
+                chip.range.start = chip.range.start - range.len() + new_len;
+                chip.range.end = chip.range.end - range.len() + new_len;
+            }
+        }
+    }
+
+    /// Inserts an attachment chip in place of the selection.
+    pub fn insert_chip(&mut self, payload: ChipPayload, cx: &mut Context<Self>) {
+        let text = payload.text();
+        let range = self.expand_to_chips(self.selected_range.clone());
+        self.update_chips_for_edit(&range, text.len());
+        self.content =
+            (self.content[0..range.start].to_owned() + &text + &self.content[range.end..]).into();
+        let chip_range = range.start..range.start + text.len();
+        let ix = self
+            .chips
+            .partition_point(|chip| chip.range.start < chip_range.start);
+        self.chips.insert(
+            ix,
+            MessageChip {
+                range: chip_range.clone(),
+                payload,
+            },
+        );
+        self.selected_range = chip_range.end..chip_range.end;
+        self.selection_reversed = false;
+        self.marked_range = None;
+        self.validate();
+        cx.emit(ChatInputEvent::Edited);
+        self.cursor_blinker.update(cx, |blinker, cx| blinker.pause_blinking(cx));
+        cx.notify();
+    }
+
+    pub fn chips(&self) -> &[MessageChip] {
+        &self.chips
+    }
+
+    /// Restores chips over the current text, e.g. when editing a sent
+    /// message. Chips whose range doesn't fit the text are dropped.
+    pub fn set_chips(&mut self, mut chips: Vec<MessageChip>, cx: &mut Context<Self>) {
+        chips.retain(|chip| {
+            chip.range.start < chip.range.end
+                && chip.range.end <= self.content.len()
+                && self.content.is_char_boundary(chip.range.start)
+                && self.content.is_char_boundary(chip.range.end)
+        });
+        chips.sort_by_key(|chip| chip.range.start);
+        chips.dedup_by(|next, previous| next.range.start < previous.range.end);
+        self.chips = chips;
+        cx.notify();
+    }
+
+    fn on_drop_paths(&mut self, paths: &ExternalPaths, _: &mut Window, cx: &mut Context<Self>) {
+        if self.secret {
+            return;
+        }
+        for path in paths.paths() {
+            self.insert_chip(ChipPayload::Attachment { path: path.clone() }, cx);
+        }
     }
 
     fn clear(&mut self, cx: &mut Context<Self>) {
@@ -387,18 +733,134 @@ impl InteractiveChatInput {
         self.selected_range = 0..0;
         self.selection_reversed = false;
         self.marked_range = None;
+        self.chips.clear();
+        self.validate();
+        cx.emit(ChatInputEvent::Edited);
         self.cursor_blinker.update(cx, |blinker, cx| blinker.pause_blinking(cx));
         cx.notify();
     }
 
-    fn get_text(&self) -> String {
+    // Drops characters the filter rejects and whatever would go past the
+    // maximum length once `text` replaces `range`
+    fn filter_insertion(&self, range: &Range<usize>, text: &str) -> String {
+        let text: String = match &self.char_filter {
+            Some(filter) => text.chars().filter(|ch| filter(*ch)).collect(),
+            None => text.to_string(),
+        };
+        match self.max_length {
+            Some(max_length) => {
+                let kept = self.content[..range.start].graphemes(true).count()
+                    + self.content[range.end..].graphemes(true).count();
+                text.graphemes(true)
+                    .take(max_length.saturating_sub(kept))
+                    .collect()
+            }
+            None => text,
+        }
+    }
+
+    fn validate(&mut self) {
+        let too_long = self
+            .max_length
+            .filter(|max_length| self.content.graphemes(true).count() > *max_length);
+        self.error = match (too_long, &self.validator) {
+            (Some(max_length), _) => Some(format!("Must be at most {max_length} characters").into()),
+            (None, Some(validator)) => validator(&self.content).err(),
+            (None, None) => None,
+        };
+    }
+
+    /// Only characters the filter accepts can be typed or pasted, e.g.
+    /// `|ch| ch.is_ascii_digit()` for a numeric field.
+    pub fn set_char_filter(&mut self, filter: impl Fn(char) -> bool + 'static) {
+        self.char_filter = Some(Box::new(filter));
+    }
+
+    /// Limits the text to `max_length` graphemes.
+    pub fn set_max_length(&mut self, max_length: Option<usize>, cx: &mut Context<Self>) {
+        self.max_length = max_length;
+        self.validate();
+        cx.notify();
+    }
+
+    /// Checks the text after every change; the error is shown under the
+    /// input until the text is valid again.
+    pub fn set_validator(
+        &mut self,
+        validator: impl Fn(&str) -> Result<(), SharedString> + 'static,
+        cx: &mut Context<Self>,
+    ) {
+        self.validator = Some(Box::new(validator));
+        self.validate();
+        cx.notify();
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    pub fn error(&self) -> Option<&SharedString> {
+        self.error.as_ref()
+    }
+
+    pub fn get_text(&self) -> String {
         self.content.to_string()
     }
 
+    pub fn set_placeholder(&mut self, placeholder: impl Into<SharedString>) {
+        self.placeholder = placeholder.into();
+    }
+
+    pub fn set_text_size(&mut self, text_size: Pixels, cx: &mut Context<Self>) {
+        self.text_size = text_size;
+        cx.notify();
+    }
+
+    pub fn set_send_keybinding(&mut self, keybinding: SendKeybinding, cx: &mut Context<Self>) {
+        self.send_keybinding = keybinding;
+        cx.notify();
+    }
+
+    /// Grows the input with its text from `min_lines` to `max_lines`, after
+    /// which it scrolls to keep the cursor in view.
+    pub fn set_auto_height(&mut self, min_lines: usize, max_lines: usize, cx: &mut Context<Self>) {
+        self.min_lines = min_lines.max(1);
+        self.max_lines = max_lines.max(self.min_lines);
+        cx.notify();
+    }
+
+    /// Masks the text, for API keys and passwords. Copy and cut do nothing
+    /// while the input is secret.
+    pub fn set_secret(&mut self, secret: bool, cx: &mut Context<Self>) {
+        self.secret = secret;
+        self.revealed = false;
+        cx.notify();
+    }
+
+    pub fn is_secret(&self) -> bool {
+        self.secret
+    }
+
+    /// Shows a button that reveals a secret input's text while held down.
+    pub fn set_reveal_toggle(&mut self, reveal_toggle: bool, cx: &mut Context<Self>) {
+        self.reveal_toggle = reveal_toggle;
+        cx.notify();
+    }
+
+    fn set_revealed(&mut self, revealed: bool, cx: &mut Context<Self>) {
+        if self.revealed != revealed {
+            self.revealed = revealed;
+            cx.notify();
+        }
+    }
+
     pub fn set_text(&mut self, text: String, cx: &mut Context<Self>) {
         let len = text.len();
         self.content = text.into();
         self.selected_range = len..len;
+        self.chips.clear();
+        self.validate();
+        cx.emit(ChatInputEvent::Edited);
         self.cursor_blinker.update(cx, |blinker, cx| blinker.pause_blinking(cx));
         cx.notify();
     }
@@ -414,8 +876,16 @@ impl EntityInputHandler for InteractiveChatInput {
         _cx: &mut Context<Self>,
     ) -> Option<String> {
         let range = self.range_from_utf16(&range_utf16);
-        actual_range.replace(self.range_to_utf16(&range));
-        Some(self.content[range].to_string())
+        let range_utf16 = self.range_to_utf16(&range);
+        // Input methods and assistive tools only ever see a secret masked,
+        // one mask per UTF-16 unit so their offsets still line up
+        let text = if self.secret {
+            SECRET_MASK.to_string().repeat(range_utf16.len())
+        } else {
+            self.content[range].to_string()
+        };
+        actual_range.replace(range_utf16);
+        Some(text)
     }
 
     fn selected_text_range(
@@ -456,12 +926,17 @@ impl EntityInputHandler for InteractiveChatInput {
             .map(|range_utf16| self.range_from_utf16(range_utf16))
             .or(self.marked_range.clone())
             .unwrap_or(self.selected_range.clone());
+        let range = self.expand_to_chips(range);
+        let new_text = self.filter_insertion(&range, new_text);
+        self.update_chips_for_edit(&range, new_text.len());
 
         self.content =
-            (self.content[0..range.start].to_owned() + new_text + &self.content[range.end..])
+            (self.content[0..range.start].to_owned() + &new_text + &self.content[range.end..])
                 .into();
         self.selected_range = range.start + new_text.len()..range.start + new_text.len();
         self.marked_range.take();
+        self.validate();
+        cx.emit(ChatInputEvent::Edited);
         self.cursor_blinker.update(cx, |blinker, cx| blinker.pause_blinking(cx));
         cx.notify();
     }
@@ -479,20 +954,30 @@ impl EntityInputHandler for InteractiveChatInput {
             .map(|range_utf16| self.range_from_utf16(range_utf16))
             .or(self.marked_range.clone())
             .unwrap_or(self.selected_range.clone());
+        let range = self.expand_to_chips(range);
+        // Composed text goes through the same filter as typed text
+        let new_text = self.filter_insertion(&range, new_text);
+        self.update_chips_for_edit(&range, new_text.len());
 
         self.content =
-            (self.content[0..range.start].to_owned() + new_text + &self.content[range.end..])
+            (self.content[0..range.start].to_owned() + &new_text + &self.content[range.end..])
                 .into();
         if !new_text.is_empty() {
             self.marked_range = Some(range.start..range.start + new_text.len());
         } else {
             self.marked_range = None;
         }
+        // The selection is relative to the marked text, which the filter may
+        // have shortened
         self.selected_range = new_selected_range_utf16
             .as_ref()
-            .map(|range_utf16| self.range_from_utf16(range_utf16))
-            .map(|new_range| new_range.start + range.start..new_range.end + range.end)
+            .map(|range_utf16| {
+                range.start + utf8_offset_from_utf16(&new_text, range_utf16.start)
+                    ..range.start + utf8_offset_from_utf16(&new_text, range_utf16.end)
+            })
             .unwrap_or_else(|| range.start + new_text.len()..range.start + new_text.len());
+        self.validate();
+        cx.emit(ChatInputEvent::Edited);
 
         self.cursor_blinker.update(cx, |blinker, cx| blinker.pause_blinking(cx));
         cx.notify();
@@ -507,15 +992,16 @@ impl EntityInputHandler for InteractiveChatInput {
     ) -> Option<Bounds<Pixels>> {
         let last_layout = self.last_layout.as_ref()?;
         let range = self.range_from_utf16(&range_utf16);
+        let line_height = self.line_height();
+        let start = last_layout.position_for_index(self.display_offset(range.start), line_height)?;
+        let end = last_layout
+            .position_for_index(self.display_offset(range.end), line_height)
+            .filter(|end| end.y == start.y)
+            .unwrap_or(start);
+        let origin = point(bounds.left(), bounds.top() - self.scroll_top);
         Some(Bounds::from_corners(
-            point(
-                bounds.left() + last_layout.x_for_index(range.start),
-                bounds.top(),
-            ),
-            point(
-                bounds.left() + last_layout.x_for_index(range.end),
-                bounds.bottom(),
-            ),
+            origin + start,
+            origin + point(end.x, end.y + line_height),
         ))
     }
 
@@ -528,8 +1014,111 @@ impl EntityInputHandler for InteractiveChatInput {
         let line_point = self.last_bounds?.localize(&point)?;
         let last_layout = self.last_layout.as_ref()?;
 
-        let utf8_index = last_layout.index_for_x(point.x - line_point.x)?;
-        Some(self.offset_to_utf16(utf8_index))
+        let line_point = gpui::point(line_point.x, line_point.y + self.scroll_top);
+        let display_index = last_layout.index_for_position(line_point, self.line_height())?;
+        Some(self.offset_to_utf16(self.content_offset(display_index)))
+    }
+}
+
+// The shaped text of an input: one wrapped line per paragraph, stacked top
+// to bottom. Offsets are into the whole displayed text, where each paragraph
+// is followed by the newline that ends it.
+#[derive(Default)]
+struct InputLayout {
+    lines: Vec<WrappedLine>,
+}
+
+impl InputLayout {
+    // Each paragraph with the offset it starts at and its top edge
+    fn paragraphs(
+        &self,
+        line_height: Pixels,
+    ) -> impl Iterator<Item = (usize, Pixels, &WrappedLine)> + '_ {
+        let mut start = 0;
+        let mut top = px(0.);
+        self.lines.iter().map(move |line| {
+            let paragraph = (start, top, line);
+            start += line.len() + 1;
+            top += line_height * (line.wrap_boundaries().len() + 1) as f32;
+            paragraph
+        })
+    }
+
+    // Rows on screen, counting each paragraph's wrapped rows
+    fn rows(&self) -> usize {
+        self.lines
+            .iter()
+            .map(|line| line.wrap_boundaries().len() + 1)
+            .sum::<usize>()
+            .max(1)
+    }
+
+    fn width(&self, line_height: Pixels) -> Pixels {
+        self.lines
+            .iter()
+            .map(|line| line.size(line_height).width)
+            .fold(px(0.), Pixels::max)
+    }
+
+    fn height(&self, line_height: Pixels) -> Pixels {
+        line_height * self.rows() as f32
+    }
+
+    fn position_for_index(&self, index: usize, line_height: Pixels) -> Option<Point<Pixels>> {
+        let (start, top, line) = self
+            .paragraphs(line_height)
+            .find(|(start, _, line)| index <= start + line.len())?;
+        let position = line.position_for_index(index - start, line_height)?;
+        Some(point(position.x, position.y + top))
+    }
+
+    // The paragraph under `y`, or the nearest one above or below the text
+    fn paragraph_at(
+        &self,
+        y: Pixels,
+        line_height: Pixels,
+    ) -> Option<(usize, Pixels, &WrappedLine)> {
+        self.paragraphs(line_height)
+            .take_while(|(_, top, _)| *top <= y)
+            .last()
+            .or_else(|| self.paragraphs(line_height).next())
+    }
+
+    fn closest_index_for_position(&self, position: Point<Pixels>, line_height: Pixels) -> usize {
+        let Some((start, top, line)) = self.paragraph_at(position.y, line_height) else {
+            return 0;
+        };
+        match line.closest_index_for_position(point(position.x, position.y - top), line_height) {
+            Ok(index) | Err(index) => start + index,
+        }
+    }
+
+    fn index_for_position(&self, position: Point<Pixels>, line_height: Pixels) -> Option<usize> {
+        let (start, top, line) = self.paragraph_at(position.y, line_height)?;
+        line.index_for_position(point(position.x, position.y - top), line_height)
+            .ok()
+            .map(|index| start + index)
+    }
+
+    fn paint(
+        &self,
+        origin: Point<Pixels>,
+        line_height: Pixels,
+        bounds: Bounds<Pixels>,
+        window: &mut Window,
+        cx: &mut App,
+    ) {
+        for (_, top, line) in self.paragraphs(line_height) {
+            line.paint(
+                point(origin.x, origin.y + top),
+                line_height,
+                TextAlign::Left,
+                Some(bounds),
+                window,
+                cx,
+            )
+            .ok();
+        }
     }
 }
 
@@ -539,9 +1128,11 @@ struct ChatInputTextElement {
 }
 
 struct PrepaintState {
-    line: Option<ShapedLine>,
+    layout: Option<InputLayout>,
     cursor: Option<PaintQuad>,
-    selection: Option<PaintQuad>,
+    selections: Vec<PaintQuad>,
+    scroll_top: Pixels,
+    cursor_offset: usize,
 }
 
 impl IntoElement for ChatInputTextElement {
@@ -573,8 +1164,29 @@ impl Element for ChatInputTextElement {
     ) -> (LayoutId, Self::RequestLayoutState) {
         let mut style = Style::default();
         style.size.width = relative(1.).into();
-        style.size.height = window.line_height().into();
-        (window.request_layout(style, [], cx), ())
+
+        // The height follows the number of wrapped rows, which depends on the
+        // width the input ends up with
+        let input = self.input.clone();
+        let text_style = window.text_style();
+        let layout_id = window.request_measured_layout(
+            style,
+            move |known_dimensions, available_space, window, cx| {
+                let input = input.read(cx);
+                let width = known_dimensions.width.or(match available_space.width {
+                    AvailableSpace::Definite(width) => Some(width),
+                    _ => None,
+                });
+                let line_height = input.line_height();
+                let layout = input.shape(&text_style, width, window);
+                let rows = layout.rows().clamp(input.min_lines, input.max_lines);
+                size(
+                    width.unwrap_or(layout.width(line_height)),
+                    line_height * rows as f32,
+                )
+            },
+        );
+        (layout_id, ())
     }
 
     fn prepaint(
@@ -587,91 +1199,71 @@ impl Element for ChatInputTextElement {
         cx: &mut App,
     ) -> Self::PrepaintState {
         let input = self.input.read(cx);
-        let content = input.content.clone();
-        let selected_range = input.selected_range.clone();
-        let cursor = input.cursor_offset();
-        let style = window.text_style();
-
-        let (display_text, text_color) = if content.is_empty() {
-            (input.placeholder.clone(), hsla(0., 0., 0., 0.3))
-        } else {
-            (content, style.color)
-        };
-
-        let run = TextRun {
-            len: display_text.len(),
-            font: style.font(),
-            color: text_color,
-            background_color: None,
-            underline: None,
-            strikethrough: None,
-        };
-        let runs = if let Some(marked_range) = input.marked_range.as_ref() {
-            vec![
-                TextRun {
-                    len: marked_range.start,
-                    ..run.clone()
-                },
-                TextRun {
-                    len: marked_range.end - marked_range.start,
-                    underline: Some(UnderlineStyle {
-                        color: Some(run.color),
-                        thickness: px(1.0),
-                        wavy: false,
-                    }),
-                    ..run.clone()
-                },
-                TextRun {
-                    len: display_text.len() - marked_range.end,
-                    ..run
-                },
-            ]
-            .into_iter()
-            .filter(|run| run.len > 0)
-            .collect()
-        } else {
-            vec![run]
-        };
-
-        let font_size = style.font_size.to_pixels(window.rem_size());
-        let line = window
-            .text_system()
-            .shape_line(display_text, font_size, &runs, None);
+        let line_height = input.line_height();
+        // Offsets below are into the displayed text, which differs from the
+        // content when it is masked
+        let selected_range = input.display_offset(input.selected_range.start)
+            ..input.display_offset(input.selected_range.end);
+        let cursor = input.display_offset(input.cursor_offset());
+        let layout = input.shape(&window.text_style(), Some(bounds.size.width), window);
+
+        // Scroll the cursor's row into view when the cursor has moved, and
+        // never past the end of the text
+        let cursor_position = layout
+            .position_for_index(cursor, line_height)
+            .unwrap_or_default();
+        let mut scroll_top = input.scroll_top;
+        if input.last_cursor != Some(cursor) {
+            if cursor_position.y < scroll_top {
+                scroll_top = cursor_position.y;
+            } else if cursor_position.y + line_height > scroll_top + bounds.size.height {
+                scroll_top = cursor_position.y + line_height - bounds.size.height;
+            }
+        }
+        let max_scroll_top = (layout.height(line_height) - bounds.size.height).max(px(0.));
+        let scroll_top = scroll_top.min(max_scroll_top).max(px(0.));
+        let origin = point(bounds.left(), bounds.top() - scroll_top);
 
-        let cursor_pos = line.x_for_index(cursor);
-        let (selection, cursor) = if selected_range.is_empty() {
+        let (selections, cursor_quad) = if selected_range.is_empty() {
             (
-                None,
+                Vec::new(),
                 Some(fill(
-                    Bounds::new(
-                        point(bounds.left() + cursor_pos, bounds.top()),
-                        size(px(2.), bounds.bottom() - bounds.top()),
-                    ),
+                    Bounds::new(origin + cursor_position, size(px(2.), line_height)),
                     blue(),
                 )),
             )
         } else {
-            (
-                Some(fill(
-                    Bounds::from_corners(
-                        point(
-                            bounds.left() + line.x_for_index(selected_range.start),
-                            bounds.top(),
+            let start = layout
+                .position_for_index(selected_range.start, line_height)
+                .unwrap_or_default();
+            let end = layout
+                .position_for_index(selected_range.end, line_height)
+                .unwrap_or_default();
+            // One quad for each row the selection touches
+            let start_row = (start.y / line_height).round() as usize;
+            let end_row = (end.y / line_height).round() as usize;
+            let selections = (start_row..=end_row)
+                .map(|row| {
+                    let top = line_height * row as f32;
+                    let left = if row == start_row { start.x } else { px(0.) };
+                    let right = if row == end_row { end.x } else { bounds.size.width };
+                    fill(
+                        Bounds::from_corners(
+                            origin + point(left, top),
+                            origin + point(right, top + line_height),
                         ),
-                        point(
-                            bounds.left() + line.x_for_index(selected_range.end),
-                            bounds.bottom(),
-                        ),
-                    ),
-                    blue().opacity(0.3),
-                )),
-                None,
-            )
+                        blue().opacity(0.3),
+                    )
+                })
+                .collect();
+            (selections, None)
         };
         PrepaintState {
-            line: Some(line),
-            cursor,
-            selection,
+            layout: Some(layout),
+            cursor: cursor_quad,
+            selections,
+            scroll_top,
+            cursor_offset: cursor,
         }
     }
 
@@ -685,31 +1277,39 @@ impl Element for ChatInputTextElement {
         window: &mut Window,
         cx: &mut App,
     ) {
-        let focus_handle = self.input.read(cx).focus_handle.clone();
+        let input = self.input.read(cx);
+        let focus_handle = input.focus_handle.clone();
+        let line_height = input.line_height();
+        let cursor_visible =
+            focus_handle.is_focused(window) && input.cursor_blinker.read(cx).is_visible();
         window.handle_input(
             &focus_handle,
             ElementInputHandler::new(bounds, self.input.clone()),
             cx,
         );
-        if let Some(selection) = prepaint.selection.take() {
-            window.paint_quad(selection)
-        }
-        let line = prepaint.line.take().unwrap();
-        line.paint(bounds.origin, window.line_height(), window, cx)
-            .unwrap();
 
-        if focus_handle.is_focused(window) {
-            let cursor_visible = self.input.read(cx).cursor_blinker.read(cx).is_visible();
-            if let Some(cursor) = prepaint.cursor.take() {
-                if cursor_visible {
+        let layout = prepaint.layout.take().unwrap();
+        let origin = point(bounds.left(), bounds.top() - prepaint.scroll_top);
+        // Rows scrolled out of view are clipped to the input
+        window.with_content_mask(Some(ContentMask { bounds }), |window| {
+            for selection in prepaint.selections.drain(..) {
+                window.paint_quad(selection);
+            }
+            layout.paint(origin, line_height, bounds, window, cx);
+            if cursor_visible {
+                if let Some(cursor) = prepaint.cursor.take() {
                     window.paint_quad(cursor);
                 }
             }
-        }
+        });
 
+        let scroll_top = prepaint.scroll_top;
+        let cursor_offset = prepaint.cursor_offset;
         self.input.update(cx, |input, _cx| {
-            input.last_layout = Some(line);
+            input.last_layout = Some(layout);
             input.last_bounds = Some(bounds);
+            input.scroll_top = scroll_top;
+            input.last_cursor = Some(cursor_offset);
         });
     }
 }
@@ -727,9 +1327,20 @@ impl Render for InteractiveChatInput {
             });
         }
 
-        div()
+        // Enter sends unless the input is marked for cmd-enter, and only
+        // multiline inputs take newlines (see the keymap)
+        let mut key_context = KeyContext::new_with_defaults();
+        key_context.add("InteractiveChatInput");
+        if self.send_keybinding == SendKeybinding::CmdEnter {
+            key_context.add("send_with_cmd_enter");
+        }
+        if self.max_lines > 1 {
+            key_context.add("multiline");
+        }
+
+        let input = div()
             .flex()
-            .key_context("InteractiveChatInput")
+            .key_context(key_context)
             .track_focus(&self.focus_handle(cx))
             .cursor(CursorStyle::IBeam)
             .on_action(cx.listener(Self::backspace))
@@ -745,19 +1356,63 @@ impl Render for InteractiveChatInput {
             .on_action(cx.listener(Self::paste))
             .on_action(cx.listener(Self::cut))
             .on_action(cx.listener(Self::copy))
+            .on_action(cx.listener(Self::newline))
             .on_mouse_down(MouseButton::Left, cx.listener(Self::on_mouse_down))
             .on_mouse_up(MouseButton::Left, cx.listener(Self::on_mouse_up))
             .on_mouse_up_out(MouseButton::Left, cx.listener(Self::on_mouse_up))
             .on_mouse_move(cx.listener(Self::on_mouse_move))
+            .on_scroll_wheel(cx.listener(Self::on_scroll_wheel))
+            .on_drop(cx.listener(Self::on_drop_paths))
             .bg(rgb(0xf8fafc))
             .border_1()
-            .border_color(rgb(0xd1d5db))
+            .border_color(if self.error.is_some() { rgb(0xdc2626) } else { rgb(0xd1d5db) })
             .rounded_md()
-            .line_height(px(28.))
-            .text_size(px(16.))
+            .line_height(self.line_height())
+            .text_size(self.text_size)
             .px_3()
             .py_2()
             .child(ChatInputTextElement { input: cx.entity() })
+            .when(self.secret && self.reveal_toggle, |this| {
+                // Shows the text only while the button is held down
+                this.child(
+                    div()
+                        .id("reveal-secret")
+                        .flex_none()
+                        .pl_2()
+                        .cursor_pointer()
+                        .text_color(if self.revealed { rgb(0x2196f3) } else { rgb(0x9ca3af) })
+                        .on_mouse_down(
+                            MouseButton::Left,
+                            cx.listener(|this, _, _, cx| {
+                                cx.stop_propagation();
+                                this.set_revealed(true, cx);
+                            }),
+                        )
+                        .on_mouse_up(
+                            MouseButton::Left,
+                            cx.listener(|this, _, _, cx| this.set_revealed(false, cx)),
+                        )
+                        .on_mouse_up_out(
+                            MouseButton::Left,
+                            cx.listener(|this, _, _, cx| this.set_revealed(false, cx)),
+                        )
+                        .child("👁"),
+                )
+            });
+
+        div()
+            .flex()
+            .flex_col()
+            .gap_1()
+            .child(input)
+            .when_some(self.error.clone(), |this, error| {
+                this.child(
+                    div()
+                        .text_color(rgb(0xdc2626))
+                        .text_size(px(12.0))
+                        .child(error)
+                )
+            })
     }
 }
 
@@ -767,43 +1422,1349 @@ impl Focusable for InteractiveChatInput {
     }
 }
 
+// Terminal output kept for the tool card; only the tail is retained
+const MAX_TOOL_ACTIVITY_BYTES: usize = 256 * 1024;
+
+struct ToolActivityState {
+    output: AnsiOutput,
+    summary: Option<String>,
+    // Taken once the tool is killed
+    kill: Option<oneshot::Sender<()>>,
+    _events: Task<()>,
+}
+
+impl ToolActivityState {
+    fn apply(&mut self, event: ToolActivityEvent) {
+        match event {
+            ToolActivityEvent::Output(text) => self.output.push(&text),
+            ToolActivityEvent::Summary(summary) => self.summary = Some(summary),
+        }
+    }
+}
+
 // Main chatbox component with messages
 pub struct InteractiveChatbox {
-    messages: Vec<String>,
+    conversation: Conversation,
+    context_budget: ContextBudget,
+    model_catalog: ModelCatalog,
+    model_menu_open: bool,
+    backend: Arc<dyn AgentBackend>,
+    pending_completion: Option<Task<()>>,
+    // Token counts the provider reported for the latest reply
+    last_usage: Option<TokenUsage>,
+    // Why the latest request failed. Shown under the transcript only; it is
+    // neither saved nor sent to the model.
+    completion_error: Option<SharedString>,
+    store: ConversationStore,
+    // Saves of a conversation run one at a time. A snapshot taken while one
+    // is being written waits here and replaces any older waiting snapshot.
+    unsaved: HashMap<ConversationId, Conversation>,
+    saving: HashSet<ConversationId>,
+    // Conversations closed while their tools were running. Results are
+    // recorded here until the last tool finishes.
+    detached: HashMap<ConversationId, Conversation>,
+    workspace_root: PathBuf,
+    tool_registry: ToolRegistry,
+    tool_policy: ToolPolicy,
+    settings: AssistantSettings,
+    mcp_clients: Vec<Arc<McpClient>>,
+    // Live output of running tool calls, keyed by call id. A finished call's
+    // card shows its result instead.
+    tool_activity: HashMap<String, ToolActivityState>,
+    settings_panel: Option<(Entity<ConversationSettingsPanel>, Subscription)>,
+    assistant_settings_view: Option<(Entity<AssistantSettingsView>, Subscription)>,
+    system_prompt_expanded: bool,
+    // Message range used by export; `None` exports the whole thread
+    message_selection: Option<Range<usize>>,
+    message_selection_anchor: usize,
+    status: Option<SharedString>,
+    search_panel: Option<(Entity<ConversationSearchPanel>, Subscription)>,
+    // Message revealed by the last search result, shown highlighted
+    highlighted_message: Option<MessageId>,
+    find_bar: Option<(Entity<FindBar>, Subscription)>,
+    find_matches: Vec<FindMatch>,
+    active_match: usize,
+    // User message being edited in place in the transcript; saving it starts
+    // a sibling branch
+    editing_message: Option<(MessageId, Entity<InteractiveChatInput>)>,
+    transcript_selection: Option<TranscriptSelection>,
+    selecting_transcript: bool,
+    // Text layouts of the rendered messages, for mapping mouse positions.
+    // Tool results shown inside their tool card have no layout.
+    message_layouts: Vec<Option<TextLayout>>,
+    scroll_handle: ScrollHandle,
     chat_input: Entity<InteractiveChatInput>,
     focus_handle: FocusHandle,
+    _subscriptions: Vec<Subscription>,
 }
 
 impl InteractiveChatbox {
-    pub fn new(cx: &mut App) -> Self {
+    pub fn new(cx: &mut Context<Self>) -> Self {
         let chat_input = cx.new(|cx| InteractiveChatInput::new(cx));
+        // Re-render on every edit so the token indicator stays live
+        assistant_settings::init(cx);
+        let subscriptions = vec![
+            cx.observe(&chat_input, |_, _, cx| cx.notify()),
+            cx.observe_global::<SettingsStore>(|this, cx| {
+                this.apply_settings(AssistantSettings::get_global(cx).clone(), cx);
+            }),
+            // A file that doesn't validate leaves the current settings in place
+            cx.observe_global::<SettingsFile>(|this, cx| {
+                if let Some(error) = cx.global::<SettingsFile>().error() {
+                    this.status = Some(format!("Settings not applied: {error}").into());
+                    cx.notify();
+                }
+            }),
+        ];
+
+        let mut conversation = Conversation::new();
+        for message in [
+            "🎉 Welcome to the Interactive Chatbox!",
+            "✨ This input field has full text editing functionality!",
+            "💪 Try typing, selecting, copying, and pasting!",
+            "⌨️ Use arrow keys, Home/End, Ctrl+A to select all!",
+            "✨ The cursor blinks naturally when focused!",
+        ] {
+            conversation.push(Role::Assistant, message);
+        }
 
-        Self {
-            messages: vec![
-                "🎉 Welcome to the Interactive Chatbox!".to_string(),
-                "✨ This input field has full text editing functionality!".to_string(),
-                "💪 Try typing, selecting, copying, and pasting!".to_string(),
-                "⌨️ Use arrow keys, Home/End, Ctrl+A to select all!".to_string(),
-                "✨ The cursor blinks naturally when focused!".to_string(),
-            ],
+        let settings = AssistantSettings::get_global(cx).clone();
+        chat_input.update(cx, |input, cx| {
+            input.set_text_size(px(settings.font_sizes.input), cx);
+            input.set_send_keybinding(settings.send_keybinding, cx);
+            input.set_auto_height(1, 6, cx);
+        });
+
+        let mut model_catalog = ModelCatalog::from_env();
+        let backend = Self::build_backend(&settings, &mut model_catalog, cx);
+        if let Some(model) = &settings.default_model {
+            model_catalog.set_default_model(model);
+        }
+        let context_budget = ContextBudget::new(model_catalog.default_model().context_length);
+        let workspace_root = workspace_root_from_env();
+        let mut tool_registry = ToolRegistry::new();
+        register_workspace_tools(&mut tool_registry, &workspace_root);
+        tool_registry.register(Arc::new(RunCommandTool::new(workspace_root.clone())));
+        let tool_policy = ToolPolicy::load(&workspace_root).unwrap_or_else(|error| {
+            log::error!("Failed to load tool policy: {error:#}");
+            ToolPolicy::default()
+        });
+        Self::start_mcp_servers(cx);
+
+        let this = Self {
+            conversation,
+            context_budget,
+            model_catalog,
+            model_menu_open: false,
+            backend,
+            pending_completion: None,
+            last_usage: None,
+            completion_error: None,
+            store: ConversationStore::from_env(),
+            unsaved: HashMap::new(),
+            saving: HashSet::new(),
+            detached: HashMap::new(),
+            workspace_root,
+            tool_registry,
+            tool_policy,
+            settings,
+            mcp_clients: Vec::new(),
+            tool_activity: HashMap::new(),
+            settings_panel: None,
+            assistant_settings_view: None,
+            system_prompt_expanded: false,
+            message_selection: None,
+            message_selection_anchor: 0,
+            status: None,
+            search_panel: None,
+            highlighted_message: None,
+            find_bar: None,
+            find_matches: Vec::new(),
+            active_match: 0,
+            editing_message: None,
+            transcript_selection: None,
+            selecting_transcript: false,
+            message_layouts: Vec::new(),
+            scroll_handle: ScrollHandle::new(),
             chat_input,
             focus_handle: cx.focus_handle(),
-        }
+            _subscriptions: subscriptions,
+        };
+        this.prune_history(cx);
+        this
     }
 
     pub fn add_message(&mut self, message: &str, cx: &mut Context<Self>) {
-        self.messages.push(message.to_string());
+        self.conversation.push(Role::Assistant, message);
         cx.notify();
     }
 
-    pub fn get_input_text(&self, cx: &App) -> String {
-        self.chat_input.read(cx).get_text()
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
     }
 
-    pub fn clear_input(&mut self, cx: &mut Context<Self>) {
-        self.chat_input.update(cx, |input, cx| {
-            input.clear(cx);
-        });
+    pub fn context_budget(&self) -> ContextBudget {
+        let mut budget = self.context_budget;
+        // Leave room for the reply length requested by this conversation,
+        // but never more than half the window so some history always fits
+        if let Some(max_tokens) = self.conversation.settings().max_tokens {
+            budget.reserved_output_tokens = (max_tokens as usize).min(budget.max_tokens / 2);
+        }
+        budget
+    }
+
+    pub fn set_context_budget(&mut self, budget: ContextBudget, cx: &mut Context<Self>) {
+        self.context_budget = budget;
+        cx.notify();
+    }
+
+    /// The history as it would be sent with the next request, after the
+    /// truncation strategy has been applied.
+    pub fn request_history(&self) -> FittedHistory {
+        self.context_budget().fit(
+            self.conversation.settings().system_prompt(),
+            self.conversation.messages(),
+        )
+    }
+
+    pub fn set_conversation_settings(
+        &mut self,
+        settings: ConversationSettings,
+        cx: &mut Context<Self>,
+    ) {
+        self.conversation.set_settings(settings);
+        self.save_conversation(cx);
+        cx.notify();
+    }
+
+    pub fn set_store(&mut self, store: ConversationStore) {
+        self.store = store;
+    }
+
+    fn save_conversation(&mut self, cx: &mut Context<Self>) {
+        self.conversation.touch();
+        self.enqueue_save(self.conversation.clone(), cx);
+    }
+
+    fn enqueue_save(&mut self, conversation: Conversation, cx: &mut Context<Self>) {
+        let id = conversation.id().clone();
+        self.unsaved.insert(id.clone(), conversation);
+        if !self.saving.insert(id.clone()) {
+            return;
+        }
+
+        let store = self.store.clone();
+        cx.spawn(async move |this, cx| loop {
+            let next = this.update(cx, |this, _| {
+                let next = this.unsaved.remove(&id);
+                if next.is_none() {
+                    this.saving.remove(&id);
+                }
+                next
+            });
+            let Ok(Some(conversation)) = next else {
+                break;
+            };
+            let store = store.clone();
+            cx.background_spawn(async move {
+                if let Err(error) = store.save(&conversation) {
+                    log::error!("Failed to save conversation: {error:#}");
+                }
+            })
+            .await;
+        })
+        .detach();
+    }
+
+    fn toggle_settings_panel(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        if self.settings_panel.take().is_some() {
+            cx.notify();
+            return;
+        }
+
+        let panel = cx.new(|cx| ConversationSettingsPanel::new(self.conversation.settings(), cx));
+        let subscription = cx.subscribe(&panel, |this, _, event, cx| {
+            if let ConversationSettingsEvent::Saved(settings) = event {
+                this.set_conversation_settings(settings.clone(), cx);
+            }
+            this.settings_panel = None;
+            cx.notify();
+        });
+        window.focus(&panel.focus_handle(cx));
+        self.settings_panel = Some((panel, subscription));
+        cx.notify();
+    }
+
+    // Routes each provider to its backend. Endpoints from the settings win
+    // over the environment; only OpenAI's key and model can also be set
+    // there.
+    fn build_backend(
+        settings: &AssistantSettings,
+        model_catalog: &mut ModelCatalog,
+        cx: &mut Context<Self>,
+    ) -> Arc<dyn AgentBackend> {
+        let http_client = cx.http_client();
+        let endpoints = &settings.endpoints;
+        let code_agent = match &endpoints.code_agent {
+            Some(url) => CodeAgentBackend::new(
+                Some(url.clone()),
+                std::env::var(backend::API_KEY_ENV_VAR).ok(),
+                http_client.clone(),
+            ),
+            None => CodeAgentBackend::from_env(http_client.clone()),
+        };
+        let mut router = ProviderRouter::new(Arc::new(code_agent));
+
+        let openai_settings = OpenAiSettings::from_settings(settings);
+        if openai_settings.is_configured() {
+            if let Some(model) = openai_settings.model_id() {
+                model_catalog.add_model(&model);
+            }
+            router.register(
+                openai::PROVIDER_ID,
+                Arc::new(OpenAiBackend::new(openai_settings, http_client.clone())),
+            );
+        }
+        let mut anthropic_settings = AnthropicSettings::from_env();
+        if endpoints.anthropic.is_some() {
+            anthropic_settings.base_url = endpoints.anthropic.clone();
+        }
+        if anthropic_settings.is_configured() {
+            router.register(
+                anthropic::PROVIDER_ID,
+                Arc::new(AnthropicBackend::new(anthropic_settings, http_client.clone())),
+            );
+        }
+        let mut ollama_settings = OllamaSettings::from_env();
+        if endpoints.ollama.is_some() {
+            ollama_settings.base_url = endpoints.ollama.clone();
+        }
+        // Without a host, a local server is probed. Its models only reach the
+        // catalog if it answers, so nothing routes to it otherwise.
+        let report_errors = ollama_settings.is_configured();
+        let ollama = Arc::new(OllamaBackend::new(ollama_settings, http_client));
+        router.register(ollama::PROVIDER_ID, ollama.clone());
+        Self::load_ollama_models(ollama, report_errors, cx);
+
+        let router: Arc<dyn AgentBackend> = Arc::new(router);
+        cassette::backend_from_env(router.clone()).unwrap_or_else(|error| {
+            log::error!("Failed to open cassette: {error:#}");
+            router
+        })
+    }
+
+    pub fn settings(&self) -> &AssistantSettings {
+        &self.settings
+    }
+
+    pub fn apply_settings(&mut self, settings: AssistantSettings, cx: &mut Context<Self>) {
+        if settings == self.settings {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.settings, settings);
+        if self.settings.endpoints != previous.endpoints
+            || self.settings.openai != previous.openai
+        {
+            self.backend = Self::build_backend(&self.settings, &mut self.model_catalog, cx);
+        }
+        // Clearing the default keeps the current one until the next launch
+        if let Some(model) = &self.settings.default_model {
+            self.model_catalog.set_default_model(model);
+        }
+        self.context_budget.max_tokens = self.active_model().context_length;
+        let font_sizes = self.settings.font_sizes;
+        let send_keybinding = self.settings.send_keybinding;
+        self.chat_input.update(cx, |input, cx| {
+            input.set_text_size(px(font_sizes.input), cx);
+            input.set_send_keybinding(send_keybinding, cx);
+        });
+        if let Some((_, input)) = self.editing_message.as_ref() {
+            input.update(cx, |input, cx| {
+                input.set_text_size(px(font_sizes.messages), cx);
+                input.set_send_keybinding(send_keybinding, cx);
+            });
+        }
+        if self.settings.history_retention_days != previous.history_retention_days {
+            self.prune_history(cx);
+        }
+        self.status = Some("Settings applied".into());
+        cx.notify();
+    }
+
+    fn save_assistant_settings(&mut self, settings: AssistantSettings, cx: &mut Context<Self>) {
+        let save = save_settings(settings.clone(), cx);
+        cx.spawn(async move |this, cx| {
+            if let Err(error) = save.await {
+                this.update(cx, |this, cx| {
+                    this.status = Some(format!("Settings not saved: {error:#}").into());
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+        // The settings store sees the same settings come back and ignores them
+        self.apply_settings(settings, cx);
+    }
+
+    // Deletes stored conversations past the retention period, never the
+    // open one
+    fn prune_history(&self, cx: &mut Context<Self>) {
+        let Some(cutoff) = self.settings.retention_cutoff(chrono::Utc::now()) else {
+            return;
+        };
+        let store = self.store.clone();
+        let keep = self.conversation.id().clone();
+        cx.spawn(async move |this, cx| {
+            let result = cx
+                .background_spawn(async move { store.delete_older_than(cutoff, &keep) })
+                .await;
+            this.update(cx, |this, cx| {
+                match result {
+                    Ok(0) => return,
+                    Ok(count) => {
+                        this.status =
+                            Some(format!("Deleted {count} conversations past retention").into());
+                    }
+                    Err(error) => {
+                        this.status = Some(format!("Failed to prune history: {error:#}").into());
+                    }
+                }
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn toggle_assistant_settings(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        if self.assistant_settings_view.take().is_some() {
+            cx.notify();
+            return;
+        }
+
+        let settings = self.settings.clone();
+        let path = cx.global::<SettingsFile>().path().clone();
+        let view = cx.new(|cx| AssistantSettingsView::new(&settings, path, cx));
+        let subscription = cx.subscribe(&view, |this, _, event, cx| {
+            if let AssistantSettingsEvent::Saved(settings) = event {
+                this.save_assistant_settings(settings.clone(), cx);
+            }
+            this.assistant_settings_view = None;
+            cx.notify();
+        });
+        window.focus(&view.focus_handle(cx));
+        self.assistant_settings_view = Some((view, subscription));
+        cx.notify();
+    }
+
+    /// Selects a message for export; with `extend`, selects the range from
+    /// the previously selected message.
+    pub fn select_message(&mut self, ix: usize, extend: bool, cx: &mut Context<Self>) {
+        if extend && self.message_selection.is_some() {
+            let anchor = self.message_selection_anchor;
+            self.message_selection = Some(anchor.min(ix)..anchor.max(ix) + 1);
+        } else if self.message_selection == Some(ix..ix + 1) {
+            self.message_selection = None;
+        } else {
+            self.message_selection_anchor = ix;
+            self.message_selection = Some(ix..ix + 1);
+        }
+        cx.notify();
+    }
+
+    pub fn export(&mut self, format: ExportFormat, cx: &mut Context<Self>) {
+        let range = self.message_selection.clone();
+        let contents = match export_conversation(&self.conversation, range, format) {
+            Ok(contents) => contents,
+            Err(error) => {
+                self.status = Some(format!("Export failed: {error:#}").into());
+                cx.notify();
+                return;
+            }
+        };
+
+        let slug: String = self
+            .conversation
+            .title()
+            .chars()
+            .map(|ch| if ch.is_alphanumeric() { ch.to_ascii_lowercase() } else { '-' })
+            .collect();
+        let file_name = format!(
+            "{}-{}.{}",
+            slug.trim_matches('-'),
+            chrono::Utc::now().format("%Y%m%d-%H%M%S"),
+            format.extension()
+        );
+        let path = self.store.exports_dir().join(file_name);
+
+        cx.spawn(async move |this, cx| {
+            let write_path = path.clone();
+            let result = cx
+                .background_spawn(async move {
+                    if let Some(parent) = write_path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&write_path, contents)
+                })
+                .await;
+            this.update(cx, |this, cx| {
+                this.status = Some(match result {
+                    Ok(()) => format!("Exported to {}", path.display()).into(),
+                    Err(error) => format!("Export failed: {error}").into(),
+                });
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    /// Replaces the displayed conversation, e.g. one loaded from the store.
+    pub fn open_conversation(&mut self, conversation: Conversation, cx: &mut Context<Self>) {
+        // A detached copy is newer than the stored one
+        let conversation = self
+            .detached
+            .remove(conversation.id())
+            .unwrap_or(conversation);
+        let previous = std::mem::replace(&mut self.conversation, conversation);
+        if previous.has_running_tools() {
+            self.detached.insert(previous.id().clone(), previous);
+        }
+        self.context_budget.max_tokens = self.active_model().context_length;
+        self.pending_completion = None;
+        self.completion_error = None;
+        self.message_selection = None;
+        self.highlighted_message = None;
+        self.editing_message = None;
+        self.transcript_selection = None;
+        self.status = None;
+        self.update_find_matches(cx);
+        cx.notify();
+    }
+
+    /// Opens a stored conversation and scrolls to one of its messages.
+    pub fn reveal_message(
+        &mut self,
+        conversation_id: ConversationId,
+        message_id: MessageId,
+        cx: &mut Context<Self>,
+    ) {
+        if self.conversation.id() == &conversation_id {
+            self.scroll_to_message(message_id, cx);
+            return;
+        }
+
+        let store = self.store.clone();
+        cx.spawn(async move |this, cx| {
+            let result = cx
+                .background_spawn(async move { store.load(&conversation_id) })
+                .await;
+            this.update(cx, |this, cx| match result {
+                Ok(conversation) => {
+                    this.open_conversation(conversation, cx);
+                    this.scroll_to_message(message_id, cx);
+                }
+                Err(error) => {
+                    this.status = Some(format!("Failed to open conversation: {error:#}").into());
+                    cx.notify();
+                }
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn scroll_to_message(&mut self, message_id: MessageId, cx: &mut Context<Self>) {
+        // Search results can point into a branch that isn't shown
+        if self.conversation.position(message_id).is_none() {
+            self.conversation.activate(message_id);
+            self.transcript_selection = None;
+            self.update_find_matches(cx);
+        }
+        self.scroll_message_into_view(message_id);
+        self.highlighted_message = Some(message_id);
+        cx.notify();
+    }
+
+    fn scroll_message_into_view(&self, message_id: MessageId) {
+        let Some(ix) = self.conversation.position(message_id) else {
+            return;
+        };
+        // The system prompt pseudo-message is the first child of the list
+        let offset = usize::from(self.conversation.settings().system_prompt().is_some());
+        self.scroll_handle.scroll_to_item(ix + offset);
+    }
+
+    fn transcript_point_for_position(&self, position: Point<Pixels>) -> Option<TranscriptPoint> {
+        // Pick the first message whose text ends below the pointer, so
+        // positions in the gaps between messages snap to the next one
+        let mut layouts = self
+            .message_layouts
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, layout)| Some((ix, layout.as_ref()?)));
+        let (message_ix, layout) = layouts
+            .clone()
+            .find(|(_, layout)| position.y <= layout.bounds().bottom())
+            .or_else(|| layouts.next_back())?;
+        let offset = match layout.index_for_position(position) {
+            Ok(offset) | Err(offset) => offset,
+        };
+        Some(TranscriptPoint { message_ix, offset })
+    }
+
+    fn begin_transcript_selection(
+        &mut self,
+        event: &MouseDownEvent,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(point) = self.transcript_point_for_position(event.position) else {
+            return;
+        };
+        self.transcript_selection = match self.transcript_selection {
+            Some(mut selection) if event.modifiers.shift => {
+                selection.head = point;
+                Some(selection)
+            }
+            _ => Some(TranscriptSelection::new(point)),
+        };
+        self.selecting_transcript = true;
+        // Focus the transcript so copy reaches it instead of the input
+        window.focus(&self.focus_handle);
+        cx.stop_propagation();
+        cx.notify();
+    }
+
+    fn extend_transcript_selection(&mut self, event: &MouseMoveEvent, cx: &mut Context<Self>) {
+        if !self.selecting_transcript {
+            return;
+        }
+        let Some(point) = self.transcript_point_for_position(event.position) else {
+            return;
+        };
+        if let Some(selection) = self.transcript_selection.as_mut() {
+            if selection.head != point {
+                selection.head = point;
+                cx.notify();
+            }
+        }
+    }
+
+    fn end_transcript_selection(&mut self, cx: &mut Context<Self>) {
+        self.selecting_transcript = false;
+        if self.transcript_selection.is_some_and(|selection| selection.is_empty()) {
+            self.transcript_selection = None;
+            cx.notify();
+        }
+    }
+
+    fn copy_transcript_selection(&mut self, as_markdown: bool, cx: &mut Context<Self>) {
+        let Some(selection) = self.transcript_selection else {
+            return;
+        };
+        let messages = self.conversation.messages();
+        let text = if as_markdown {
+            selection.selected_markdown(messages)
+        } else {
+            selection.selected_text(messages)
+        };
+        if text.is_empty() {
+            return;
+        }
+        self.status = Some(format!("Copied {} characters", text.chars().count()).into());
+        cx.write_to_clipboard(ClipboardItem::new_string(text));
+        cx.notify();
+    }
+
+    fn copy(&mut self, _: &Copy, _: &mut Window, cx: &mut Context<Self>) {
+        self.copy_transcript_selection(false, cx);
+    }
+
+    fn copy_as_markdown(&mut self, _: &CopyAsMarkdown, _: &mut Window, cx: &mut Context<Self>) {
+        self.copy_transcript_selection(true, cx);
+    }
+
+    fn deploy_find(&mut self, _: &find_bar::Deploy, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some((find_bar, _)) = self.find_bar.as_ref() {
+            let query_input = find_bar.read(cx).query_input().clone();
+            window.focus(&query_input.focus_handle(cx));
+            return;
+        }
+
+        let find_bar = cx.new(|cx| FindBar::new(cx));
+        let subscription = cx.subscribe_in(&find_bar, window, |this, _, event, window, cx| {
+            match event {
+                FindBarEvent::QueryChanged => {
+                    this.active_match = 0;
+                    this.update_find_matches(cx);
+                    if let Some(found) = this.find_matches.first() {
+                        this.scroll_message_into_view(found.message_id);
+                    }
+                }
+                FindBarEvent::SelectNext => this.select_match(true, cx),
+                FindBarEvent::SelectPrevious => this.select_match(false, cx),
+                FindBarEvent::Dismissed => {
+                    this.find_bar = None;
+                    this.find_matches.clear();
+                    this.focus_input(window, cx);
+                    cx.notify();
+                }
+            }
+        });
+        let query_input = find_bar.read(cx).query_input().clone();
+        window.focus(&query_input.focus_handle(cx));
+        self.find_bar = Some((find_bar, subscription));
+        cx.notify();
+    }
+
+    // Recomputes matches against the current transcript, keeping the active
+    // match where it was when possible
+    fn update_find_matches(&mut self, cx: &mut Context<Self>) {
+        let Some((find_bar, _)) = self.find_bar.as_ref() else {
+            return;
+        };
+        let find_bar = find_bar.clone();
+        self.find_matches = match find_bar.read(cx).query(cx) {
+            Ok(Some(query)) => query.find_in_messages(self.conversation.messages()),
+            Ok(None) | Err(_) => Vec::new(),
+        };
+        self.active_match = self.active_match.min(self.find_matches.len().saturating_sub(1));
+        let status = (!self.find_matches.is_empty())
+            .then(|| (self.active_match, self.find_matches.len()));
+        find_bar.update(cx, |find_bar, cx| find_bar.set_match_status(status, cx));
+        cx.notify();
+    }
+
+    fn select_match(&mut self, forward: bool, cx: &mut Context<Self>) {
+        let count = self.find_matches.len();
+        if count == 0 {
+            return;
+        }
+        self.active_match = if forward {
+            (self.active_match + 1) % count
+        } else {
+            (self.active_match + count - 1) % count
+        };
+        if let Some((find_bar, _)) = self.find_bar.as_ref() {
+            let status = Some((self.active_match, count));
+            find_bar.update(cx, |find_bar, cx| find_bar.set_match_status(status, cx));
+        }
+        self.scroll_message_into_view(self.find_matches[self.active_match].message_id);
+        cx.notify();
+    }
+
+    fn toggle_search_panel(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        if self.search_panel.take().is_some() {
+            cx.notify();
+            return;
+        }
+
+        let store = self.store.clone();
+        let panel = cx.new(|cx| ConversationSearchPanel::new(store, cx));
+        let subscription = cx.subscribe(&panel, |this, _, event, cx| {
+            match event {
+                SearchPanelEvent::Open {
+                    conversation_id,
+                    message_id,
+                } => this.reveal_message(conversation_id.clone(), *message_id, cx),
+                SearchPanelEvent::Dismissed => {}
+            }
+            this.search_panel = None;
+            cx.notify();
+        });
+        window.focus(&panel.read(cx).query_input().focus_handle(cx));
+        self.search_panel = Some((panel, subscription));
+        cx.notify();
+    }
+
+    fn toggle_system_prompt(&mut self, cx: &mut Context<Self>) {
+        self.system_prompt_expanded = !self.system_prompt_expanded;
+        cx.notify();
+    }
+
+    /// Switches the message at `id` to its previous or next alternative.
+    pub fn switch_branch(&mut self, id: MessageId, forward: bool, cx: &mut Context<Self>) {
+        let siblings = self.conversation.siblings(id);
+        let Some(ix) = siblings.iter().position(|sibling| *sibling == id) else {
+            return;
+        };
+        let target = if forward {
+            siblings.get(ix + 1)
+        } else {
+            ix.checked_sub(1).and_then(|ix| siblings.get(ix))
+        };
+        let Some(&target) = target else {
+            return;
+        };
+        self.conversation.activate(target);
+        self.editing_message = None;
+        self.transcript_selection = None;
+        self.update_find_matches(cx);
+        self.save_conversation(cx);
+        cx.notify();
+    }
+
+    pub fn toggle_pinned(&mut self, id: MessageId, cx: &mut Context<Self>) {
+        let pinned = self.conversation.message(id).is_some_and(|message| message.pinned);
+        self.conversation.set_pinned(id, !pinned);
+        cx.notify();
+    }
+
+    pub fn set_backend(&mut self, backend: Arc<dyn AgentBackend>, cx: &mut Context<Self>) {
+        self.backend = backend;
+        cx.notify();
+    }
+
+    pub fn model_catalog(&self) -> &ModelCatalog {
+        &self.model_catalog
+    }
+
+    pub fn set_model_catalog(&mut self, catalog: ModelCatalog, cx: &mut Context<Self>) {
+        self.model_catalog = catalog;
+        self.context_budget.max_tokens = self.active_model().context_length;
+        cx.notify();
+    }
+
+    /// The model used by this conversation, falling back to the catalog default.
+    pub fn active_model(&self) -> &ModelInfo {
+        self.conversation
+            .model()
+            .and_then(|id| self.model_catalog.model(id))
+            .unwrap_or_else(|| self.model_catalog.default_model())
+    }
+
+    pub fn select_model(&mut self, id: &str, cx: &mut Context<Self>) {
+        let Some(model) = self.model_catalog.model(id) else {
+            return;
+        };
+        self.context_budget.max_tokens = model.context_length;
+        self.conversation.set_model(Some(model.id.clone()));
+        self.model_menu_open = false;
+        self.save_conversation(cx);
+        cx.notify();
+    }
+
+    fn toggle_model_menu(&mut self, cx: &mut Context<Self>) {
+        self.model_menu_open = !self.model_menu_open;
+        cx.notify();
+    }
+
+    fn cycle_truncation_strategy(&mut self, cx: &mut Context<Self>) {
+        self.context_budget.strategy = self.context_budget.strategy.next();
+        cx.notify();
+    }
+
+    /// Turns a previous user message into an editor in the transcript.
+    /// Saving it adds the edited text as a new branch and regenerates from
+    /// there.
+    pub fn edit_message(&mut self, id: MessageId, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(message) = self.conversation.message(id) else {
+            return;
+        };
+        if message.role != Role::User {
+            return;
+        }
+        let content = message.content.clone();
+        let chips = message.chips.clone();
+        let text_size = px(self.settings.font_sizes.messages);
+        let send_keybinding = self.settings.send_keybinding;
+        let input = cx.new(|cx| {
+            let mut input = InteractiveChatInput::new(cx);
+            input.set_text_size(text_size, cx);
+            input.set_send_keybinding(send_keybinding, cx);
+            input.set_auto_height(1, 12, cx);
+            input.set_text(content, cx);
+            input.set_chips(chips, cx);
+            input
+        });
+        window.focus(&input.focus_handle(cx));
+        self.editing_message = Some((id, input));
+        self.transcript_selection = None;
+        cx.notify();
+    }
+
+    pub fn cancel_edit(&mut self, cx: &mut Context<Self>) {
+        if self.editing_message.take().is_some() {
+            cx.notify();
+        }
+    }
+
+    /// Sends the edited message as a sibling of the original.
+    pub fn submit_edit(&mut self, cx: &mut Context<Self>) {
+        let Some((id, input)) = self.editing_message.as_ref() else {
+            return;
+        };
+        let (id, input) = (*id, input.read(cx));
+        let text = input.get_text();
+        if text.trim().is_empty() || !input.is_valid() {
+            return;
+        }
+        let chips = input.chips().to_vec();
+        self.editing_message = None;
+        self.pending_completion = None;
+        self.conversation.branch_before(id);
+        self.transcript_selection = None;
+        let message_id = self.conversation.push_with_chips(text, chips);
+        self.update_find_matches(cx);
+        self.attach_and_complete(message_id, cx);
+        cx.notify();
+    }
+
+    /// Asks the backend again with the history before an assistant reply.
+    /// The new reply becomes a sibling branch of the old one.
+    pub fn regenerate(&mut self, id: MessageId, cx: &mut Context<Self>) {
+        if !self.can_regenerate(id) {
+            return;
+        }
+        self.pending_completion = None;
+        self.conversation.branch_before(id);
+        self.transcript_selection = None;
+        self.update_find_matches(cx);
+        self.complete(cx);
+        cx.notify();
+    }
+
+    // Only replies to the user can be asked for again, not e.g. the welcome
+    // messages
+    fn can_regenerate(&self, id: MessageId) -> bool {
+        let Some(message) = self.conversation.message(id) else {
+            return false;
+        };
+        message.role == Role::Assistant
+            && message
+                .parent
+                .and_then(|parent| self.conversation.message(parent))
+                .is_some_and(|parent| parent.role == Role::User)
+    }
+
+    fn send(&mut self, _: &Send, _window: &mut Window, cx: &mut Context<Self>) {
+        let text = self.get_input_text(cx);
+        if text.trim().is_empty() || !self.chat_input.read(cx).is_valid() {
+            return;
+        }
+        let chips = self.chat_input.read(cx).chips().to_vec();
+        let message_id = self.conversation.push_with_chips(text, chips);
+        self.clear_input(cx);
+        self.update_find_matches(cx);
+        self.attach_and_complete(message_id, cx);
+        cx.notify();
+    }
+
+    // Reads the files behind the message's chips off the main thread and
+    // stores them with the message, then asks for a reply
+    fn attach_and_complete(&mut self, message_id: MessageId, cx: &mut Context<Self>) {
+        let mut paths: Vec<PathBuf> = self
+            .conversation
+            .message(message_id)
+            .map(|message| {
+                message
+                    .chips
+                    .iter()
+                    .map(|chip| chip.payload.path().to_path_buf())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut seen = HashSet::new();
+        paths.retain(|path| seen.insert(path.clone()));
+        if paths.is_empty() {
+            self.complete(cx);
+            return;
+        }
+        self.last_usage = None;
+        self.completion_error = None;
+        // Held as the pending completion, so switching conversations cancels it
+        self.pending_completion = Some(cx.spawn(async move |this, cx| {
+            let attachments = cx
+                .background_spawn(async move {
+                    paths
+                        .iter()
+                        .map(|path| Attachment::read(path))
+                        .collect::<Vec<_>>()
+                })
+                .await;
+            this.update(cx, |this, cx| {
+                if let Some(message) = this.conversation.message_mut(message_id) {
+                    message.attachments = attachments;
+                }
+                this.complete(cx);
+            })
+            .ok();
+        }));
+    }
+
+    fn complete(&mut self, cx: &mut Context<Self>) {
+        let settings = self.conversation.settings();
+        let request = ChatRequest {
+            model: self.active_model().id.clone(),
+            messages: self.request_history().messages,
+            temperature: settings.temperature,
+            max_tokens: settings.max_tokens,
+            stop: settings.stop_sequences.clone(),
+            tools: self.tool_registry.definitions(),
+        };
+        let backend = self.backend.clone();
+        self.last_usage = None;
+        self.completion_error = None;
+
+        self.pending_completion = Some(cx.spawn(async move |this, cx| {
+            let mut reply = None;
+            let result = async {
+                let mut events = backend.stream_completion(request, cx).await?;
+                let message_id = this.update(cx, |this, cx| {
+                    cx.notify();
+                    this.conversation.push(Role::Assistant, "")
+                })?;
+                reply = Some(message_id);
+                while let Some(event) = events.next().await {
+                    match event? {
+                        CompletionEvent::Text(text) => this.update(cx, |this, cx| {
+                            if let Some(message) = this.conversation.message_mut(message_id) {
+                                message.content.push_str(&text);
+                            }
+                            this.update_find_matches(cx);
+                            cx.notify();
+                        })?,
+                        CompletionEvent::ToolUse(call) => this.update(cx, |this, cx| {
+                            if let Some(message) = this.conversation.message_mut(message_id) {
+                                message.tool_calls.push(call);
+                            }
+                            cx.notify();
+                        })?,
+                        CompletionEvent::Usage(usage) => this.update(cx, |this, cx| {
+                            this.last_usage = Some(usage);
+                            cx.notify();
+                        })?,
+                        CompletionEvent::Stop(_) => break,
+                    }
+                }
+                anyhow::Ok(message_id)
+            }
+            .await;
+
+            this.update(cx, |this, cx| {
+                this.pending_completion = None;
+                match result {
+                    Ok(message_id) => this.run_tool_calls(message_id, cx),
+                    Err(error) => {
+                        // Drop the reply if the request failed before it got any content
+                        let empty = reply
+                            .and_then(|id| this.conversation.message(id))
+                            .is_some_and(|message| {
+                                message.content.is_empty() && message.tool_calls.is_empty()
+                            });
+                        if let Some(id) = reply.filter(|_| empty) {
+                            this.conversation.remove_last(id);
+                            this.update_find_matches(cx);
+                        }
+                        this.completion_error = Some(format!("{error:#}").into());
+                    }
+                }
+                this.save_conversation(cx);
+                cx.notify();
+            })
+            .ok();
+        }));
+    }
+
+    pub fn workspace_root(&self) -> &Path {
+        &self.workspace_root
+    }
+
+    // Starts the pending tool calls of an assistant message. Calls the
+    // policy doesn't allow wait for the user's decision.
+    fn run_tool_calls(&mut self, message_id: MessageId, cx: &mut Context<Self>) {
+        let Some(message) = self.conversation.message(message_id) else {
+            return;
+        };
+        let pending: Vec<ToolCall> = message
+            .tool_calls
+            .iter()
+            .filter(|call| call.status == ToolStatus::Pending)
+            .cloned()
+            .collect();
+        for call in pending {
+            let needs_approval = self
+                .tool_registry
+                .tool(&call.name)
+                .is_some_and(|tool| tool.needs_approval());
+            let approval = self.settings.tool_approval;
+            if !needs_approval
+                || approval == ToolApproval::AlwaysAllow
+                || (approval == ToolApproval::Ask && self.tool_policy.allows(&call))
+            {
+                self.run_tool(message_id, call, cx);
+            } else if approval == ToolApproval::Deny {
+                self.conversation.finish_tool_call(
+                    message_id,
+                    &call.id,
+                    ToolStatus::Denied,
+                    format!("Error: running `{}` is disabled in the assistant settings", call.name),
+                );
+            } else if let Some(call) = self.conversation.tool_call_mut(message_id, &call.id) {
+                call.status = ToolStatus::AwaitingApproval;
+            }
+        }
+        self.continue_after_tools(message_id, cx);
+    }
+
+    /// Applies the user's decision for a call that is awaiting approval.
+    pub fn resolve_tool_permission(
+        &mut self,
+        message_id: MessageId,
+        call_id: &str,
+        decision: PermissionDecision,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(call) = self.conversation.tool_call_mut(message_id, call_id) else {
+            return;
+        };
+        if call.status != ToolStatus::AwaitingApproval {
+            return;
+        }
+        let call = call.clone();
+
+        if self.tool_policy.record(&call, &decision) {
+            let policy = self.tool_policy.clone();
+            cx.background_spawn(async move {
+                if let Err(error) = policy.save() {
+                    log::error!("Failed to save tool policy: {error:#}");
+                }
+            })
+            .detach();
+        }
+
+        if decision == PermissionDecision::Deny {
+            // The model sees the denial as a failed call and can adjust
+            self.conversation.finish_tool_call(
+                message_id,
+                call_id,
+                ToolStatus::Denied,
+                format!("Error: the user denied permission to run `{}`", call.name),
+            );
+        } else {
+            self.run_tool(message_id, call, cx);
+        }
+        self.continue_after_tools(message_id, cx);
+        self.save_conversation(cx);
+        cx.notify();
+    }
+
+    // Launches the configured MCP servers in the background and registers
+    // their tools as they come up
+    fn start_mcp_servers(cx: &mut Context<Self>) {
+        let config = match McpConfig::load() {
+            Ok(config) => config,
+            Err(error) => {
+                log::error!("Failed to load MCP config: {error:#}");
+                return;
+            }
+        };
+        for (name, server) in config.servers {
+            if server.disabled {
+                continue;
+            }
+            cx.spawn(async move |this, cx| {
+                let executor = cx.background_executor().clone();
+                let result = McpClient::start(name.clone(), &server, executor).await;
+                this.update(cx, |this, cx| {
+                    this.status = Some(match result {
+                        Ok(client) => {
+                            let client = Arc::new(client);
+                            let registration =
+                                register_mcp_tools(&mut this.tool_registry, &client);
+                            this.mcp_clients.push(client);
+                            let mut status = format!(
+                                "MCP server {name} connected · {} tools",
+                                registration.registered
+                            );
+                            if !registration.skipped.is_empty() {
+                                status.push_str(&format!(
+                                    " · skipped {} (name already taken)",
+                                    registration.skipped.join(", ")
+                                ));
+                            }
+                            status.into()
+                        }
+                        Err(error) => format!("MCP server {name} failed: {error:#}").into(),
+                    });
+                    cx.notify();
+                })
+                .ok();
+            })
+            .detach();
+        }
+    }
+
+    // Adds the locally installed Ollama models to the model picker
+    fn load_ollama_models(
+        ollama: Arc<OllamaBackend>,
+        report_errors: bool,
+        cx: &mut Context<Self>,
+    ) {
+        cx.spawn(async move |this, cx| {
+            let result = ollama.list_models().await;
+            this.update(cx, |this, cx| {
+                match result {
+                    Ok(models) => {
+                        let count = models.len();
+                        for model in models {
+                            this.model_catalog.insert_model(model);
+                        }
+                        this.context_budget.max_tokens = this.active_model().context_length;
+                        this.status = Some(format!("Ollama: {count} models installed").into());
+                    }
+                    Err(error) if report_errors => {
+                        this.status = Some(format!("Ollama unavailable: {error:#}").into());
+                    }
+                    Err(_) => {}
+                }
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    pub fn mcp_clients(&self) -> &[Arc<McpClient>] {
+        &self.mcp_clients
+    }
+
+    pub fn tool_registry(&self) -> &ToolRegistry {
+        &self.tool_registry
+    }
+
+    pub fn tool_registry_mut(&mut self) -> &mut ToolRegistry {
+        &mut self.tool_registry
+    }
+
+    fn run_tool(&mut self, message_id: MessageId, call: ToolCall, cx: &mut Context<Self>) {
+        let Some(tool) = self.tool_registry.tool(&call.name) else {
+            self.conversation.finish_tool_call(
+                message_id,
+                &call.id,
+                ToolStatus::Failed,
+                format!("Error: no tool named `{}` is available", call.name),
+            );
+            return;
+        };
+        if let Some(call) = self.conversation.tool_call_mut(message_id, &call.id) {
+            call.status = ToolStatus::Running;
+        }
+
+        let (activity, mut events, kill) = ToolActivity::new();
+        let call_id = call.id.clone();
+        let conversation_id = self.conversation.id().clone();
+        self.tool_activity.insert(
+            call.id.clone(),
+            ToolActivityState {
+                output: AnsiOutput::new(MAX_TOOL_ACTIVITY_BYTES),
+                summary: None,
+                kill: Some(kill),
+                _events: cx.spawn(async move |this, cx| {
+                    while let Some(event) = events.next().await {
+                        let updated = this.update(cx, |this, cx| {
+                            if let Some(state) = this.tool_activity.get_mut(&call_id) {
+                                state.apply(event);
+                                cx.notify();
+                            }
+                        });
+                        if updated.is_err() {
+                            break;
+                        }
+                    }
+                }),
+            },
+        );
+
+        cx.spawn(async move |this, cx| {
+            let result = tool.run(call.arguments.clone(), activity, cx).await;
+            this.update(cx, |this, cx| {
+                let (status, output) = match result {
+                    Ok(output) => (ToolStatus::Completed, output),
+                    Err(error) => (ToolStatus::Failed, format!("Error: {error:#}")),
+                };
+                this.tool_activity.remove(&call.id);
+                this.record_tool_result(&conversation_id, message_id, &call.id, status, output, cx);
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    // Records a finished call in the conversation that made it, which may no
+    // longer be open. Only the open conversation goes on to send the results
+    // back to the model.
+    fn record_tool_result(
+        &mut self,
+        conversation_id: &ConversationId,
+        message_id: MessageId,
+        call_id: &str,
+        status: ToolStatus,
+        output: String,
+        cx: &mut Context<Self>,
+    ) {
+        if self.conversation.id() == conversation_id {
+            self.conversation.finish_tool_call(message_id, call_id, status, output);
+            self.continue_after_tools(message_id, cx);
+            self.save_conversation(cx);
+        } else if let Some(mut conversation) = self.detached.remove(conversation_id) {
+            conversation.finish_tool_call(message_id, call_id, status, output);
+            conversation.touch();
+            if conversation.has_running_tools() {
+                self.detached.insert(conversation_id.clone(), conversation.clone());
+            }
+            self.enqueue_save(conversation, cx);
+        }
+    }
+
+    /// Asks a running tool to stop; it still reports what it produced.
+    pub fn kill_tool(&mut self, call_id: &str) {
+        if let Some(kill) = self
+            .tool_activity
+            .get_mut(call_id)
+            .and_then(|state| state.kill.take())
+        {
+            kill.send(()).ok();
+        }
+    }
+
+    // Once every call of the message has finished, sends the results back to
+    // the model
+    fn continue_after_tools(&mut self, message_id: MessageId, cx: &mut Context<Self>) {
+        let Some(message) = self.conversation.message(message_id) else {
+            return;
+        };
+        if message.tool_calls.is_empty()
+            || !message.tool_calls.iter().all(|call| call.status.is_finished())
+        {
+            return;
+        }
+        // Only when the message and its results are what's shown, not after
+        // a switch to another branch
+        let Some(ix) = self.conversation.position(message_id) else {
+            return;
+        };
+        if !self.conversation.messages()[ix + 1..]
+            .iter()
+            .all(|message| message.role == Role::Tool)
+        {
+            return;
+        }
+        self.update_find_matches(cx);
+        self.complete(cx);
+    }
+
+    pub fn get_input_text(&self, cx: &App) -> String {
+        self.chat_input.read(cx).get_text()
+    }
+
+    pub fn clear_input(&mut self, cx: &mut Context<Self>) {
+        self.chat_input.update(cx, |input, cx| {
+            input.clear(cx);
+        });
     }
 
     pub fn set_input_text(&mut self, text: String, cx: &mut Context<Self>) {
@@ -813,7 +2774,15 @@ impl InteractiveChatbox {
     }
 
     pub fn clear_messages(&mut self, cx: &mut Context<Self>) {
-        self.messages.clear();
+        self.conversation.clear();
+        for (_, mut state) in self.tool_activity.drain() {
+            if let Some(kill) = state.kill.take() {
+                kill.send(()).ok();
+            }
+        }
+        self.editing_message = None;
+        self.transcript_selection = None;
+        self.completion_error = None;
         cx.notify();
     }
 
@@ -821,19 +2790,466 @@ impl InteractiveChatbox {
         let focus_handle = self.chat_input.read(cx).focus_handle.clone();
         window.focus(&focus_handle);
     }
+
+    fn render_message(
+        &self,
+        ix: usize,
+        message: &ChatMessage,
+        index: &ConversationIndex,
+        excluded: bool,
+        window: &Window,
+        cx: &Context<Self>,
+    ) -> (impl IntoElement, Option<TextLayout>) {
+        let id = message.id;
+        let (background, border, text_color) = match message.role {
+            Role::User => (rgb(0xf1f5f9), rgb(0x94a3b8), rgb(0x0f172a)),
+            _ => (rgb(0xe3f2fd), rgb(0x2196f3), rgb(0x0d47a1)),
+        };
+        let selected = self
+            .message_selection
+            .as_ref()
+            .is_some_and(|range| range.contains(&ix));
+        let highlighted = self.highlighted_message == Some(id);
+        let editor = self
+            .editing_message
+            .as_ref()
+            .filter(|(editing_id, _)| *editing_id == id)
+            .map(|(_, input)| input.clone());
+        let editing = editor.is_some();
+        let siblings = index.siblings(message);
+        let branch_ix = siblings.iter().position(|sibling| *sibling == id).unwrap_or(0);
+        let matches: Vec<_> = self
+            .find_matches
+            .iter()
+            .enumerate()
+            .filter(|(_, found)| found.message_id == id)
+            .map(|(match_ix, found)| {
+                let background = if match_ix == self.active_match {
+                    rgb(0xfb923c)
+                } else {
+                    rgb(0xfde68a)
+                };
+                (found.range.clone(), background.into())
+            })
+            .collect();
+        let selection = self
+            .transcript_selection
+            .and_then(|selection| selection.range_in_message(ix, &message.content));
+        let text = highlighted_text(
+            message.content.clone(),
+            text_color.into(),
+            merge_selection(selection, matches, rgb(0xbfdbfe).into()),
+            window,
+        );
+        // A message being edited shows its editor, so its text isn't laid out
+        let layout = (!editing).then(|| text.layout().clone());
+
+        let element = div()
+            .bg(background)
+            .px_4()
+            .py_3()
+            .rounded_md()
+            .border_1()
+            .border_color(border)
+            .when(highlighted, |this| this.bg(rgb(0xfef9c3)))
+            .when(selected, |this| this.border_2().border_color(rgb(0xf59e0b)))
+            .when(editing, |this| this.border_2().border_color(rgb(0x7c3aed)))
+            .when(excluded, |this| this.opacity(0.5))
+            .child(
+                div()
+                    .flex()
+                    .items_start()
+                    .gap_2()
+                    .child(
+                        div()
+                            .cursor_pointer()
+                            .on_mouse_down(
+                                MouseButton::Left,
+                                cx.listener(move |this, event: &MouseDownEvent, _, cx| {
+                                    this.select_message(ix, event.modifiers.shift, cx)
+                                }),
+                            )
+                            .text_color(rgb(0x1976d2))
+                            .text_size(px(14.0))
+                            .font_weight(FontWeight::MEDIUM)
+                            .child(format!("{}:", ix + 1))
+                    )
+                    .child(match editor {
+                        Some(input) => self.render_inline_editor(input, cx).into_any_element(),
+                        None => div()
+                            .flex_1()
+                            .text_color(text_color)
+                            .text_size(px(self.settings.font_sizes.messages))
+                            .cursor(CursorStyle::IBeam)
+                            .on_mouse_down(
+                                MouseButton::Left,
+                                cx.listener(|this, event, window, cx| {
+                                    this.begin_transcript_selection(event, window, cx)
+                                }),
+                            )
+                            .child(text)
+                            .into_any_element(),
+                    })
+                    .child(
+                        div()
+                            .id(("pin-message", id.0))
+                            .cursor_pointer()
+                            .text_size(px(12.0))
+                            .when(!message.pinned, |this| this.opacity(0.3))
+                            .on_click(cx.listener(move |this, _, _, cx| this.toggle_pinned(id, cx)))
+                            .child("📌")
+                    )
+            )
+            .children(message.tool_calls.iter().map(|call| {
+                let call_id = call.id.clone();
+                let kill_call_id = call.id.clone();
+                let this = cx.entity().downgrade();
+                let kill_this = this.clone();
+                let activity = self.tool_activity.get(&call.id);
+                ToolCard::new(call.clone())
+                    .output(index.tool_result(&call.id).map(|result| result.content.clone()))
+                    .live_output(
+                        activity.map(|state| &state.output),
+                        activity.and_then(|state| state.summary.clone()),
+                    )
+                    .on_decision(move |decision, _, cx| {
+                        this.update(cx, |this, cx| {
+                            this.resolve_tool_permission(id, &call_id, decision, cx)
+                        })
+                        .ok();
+                    })
+                    .on_kill(move |_, cx| {
+                        kill_this.update(cx, |this, _| this.kill_tool(&kill_call_id)).ok();
+                    })
+            }))
+            .child(
+                div()
+                    .flex()
+                    .justify_end()
+                    .gap_3()
+                    .mt_1()
+                    .text_size(px(11.0))
+                    .text_color(rgb(0x6b7280))
+                    .when(siblings.len() > 1, |this| {
+                        this.child(
+                            div()
+                                .flex()
+                                .gap_1()
+                                .child(
+                                    div()
+                                        .id(("previous-branch", id.0))
+                                        .cursor_pointer()
+                                        .when(branch_ix == 0, |this| this.opacity(0.3))
+                                        .on_click(cx.listener(move |this, _, _, cx| {
+                                            this.switch_branch(id, false, cx)
+                                        }))
+                                        .child("‹")
+                                )
+                                .child(format!("{}/{}", branch_ix + 1, siblings.len()))
+                                .child(
+                                    div()
+                                        .id(("next-branch", id.0))
+                                        .cursor_pointer()
+                                        .when(branch_ix + 1 == siblings.len(), |this| this.opacity(0.3))
+                                        .on_click(cx.listener(move |this, _, _, cx| {
+                                            this.switch_branch(id, true, cx)
+                                        }))
+                                        .child("›")
+                                )
+                        )
+                    })
+                    .when(message.role == Role::User, |this| {
+                        this.child(
+                            div()
+                                .id(("edit-message", id.0))
+                                .cursor_pointer()
+                                .hover(|this| this.text_color(rgb(0x2563eb)))
+                                .on_click(cx.listener(move |this, _, window, cx| {
+                                    this.edit_message(id, window, cx)
+                                }))
+                                .child(if editing { "Editing…" } else { "Edit" })
+                        )
+                    })
+                    .when(self.can_regenerate(id), |this| {
+                        this.child(
+                            div()
+                                .id(("regenerate-message", id.0))
+                                .cursor_pointer()
+                                .hover(|this| this.text_color(rgb(0x2563eb)))
+                                .on_click(cx.listener(move |this, _, _, cx| this.regenerate(id, cx)))
+                                .child("Regenerate")
+                        )
+                    })
+            )
+            .when(excluded, |this| {
+                this.child(
+                    div()
+                        .mt_1()
+                        .text_color(rgb(0x6b7280))
+                        .text_size(px(11.0))
+                        .child("Excluded from context")
+                )
+            });
+
+        (element, layout)
+    }
+
+    // Enter (or cmd-enter) saves the edit, escape cancels it
+    fn render_inline_editor(
+        &self,
+        input: Entity<InteractiveChatInput>,
+        cx: &Context<Self>,
+    ) -> impl IntoElement {
+        div()
+            .flex_1()
+            .flex()
+            .flex_col()
+            .gap_2()
+            .key_context("InlineMessageEditor")
+            .on_action(cx.listener(|this, _: &Send, _, cx| this.submit_edit(cx)))
+            .on_action(cx.listener(|this, _: &CancelEdit, window, cx| {
+                this.cancel_edit(cx);
+                this.focus_input(window, cx);
+            }))
+            .child(input)
+            .child(
+                div()
+                    .flex()
+                    .justify_end()
+                    .gap_3()
+                    .text_size(px(12.0))
+                    .child(
+                        div()
+                            .id("cancel-edit")
+                            .cursor_pointer()
+                            .text_color(rgb(0x6b7280))
+                            .on_click(cx.listener(|this, _, window, cx| {
+                                this.cancel_edit(cx);
+                                this.focus_input(window, cx);
+                            }))
+                            .child("Cancel")
+                    )
+                    .child(
+                        div()
+                            .id("save-edit")
+                            .cursor_pointer()
+                            .text_color(rgb(0x2563eb))
+                            .on_click(cx.listener(|this, _, _, cx| this.submit_edit(cx)))
+                            .child("Save & regenerate")
+                    )
+            )
+    }
+
+    // Tool results are previewed in their card, so the result message itself
+    // is a single line
+    fn render_tool_result(&self, call: &ToolCall) -> impl IntoElement {
+        div()
+            .px_4()
+            .text_color(rgb(0x6b7280))
+            .text_size(px(11.0))
+            .child(format!("↳ {} result ({})", call.name, call.status.label().to_lowercase()))
+    }
+
+    fn render_model_picker(&self, cx: &Context<Self>) -> impl IntoElement {
+        let active_model = self.active_model();
+
+        div()
+            .relative()
+            .flex()
+            .flex_col()
+            .child(
+                div()
+                    .id("model-picker")
+                    .cursor_pointer()
+                    .px_2()
+                    .py_1()
+                    .rounded_md()
+                    .bg(rgb(0x374151))
+                    .text_color(rgb(0xe5e7eb))
+                    .text_size(px(13.0))
+                    .on_click(cx.listener(|this, _, _, cx| this.toggle_model_menu(cx)))
+                    .child(format!("{} ▾", active_model.display_name()))
+            )
+            .when(self.model_menu_open, |this| {
+                let active_id = active_model.id.clone();
+                this.child(deferred(
+                    anchored().snap_to_window().child(
+                        div()
+                            .occlude()
+                            .mt_1()
+                            .w(px(320.0))
+                            .flex()
+                            .flex_col()
+                            .bg(rgb(0xffffff))
+                            .border_1()
+                            .border_color(rgb(0xd1d5db))
+                            .rounded_md()
+                            .shadow_md()
+                            .children(self.model_catalog.models().iter().enumerate().map(|(ix, model)| {
+                                let id = model.id.clone();
+                                let mut details = vec![model.context_label()];
+                                details.extend(model.capabilities.labels().into_iter().map(String::from));
+                                details.extend(model.price_label());
+
+                                div()
+                                    .id(("model-option", ix))
+                                    .cursor_pointer()
+                                    .px_3()
+                                    .py_2()
+                                    .when(model.id == active_id, |this| this.bg(rgb(0xeff6ff)))
+                                    .hover(|this| this.bg(rgb(0xf3f4f6)))
+                                    .on_click(cx.listener(move |this, _, _, cx| this.select_model(&id, cx)))
+                                    .child(
+                                        div()
+                                            .flex()
+                                            .justify_between()
+                                            .child(
+                                                div()
+                                                    .text_color(rgb(0x111827))
+                                                    .text_size(px(14.0))
+                                                    .font_weight(FontWeight::MEDIUM)
+                                                    .child(model.display_name().to_string())
+                                            )
+                                            .child(
+                                                div()
+                                                    .text_color(rgb(0x6b7280))
+                                                    .text_size(px(12.0))
+                                                    .child(model.id.clone())
+                                            )
+                                    )
+                                    .child(
+                                        div()
+                                            .text_color(rgb(0x6b7280))
+                                            .text_size(px(11.0))
+                                            .child(details.join(" • "))
+                                    )
+                            })),
+                    ),
+                ))
+            })
+    }
+
+    fn render_system_prompt(&self, prompt: &str, cx: &Context<Self>) -> impl IntoElement {
+        let expanded = self.system_prompt_expanded;
+        let preview = if expanded {
+            prompt.to_string()
+        } else {
+            prompt.lines().next().unwrap_or_default().to_string()
+        };
+
+        div()
+            .id("system-prompt")
+            .cursor_pointer()
+            .px_4()
+            .py_2()
+            .rounded_md()
+            .border_1()
+            .border_color(rgb(0xd1d5db))
+            .bg(rgb(0xf3f4f6))
+            .on_click(cx.listener(|this, _, _, cx| this.toggle_system_prompt(cx)))
+            .child(
+                div()
+                    .flex()
+                    .items_start()
+                    .gap_2()
+                    .child(
+                        div()
+                            .text_color(rgb(0x6b7280))
+                            .text_size(px(12.0))
+                            .font_weight(FontWeight::MEDIUM)
+                            .child(if expanded { "▾ System" } else { "▸ System" })
+                    )
+                    .child(
+                        div()
+                            .flex_1()
+                            .text_color(rgb(0x374151))
+                            .text_size(px(13.0))
+                            .when(!expanded, |this| this.truncate())
+                            .child(preview)
+                    )
+            )
+    }
+
+    fn render_token_indicator(&self, history: &FittedHistory, cx: &Context<Self>) -> impl IntoElement {
+        let limit = self.context_budget().input_limit();
+        let used = history.token_count + estimate_tokens(&self.get_input_text(cx));
+        let over_budget = used > limit;
+
+        div()
+            .flex()
+            .items_center()
+            .justify_between()
+            .text_size(px(12.0))
+            .child(
+                div()
+                    .flex()
+                    .gap_3()
+                    .child(
+                        div()
+                            .text_color(if over_budget { rgb(0xdc2626) } else { rgb(0x6b7280) })
+                            .child(format!("{} / {} tokens", used, limit))
+                    )
+                    .when_some(self.last_usage, |this, usage| {
+                        this.child(
+                            div()
+                                .text_color(rgb(0x6b7280))
+                                .child(format!(
+                                    "Last reply: {} in · {} out",
+                                    usage.input_tokens, usage.output_tokens
+                                ))
+                        )
+                    })
+            )
+            .child(
+                div()
+                    .id("truncation-strategy")
+                    .cursor_pointer()
+                    .text_color(rgb(0x2563eb))
+                    .on_click(cx.listener(|this, _, _, cx| this.cycle_truncation_strategy(cx)))
+                    .child(format!("History: {}", self.context_budget.strategy.label()))
+            )
+    }
 }
 
 impl Render for InteractiveChatbox {
-    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+    fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let history = self.request_history();
+        let index = self.conversation.index();
+        let (messages, layouts): (Vec<_>, Vec<_>) = self
+            .conversation
+            .messages()
+            .iter()
+            .enumerate()
+            .map(|(ix, message)| {
+                if let Some(call_id) = message.tool_call_id.as_deref() {
+                    if let Some(call) = index.tool_call(call_id) {
+                        return (self.render_tool_result(call).into_any_element(), None);
+                    }
+                }
+                let excluded = history.is_excluded(message.id);
+                let (element, layout) =
+                    self.render_message(ix, message, &index, excluded, window, cx);
+                (element.into_any_element(), layout)
+            })
+            .unzip();
+        self.message_layouts = layouts;
+
         div()
             .flex()
             .flex_col()
             .size_full()
             .bg(rgb(0xf8fafc))
             .track_focus(&self.focus_handle(cx))
+            .on_action(cx.listener(Self::send))
+            .on_action(cx.listener(Self::deploy_find))
+            .on_action(cx.listener(Self::copy))
+            .on_action(cx.listener(Self::copy_as_markdown))
             .child(
                 // Header
                 div()
+                    .flex()
+                    .items_center()
+                    .justify_between()
                     .px_4()
                     .py_3()
                     .bg(rgb(0x1f2937))
@@ -844,52 +3260,119 @@ impl Render for InteractiveChatbox {
                             .font_weight(FontWeight::BOLD)
                             .child("✅ Interactive Chatbox - Complete Text Input")
                     )
+                    .child(
+                        div()
+                            .flex()
+                            .items_center()
+                            .gap_2()
+                            .child(self.render_model_picker(cx))
+                            .child(
+                                div()
+                                    .id("search-conversations")
+                                    .cursor_pointer()
+                                    .px_2()
+                                    .py_1()
+                                    .rounded_md()
+                                    .bg(rgb(0x374151))
+                                    .text_color(rgb(0xe5e7eb))
+                                    .text_size(px(13.0))
+                                    .on_click(cx.listener(|this, _, window, cx| {
+                                        this.toggle_search_panel(window, cx)
+                                    }))
+                                    .child("🔍")
+                            )
+                            .child(
+                                div()
+                                    .id("conversation-settings")
+                                    .cursor_pointer()
+                                    .px_2()
+                                    .py_1()
+                                    .rounded_md()
+                                    .bg(rgb(0x374151))
+                                    .text_color(rgb(0xe5e7eb))
+                                    .text_size(px(13.0))
+                                    .on_click(cx.listener(|this, _, window, cx| {
+                                        this.toggle_settings_panel(window, cx)
+                                    }))
+                                    .child("⚙")
+                            )
+                            .child(
+                                div()
+                                    .id("assistant-settings")
+                                    .cursor_pointer()
+                                    .px_2()
+                                    .py_1()
+                                    .rounded_md()
+                                    .bg(rgb(0x374151))
+                                    .text_color(rgb(0xe5e7eb))
+                                    .text_size(px(13.0))
+                                    .on_click(cx.listener(|this, _, window, cx| {
+                                        this.toggle_assistant_settings(window, cx)
+                                    }))
+                                    .child("Settings")
+                            )
+                    )
             )
+            .when_some(self.assistant_settings_view.as_ref(), |this, (view, _)| {
+                this.child(view.clone())
+            })
+            .when_some(self.settings_panel.as_ref(), |this, (panel, _)| {
+                this.child(panel.clone())
+            })
+            .when_some(self.search_panel.as_ref(), |this, (panel, _)| {
+                this.child(panel.clone())
+            })
+            .when_some(self.find_bar.as_ref(), |this, (find_bar, _)| {
+                this.child(find_bar.clone())
+            })
             .child(
                 // Messages area
                 div()
+                    .id("messages")
                     .flex_1()
                     .flex()
                     .flex_col()
                     .p_4()
                     .gap_2()
-                    .child(
-                        div()
-                            .flex()
-                            .flex_col()
-                            .gap_2()
-                            .children(
-                                self.messages.iter().enumerate().map(|(i, message)| {
-                                    div()
-                                        .bg(rgb(0xe3f2fd))
-                                        .px_4()
-                                        .py_3()
-                                        .rounded_md()
-                                        .border_1()
-                                        .border_color(rgb(0x2196f3))
-                                        .child(
-                                            div()
-                                                .flex()
-                                                .items_start()
-                                                .gap_2()
-                                                .child(
-                                                    div()
-                                                        .text_color(rgb(0x1976d2))
-                                                        .text_size(px(14.0))
-                                                        .font_weight(FontWeight::MEDIUM)
-                                                        .child(format!("{}:", i + 1))
-                                                )
-                                                .child(
-                                                    div()
-                                                        .flex_1()
-                                                        .text_color(rgb(0x0d47a1))
-                                                        .text_size(px(15.0))
-                                                        .child(message.clone())
-                                                )
-                                        )
-                                })
-                            )
+                    .overflow_y_scroll()
+                    .track_scroll(&self.scroll_handle)
+                    .on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(|this, _, _, cx| {
+                            if this.transcript_selection.take().is_some() {
+                                cx.notify();
+                            }
+                        }),
+                    )
+                    .on_mouse_move(cx.listener(|this, event, _, cx| {
+                        this.extend_transcript_selection(event, cx)
+                    }))
+                    .on_mouse_up(
+                        MouseButton::Left,
+                        cx.listener(|this, _, _, cx| this.end_transcript_selection(cx)),
                     )
+                    .on_mouse_up_out(
+                        MouseButton::Left,
+                        cx.listener(|this, _, _, cx| this.end_transcript_selection(cx)),
+                    )
+                    .when_some(self.conversation.settings().system_prompt(), |this, prompt| {
+                        this.child(self.render_system_prompt(prompt, cx))
+                    })
+                    .children(messages)
+                    .when_some(self.completion_error.clone(), |this, error| {
+                        this.child(
+                            div()
+                                .px_4()
+                                .py_2()
+                                .rounded_md()
+                                .border_1()
+                                .border_color(rgb(0xfca5a5))
+                                .bg(rgb(0xfef2f2))
+                                .text_color(rgb(0xb91c1c))
+                                .text_size(px(13.0))
+                                .child(format!("⚠️ {error}"))
+                        )
+                    })
             )
             .child(
                 // Input area
@@ -916,6 +3399,7 @@ impl Render for InteractiveChatbox {
                                     .child("💡 Full-featured text input with blinking cursor!")
                             )
                             .child(self.chat_input.clone())
+                            .child(self.render_token_indicator(&history, cx))
                             .child(
                                 div()
                                     .text_color(rgb(0x6b7280))
@@ -959,74 +3443,183 @@ impl Render for InteractiveChatbox {
                             )
                             .child(
                                 div()
-                                    .text_color(rgb(0x6b7280))
+                                    .flex()
+                                    .items_center()
+                                    .gap_2()
                                     .text_size(px(11.0))
-                                    .child(format!("{} messages", self.messages.len()))
+                                    .when_some(self.status.clone(), |this, status| {
+                                        this.child(div().text_color(rgb(0x374151)).child(status))
+                                    })
+                                    .when(self.transcript_selection.is_some(), |this| {
+                                        this.child(
+                                            div()
+                                                .id("copy-selection")
+                                                .cursor_pointer()
+                                                .text_color(rgb(0x2563eb))
+                                                .on_click(cx.listener(|this, _, _, cx| {
+                                                    this.copy_transcript_selection(false, cx)
+                                                }))
+                                                .child("Copy")
+                                        )
+                                        .child(
+                                            div()
+                                                .id("copy-selection-markdown")
+                                                .cursor_pointer()
+                                                .text_color(rgb(0x2563eb))
+                                                .on_click(cx.listener(|this, _, _, cx| {
+                                                    this.copy_transcript_selection(true, cx)
+                                                }))
+                                                .child("Copy as Markdown")
+                                        )
+                                    })
+                                    .child(
+                                        div()
+                                            .text_color(rgb(0x6b7280))
+                                            .child(match &self.message_selection {
+                                                Some(range) => format!("Export {} of {} messages:", range.len(), self.conversation.len()),
+                                                None => format!("{} messages · Export:", self.conversation.len()),
+                                            })
+                                    )
+                                    .children(ExportFormat::ALL.into_iter().map(|format| {
+                                        div()
+                                            .id(format.label())
+                                            .cursor_pointer()
+                                            .text_color(rgb(0x2563eb))
+                                            .on_click(cx.listener(move |this, _, _, cx| this.export(format, cx)))
+                                            .child(format.label())
+                                    }))
                             )
                     )
             )
     }
 }
 
+// Adds the selection highlight around find matches, which keep their own
+// colors where the two overlap
+fn merge_selection(
+    selection: Option<Range<usize>>,
+    matches: Vec<(Range<usize>, Hsla)>,
+    selection_color: Hsla,
+) -> Vec<(Range<usize>, Hsla)> {
+    let Some(selection) = selection else {
+        return matches;
+    };
+    let mut highlights = Vec::new();
+    let mut offset = selection.start;
+    for (range, color) in matches {
+        if range.start > offset && offset < selection.end {
+            highlights.push((offset..range.start.min(selection.end), selection_color));
+        }
+        offset = offset.max(range.end);
+        highlights.push((range, color));
+    }
+    if offset < selection.end {
+        highlights.push((offset..selection.end, selection_color));
+    }
+    highlights.sort_by_key(|(range, _)| range.start);
+    highlights
+}
+
 impl Focusable for InteractiveChatbox {
     fn focus_handle(&self, _: &App) -> FocusHandle {
         self.focus_handle.clone()
     }
 }
 
+/// Binds the editing keys of `InteractiveChatInput`, for apps that embed it
+/// without the rest of the chatbox.
+pub fn bind_input_keys(cx: &mut App) {
+    cx.bind_keys([
+        KeyBinding::new("backspace", Backspace, None),
+        KeyBinding::new("delete", Delete, None),
+        KeyBinding::new("left", Left, None),
+        KeyBinding::new("right", Right, None),
+        KeyBinding::new("shift-left", SelectLeft, None),
+        KeyBinding::new("shift-right", SelectRight, None),
+        KeyBinding::new("cmd-a", SelectAll, None),
+        KeyBinding::new("cmd-v", Paste, None),
+        KeyBinding::new("cmd-c", Copy, None),
+        KeyBinding::new("cmd-shift-c", CopyAsMarkdown, None),
+        KeyBinding::new("cmd-x", Cut, None),
+        KeyBinding::new("home", Home, None),
+        KeyBinding::new("end", End, None),
+        KeyBinding::new("ctrl-cmd-space", ShowCharacterPalette, None),
+        KeyBinding::new("enter", Send, Some("InteractiveChatInput && !send_with_cmd_enter")),
+        KeyBinding::new("cmd-enter", Send, Some("InteractiveChatInput")),
+        #[cfg(not(target_os = "macos"))]
+        KeyBinding::new("ctrl-enter", Send, Some("InteractiveChatInput")),
+        KeyBinding::new(
+            "enter",
+            Newline,
+            Some("InteractiveChatInput && multiline && send_with_cmd_enter"),
+        ),
+        KeyBinding::new(
+            "shift-enter",
+            Newline,
+            Some("InteractiveChatInput && multiline && !send_with_cmd_enter"),
+        ),
+    ]);
+}
+
 // Function to set up key bindings and launch the chatbox
 pub fn launch_interactive_chatbox() {
-    Application::new().run(|cx: &mut App| {
-        cx.activate(true);
-
-        // Set up key bindings for text input
-        cx.bind_keys([
-            KeyBinding::new("backspace", Backspace, None),
-            KeyBinding::new("delete", Delete, None),
-            KeyBinding::new("left", Left, None),
-            KeyBinding::new("right", Right, None),
-            KeyBinding::new("shift-left", SelectLeft, None),
-            KeyBinding::new("shift-right", SelectRight, None),
-            KeyBinding::new("cmd-a", SelectAll, None),
-            KeyBinding::new("cmd-v", Paste, None),
-            KeyBinding::new("cmd-c", Copy, None),
-            KeyBinding::new("cmd-x", Cut, None),
-            KeyBinding::new("home", Home, None),
-            KeyBinding::new("end", End, None),
-            KeyBinding::new("ctrl-cmd-space", ShowCharacterPalette, None),
-        ]);
-
-        match cx.open_window(
-            WindowOptions {
-                titlebar: Some(gpui::TitlebarOptions {
-                    title: Some("Interactive Chatbox - Complete Text Input".into()),
-                    appears_transparent: false,
-                    traffic_light_position: Some(point(px(12.0), px(20.0))),
-                }),
-                window_bounds: Some(WindowBounds::Windowed(Bounds {
-                    origin: point(px(400.0), px(200.0)),
-                    size: size(px(700.0), px(650.0)),
-                })),
-                focus: true,
-                show: true,
-                kind: WindowKind::Normal,
-                is_movable: true,
-                is_resizable: true,
-                window_min_size: Some(size(px(500.0), px(400.0))),
-                is_minimizable: true,
-                window_background: WindowBackgroundAppearance::Transparent,
-                app_id: None,
-                display_id: None,
-                tabbing_identifier: None,
-                window_decorations: Some(WindowDecorations::Server),
-            },
-            |_window, cx| {
-                let chatbox = cx.new(|cx| InteractiveChatbox::new(cx));
-                chatbox.into()
-            },
-        ) {
-            Ok(_) => println!("✅✅✅ INTERACTIVE CHATBOX WITH BLINKING CURSOR LAUNCHED! ✅✅✅"),
-            Err(e) => eprintln!("❌ Failed to open window: {:?}", e),
-        }
-    });
+    let http_client = reqwest_client::ReqwestClient::user_agent("agent-ui")
+        .expect("failed to create HTTP client");
+
+    Application::new()
+        .with_http_client(Arc::new(http_client))
+        .run(|cx: &mut App| {
+            cx.activate(true);
+
+            bind_input_keys(cx);
+            cx.bind_keys([
+                KeyBinding::new("escape", CancelEdit, Some("InlineMessageEditor")),
+                KeyBinding::new("down", search_panel::SelectNextResult, Some("ConversationSearchPanel")),
+                KeyBinding::new("up", search_panel::SelectPreviousResult, Some("ConversationSearchPanel")),
+                KeyBinding::new("cmd-f", find_bar::Deploy, None),
+                KeyBinding::new("cmd-g", find_bar::SelectNextMatch, Some("FindBar")),
+                KeyBinding::new("cmd-shift-g", find_bar::SelectPreviousMatch, Some("FindBar")),
+                KeyBinding::new("ctrl-f", find_bar::Deploy, None),
+                KeyBinding::new("ctrl-g", find_bar::SelectNextMatch, Some("FindBar")),
+                KeyBinding::new("ctrl-shift-g", find_bar::SelectPreviousMatch, Some("FindBar")),
+                KeyBinding::new("shift-enter", find_bar::SelectPreviousMatch, Some("FindBar")),
+                KeyBinding::new("escape", find_bar::Dismiss, Some("FindBar")),
+                KeyBinding::new("alt-cmd-c", find_bar::ToggleCaseSensitive, Some("FindBar")),
+                KeyBinding::new("alt-cmd-w", find_bar::ToggleWholeWord, Some("FindBar")),
+                KeyBinding::new("alt-cmd-x", find_bar::ToggleRegex, Some("FindBar")),
+            ]);
+
+            match cx.open_window(
+                WindowOptions {
+                    titlebar: Some(gpui::TitlebarOptions {
+                        title: Some("Interactive Chatbox - Complete Text Input".into()),
+                        appears_transparent: false,
+                        traffic_light_position: Some(point(px(12.0), px(20.0))),
+                    }),
+                    window_bounds: Some(WindowBounds::Windowed(Bounds {
+                        origin: point(px(400.0), px(200.0)),
+                        size: size(px(700.0), px(650.0)),
+                    })),
+                    focus: true,
+                    show: true,
+                    kind: WindowKind::Normal,
+                    is_movable: true,
+                    is_resizable: true,
+                    window_min_size: Some(size(px(500.0), px(400.0))),
+                    is_minimizable: true,
+                    window_background: WindowBackgroundAppearance::Transparent,
+                    app_id: None,
+                    display_id: None,
+                    tabbing_identifier: None,
+                    window_decorations: Some(WindowDecorations::Server),
+                },
+                |_window, cx| {
+                    let chatbox = cx.new(|cx| InteractiveChatbox::new(cx));
+                    chatbox.into()
+                },
+            ) {
+                Ok(_) => println!("✅✅✅ INTERACTIVE CHATBOX WITH BLINKING CURSOR LAUNCHED! ✅✅✅"),
+                Err(e) => eprintln!("❌ Failed to open window: {:?}", e),
+            }
+        });
 }
\ No newline at end of file
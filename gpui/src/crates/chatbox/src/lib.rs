@@ -2,11 +2,74 @@ pub mod message_editor;
 pub mod copilot_chat;
 pub mod chat_view;
 pub mod interactive_chatbox;
+pub mod conversation;
+pub mod conversation_store;
+pub mod conversation_settings_panel;
+pub mod assistant_settings;
+pub mod assistant_settings_view;
+pub mod export;
+pub mod search;
+pub mod search_panel;
+pub mod find;
+pub mod find_bar;
+pub mod transcript_selection;
+pub mod tool_permissions;
+pub mod tool_card;
+pub mod tools;
+pub mod workspace_tools;
+pub mod command_tool;
+pub mod ansi;
+pub mod mcp;
+pub mod mcp_tool;
+pub mod context_budget;
+pub mod model_catalog;
+pub mod backend;
+pub mod sse;
+pub mod cassette;
+pub mod openai;
+pub mod anthropic;
+pub mod ollama;
 
 pub use message_editor::MessageEditor;
 pub use copilot_chat::CopilotChat;
 pub use chat_view::ChatView;
-pub use interactive_chatbox::{InteractiveChatbox, InteractiveChatInput};
+pub use interactive_chatbox::{ChatInputEvent, InteractiveChatbox, InteractiveChatInput};
+pub use conversation::{
+    Attachment, ChatMessage, ChipPayload, Conversation, ConversationId, ConversationIndex,
+    ConversationSettings, MessageChip, MessageId, RequestMessage, Role, ToolCall, ToolStatus,
+};
+pub use conversation_store::ConversationStore;
+pub use conversation_settings_panel::{ConversationSettingsEvent, ConversationSettingsPanel};
+pub use assistant_settings::{
+    AssistantSettings, AssistantSettingsContent, FontSizes, OpenAiOptions, ProviderEndpoints,
+    SendKeybinding, SettingsFile, ToolApproval,
+};
+pub use assistant_settings_view::{AssistantSettingsEvent, AssistantSettingsView};
+pub use export::{export_conversation, ExportFormat};
+pub use search::{MatchSource, SearchIndex, SearchResult};
+pub use search_panel::{ConversationSearchPanel, SearchPanelEvent};
+pub use find::{FindMatch, FindOptions, FindQuery};
+pub use find_bar::{FindBar, FindBarEvent};
+pub use transcript_selection::{TranscriptPoint, TranscriptSelection};
+pub use tool_permissions::{PermissionDecision, ToolPolicy, ToolRule};
+pub use tool_card::ToolCard;
+pub use tools::{Tool, ToolActivity, ToolActivityEvent, ToolDefinition, ToolRegistry};
+pub use workspace_tools::register_workspace_tools;
+pub use command_tool::RunCommandTool;
+pub use ansi::{parse_ansi, strip_ansi, AnsiOutput, AnsiStyle, AnsiText};
+pub use mcp::{McpClient, McpConfig, McpResourceInfo, McpServerConfig, McpToolInfo};
+pub use mcp_tool::{register_mcp_tools, McpRegistration, McpTool};
+pub use context_budget::{ContextBudget, FittedHistory, TruncationStrategy};
+pub use model_catalog::{ModelCapabilities, ModelCatalog, ModelInfo};
+pub use backend::{
+    sse_completion_events, AgentBackend, ChatRequest, CodeAgentBackend, CompletionEvent,
+    ProviderRouter, SseEventMapper, StopReason, TokenUsage,
+};
+pub use sse::{sse_events, SseDecoder, SseEvent};
+pub use cassette::{Cassette, RecordingBackend, ReplayBackend};
+pub use openai::{OpenAiBackend, OpenAiSettings};
+pub use anthropic::{AnthropicBackend, AnthropicSettings};
+pub use ollama::{OllamaBackend, OllamaSettings};
 
 use gpui::{Context, Entity, Render, Window};
 use ui::prelude::*;
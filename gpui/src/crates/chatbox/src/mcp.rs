@@ -0,0 +1,370 @@
+// Model Context Protocol client
+// Launches MCP servers as child processes and talks newline-delimited
+// JSON-RPC 2.0 with them over stdio: the initialize handshake, tools/list,
+// resources/list and tools/call.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context as _, Result};
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use futures::io::BufReader;
+use futures::{AsyncBufReadExt, AsyncWriteExt, StreamExt};
+use gpui::{BackgroundExecutor, Task};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use smol::process::{Child, ChildStdin, ChildStdout, Command};
+
+use crate::tool_permissions::user_config_dir;
+
+pub const MCP_CONFIG_ENV_VAR: &str = "CODE_AGENT_MCP_CONFIG";
+const PROTOCOL_VERSION: &str = "2024-11-05";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct McpConfig {
+    #[serde(default, rename = "mcpServers", alias = "servers")]
+    pub servers: BTreeMap<String, McpServerConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct McpServerConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+impl McpConfig {
+    /// Uses `CODE_AGENT_MCP_CONFIG` if set, otherwise `mcp.json` in the user
+    /// config dir. Servers are never read from a workspace, since opening a
+    /// checkout must not start programs it names.
+    pub fn path_from_env() -> PathBuf {
+        std::env::var_os(MCP_CONFIG_ENV_VAR)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| user_config_dir().join("mcp.json"))
+    }
+
+    /// Loads the configured servers. A missing file configures none.
+    pub fn load() -> Result<Self> {
+        let path = Self::path_from_env();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json =
+            std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("parsing {}", path.display()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpToolInfo {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default = "empty_object_schema")]
+    pub input_schema: Value,
+}
+
+fn empty_object_schema() -> Value {
+    json!({ "type": "object" })
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResourceInfo {
+    pub uri: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+}
+
+type PendingRequests = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>>;
+
+pub struct McpClient {
+    name: String,
+    capabilities: Value,
+    tools: Vec<McpToolInfo>,
+    resources: Vec<McpResourceInfo>,
+    stdin: Arc<futures::lock::Mutex<ChildStdin>>,
+    pending: PendingRequests,
+    closed: Arc<AtomicBool>,
+    next_id: AtomicU64,
+    executor: BackgroundExecutor,
+    _child: Child,
+    _reader: Task<()>,
+}
+
+impl McpClient {
+    /// Launches the server, performs the initialize handshake and fetches
+    /// its tools and resources.
+    pub async fn start(
+        name: String,
+        config: &McpServerConfig,
+        executor: BackgroundExecutor,
+    ) -> Result<Self> {
+        let mut command = Command::new(&config.command);
+        command
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+        if let Some(cwd) = &config.cwd {
+            command.current_dir(cwd);
+        }
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("starting MCP server {name} ({})", config.command))?;
+        let stdin = Arc::new(futures::lock::Mutex::new(
+            child.stdin.take().context("MCP server has no stdin")?,
+        ));
+        let stdout = child.stdout.take().context("MCP server has no stdout")?;
+
+        let pending = PendingRequests::default();
+        let closed = Arc::new(AtomicBool::new(false));
+        let reader = executor.spawn(read_messages(
+            name.clone(),
+            stdout,
+            stdin.clone(),
+            pending.clone(),
+            closed.clone(),
+        ));
+
+        let mut client = Self {
+            name,
+            capabilities: Value::Null,
+            tools: Vec::new(),
+            resources: Vec::new(),
+            stdin,
+            pending,
+            closed,
+            next_id: AtomicU64::new(1),
+            executor,
+            _child: child,
+            _reader: reader,
+        };
+
+        let initialized = client
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": { "name": "agent-ui", "version": env!("CARGO_PKG_VERSION") },
+                }),
+            )
+            .await
+            .with_context(|| format!("initializing MCP server {}", client.name))?;
+        client.capabilities = initialized.get("capabilities").cloned().unwrap_or_default();
+        client.notify("notifications/initialized", json!({})).await?;
+
+        client.tools = client.list_tools().await?;
+        client.resources = client.list_resources().await?;
+        Ok(client)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn tools(&self) -> &[McpToolInfo] {
+        &self.tools
+    }
+
+    pub fn resources(&self) -> &[McpResourceInfo] {
+        &self.resources
+    }
+
+    fn supports(&self, capability: &str) -> bool {
+        self.capabilities.get(capability).is_some()
+    }
+
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(anyhow!("MCP server {} has exited", self.name));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(error) = write_message(&self.stdin, &message).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(error);
+        }
+
+        match future::select(receiver, self.executor.timer(REQUEST_TIMEOUT)).await {
+            Either::Left((result, _)) => {
+                result.map_err(|_| anyhow!("MCP server {} has exited", self.name))?
+            }
+            Either::Right(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(anyhow!("MCP server {} timed out on {method}", self.name))
+            }
+        }
+    }
+
+    pub async fn notify(&self, method: &str, params: Value) -> Result<()> {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        write_message(&self.stdin, &message).await
+    }
+
+    async fn list_tools(&self) -> Result<Vec<McpToolInfo>> {
+        if !self.supports("tools") {
+            return Ok(Vec::new());
+        }
+        self.list_paginated("tools/list", "tools").await
+    }
+
+    async fn list_resources(&self) -> Result<Vec<McpResourceInfo>> {
+        if !self.supports("resources") {
+            return Ok(Vec::new());
+        }
+        self.list_paginated("resources/list", "resources").await
+    }
+
+    async fn list_paginated<T: for<'de> Deserialize<'de>>(
+        &self,
+        method: &str,
+        key: &str,
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let mut result = self.request(method, params).await?;
+            let page = result.get_mut(key).map(Value::take).unwrap_or_default();
+            let page: Vec<T> = serde_json::from_value(page)
+                .with_context(|| format!("parsing {method} response"))?;
+            items.extend(page);
+            cursor = result
+                .get("nextCursor")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            if cursor.is_none() {
+                return Ok(items);
+            }
+        }
+    }
+
+    /// Calls a tool and returns its text content. Results flagged with
+    /// `isError` become errors.
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<String> {
+        let result = self
+            .request("tools/call", json!({ "name": name, "arguments": arguments }))
+            .await?;
+        let text = result
+            .get("content")
+            .and_then(Value::as_array)
+            .map(|content| {
+                content
+                    .iter()
+                    .map(|item| match item.get("type").and_then(Value::as_str) {
+                        Some("text") => item
+                            .get("text")
+                            .and_then(Value::as_str)
+                            .unwrap_or_default()
+                            .to_string(),
+                        Some(kind) => format!("[{kind} content]"),
+                        None => item.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+        if result.get("isError").and_then(Value::as_bool) == Some(true) {
+            return Err(anyhow!("{text}"));
+        }
+        Ok(text)
+    }
+}
+
+async fn write_message(stdin: &futures::lock::Mutex<ChildStdin>, message: &Value) -> Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    let mut stdin = stdin.lock().await;
+    stdin.write_all(line.as_bytes()).await?;
+    stdin.flush().await?;
+    Ok(())
+}
+
+// Dispatches responses to waiting requests until the server closes stdout
+async fn read_messages(
+    server: String,
+    stdout: ChildStdout,
+    stdin: Arc<futures::lock::Mutex<ChildStdin>>,
+    pending: PendingRequests,
+    closed: Arc<AtomicBool>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(Ok(line)) = lines.next().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(error) => {
+                log::warn!("MCP server {server} sent invalid JSON: {error}");
+                continue;
+            }
+        };
+
+        // Requests from the server; only ping is supported
+        if let Some(method) = message.get("method").and_then(Value::as_str) {
+            if let Some(id) = message.get("id") {
+                let response = if method == "ping" {
+                    json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                } else {
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": format!("method not found: {method}") },
+                    })
+                };
+                write_message(&stdin, &response).await.ok();
+            }
+            continue;
+        }
+
+        let Some(id) = message.get("id").and_then(Value::as_u64) else {
+            continue;
+        };
+        let Some(sender) = pending.lock().unwrap().remove(&id) else {
+            continue;
+        };
+        let result = match message.get("error") {
+            Some(error) => Err(anyhow!(
+                "{}",
+                error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown error")
+            )),
+            None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+        };
+        sender.send(result).ok();
+    }
+
+    closed.store(true, Ordering::SeqCst);
+    for (_, sender) in pending.lock().unwrap().drain() {
+        sender.send(Err(anyhow!("MCP server {server} has exited"))).ok();
+    }
+}
@@ -0,0 +1,89 @@
+// MCP tools
+// Exposes the tools of a connected MCP server through the tool registry.
+// Calls are routed back to the server the tool came from.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::{future::BoxFuture, FutureExt};
+use gpui::{AsyncApp, SharedString};
+
+use crate::mcp::{McpClient, McpToolInfo};
+use crate::tools::{Tool, ToolActivity, ToolRegistry};
+
+pub struct McpTool {
+    client: Arc<McpClient>,
+    info: McpToolInfo,
+    // Registered name; differs from `info.name` when it collides with
+    // another tool
+    name: SharedString,
+}
+
+impl Tool for McpTool {
+    fn name(&self) -> SharedString {
+        self.name.clone()
+    }
+
+    fn description(&self) -> SharedString {
+        self.info
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("Tool from the {} MCP server", self.client.name()))
+            .into()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        self.info.input_schema.clone()
+    }
+
+    // Servers describe their own tools, so hints like `readOnlyHint` are no
+    // reason to skip asking
+    fn needs_approval(&self) -> bool {
+        true
+    }
+
+    fn run(
+        &self,
+        arguments: serde_json::Value,
+        _activity: ToolActivity,
+        _cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>> {
+        let client = self.client.clone();
+        let name = self.info.name.clone();
+        async move { client.call_tool(&name, arguments).await }.boxed()
+    }
+}
+
+/// What `register_mcp_tools` did with a server's tools.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct McpRegistration {
+    pub registered: usize,
+    /// Tools left out because both their name and `<server>__<tool>` were
+    /// already taken.
+    pub skipped: Vec<String>,
+}
+
+/// Registers the tools of `client`. A tool whose name is already taken is
+/// registered as `<server>__<tool>`; if that is taken too, the tool is
+/// skipped rather than replacing another one.
+pub fn register_mcp_tools(registry: &mut ToolRegistry, client: &Arc<McpClient>) -> McpRegistration {
+    let mut registration = McpRegistration::default();
+    for info in client.tools() {
+        let fallback = format!("{}__{}", client.name(), info.name);
+        let name: SharedString = if registry.tool(&info.name).is_none() {
+            info.name.clone().into()
+        } else if registry.tool(&fallback).is_none() {
+            fallback.into()
+        } else {
+            registration.skipped.push(info.name.clone());
+            continue;
+        };
+        registry.register(Arc::new(McpTool {
+            client: client.clone(),
+            info: info.clone(),
+            name,
+        }));
+        registration.registered += 1;
+    }
+    registration
+}
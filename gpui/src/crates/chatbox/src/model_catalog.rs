@@ -0,0 +1,232 @@
+// Model catalog
+// Models are identified as `provider/model` (e.g. `openai/gpt-4`), matching
+// the identifiers used by the backend and the mac client.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Context as _, Result};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_MODEL: &str = "openai/gpt-4";
+// Environment variables shared with the mac client
+pub const MODEL_ENV_VAR: &str = "CODE_AGENT_MODEL";
+pub const CATALOG_ENV_VAR: &str = "CODE_AGENT_MODEL_CATALOG";
+
+/// Splits a `provider/model` identifier into its two halves.
+pub fn parse_model_id(id: &str) -> Result<(&str, &str)> {
+    match id.split_once('/') {
+        Some((provider, model)) if !provider.is_empty() && !model.is_empty() => {
+            Ok((provider, model))
+        }
+        _ => Err(anyhow!("invalid model id {id:?}, expected `provider/model`")),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelCapabilities {
+    pub tools: bool,
+    pub vision: bool,
+    pub streaming: bool,
+}
+
+impl ModelCapabilities {
+    pub fn labels(&self) -> Vec<&'static str> {
+        let mut labels = Vec::new();
+        if self.tools {
+            labels.push("tools");
+        }
+        if self.vision {
+            labels.push("vision");
+        }
+        if self.streaming {
+            labels.push("streaming");
+        }
+        labels
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    pub context_length: usize,
+    #[serde(default)]
+    pub capabilities: ModelCapabilities,
+    /// USD per million input tokens.
+    #[serde(default)]
+    pub input_price: Option<f64>,
+    /// USD per million output tokens.
+    #[serde(default)]
+    pub output_price: Option<f64>,
+}
+
+impl ModelInfo {
+    pub const DEFAULT_CONTEXT_LENGTH: usize = 8_192;
+
+    fn builtin(
+        id: &str,
+        display_name: &str,
+        context_length: usize,
+        capabilities: ModelCapabilities,
+        prices: (f64, f64),
+    ) -> Self {
+        Self {
+            id: id.into(),
+            display_name: Some(display_name.into()),
+            context_length,
+            capabilities,
+            input_price: Some(prices.0),
+            output_price: Some(prices.1),
+        }
+    }
+
+    pub fn provider(&self) -> &str {
+        parse_model_id(&self.id).map_or("", |(provider, _)| provider)
+    }
+
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.id)
+    }
+
+    pub fn context_label(&self) -> String {
+        if self.context_length >= 1_000 {
+            format!("{}k ctx", self.context_length / 1_000)
+        } else {
+            format!("{} ctx", self.context_length)
+        }
+    }
+
+    pub fn price_label(&self) -> Option<String> {
+        match (self.input_price, self.output_price) {
+            (Some(input), Some(output)) => Some(format!("${input:.2} / ${output:.2} per 1M")),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModelCatalog {
+    default_model: String,
+    models: Vec<ModelInfo>,
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        let chat = ModelCapabilities {
+            tools: true,
+            vision: false,
+            streaming: true,
+        };
+        let multimodal = ModelCapabilities {
+            vision: true,
+            ..chat
+        };
+
+        Self {
+            default_model: DEFAULT_MODEL.into(),
+            models: vec![
+                ModelInfo::builtin("openai/gpt-4", "GPT-4", 8_192, chat, (30.0, 60.0)),
+                ModelInfo::builtin("openai/gpt-4o", "GPT-4o", 128_000, multimodal, (2.5, 10.0)),
+                ModelInfo::builtin("openai/gpt-4o-mini", "GPT-4o mini", 128_000, multimodal, (0.15, 0.6)),
+                ModelInfo::builtin("anthropic/claude-3-opus", "Claude 3 Opus", 200_000, multimodal, (15.0, 75.0)),
+                ModelInfo::builtin("anthropic/claude-3-5-sonnet", "Claude 3.5 Sonnet", 200_000, multimodal, (3.0, 15.0)),
+                ModelInfo::builtin("anthropic/claude-3-haiku", "Claude 3 Haiku", 200_000, multimodal, (0.25, 1.25)),
+            ],
+        }
+    }
+}
+
+impl ModelCatalog {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let catalog: Self = serde_json::from_str(json)?;
+        for model in &catalog.models {
+            parse_model_id(&model.id)?;
+        }
+        if catalog.model(&catalog.default_model).is_none() {
+            return Err(anyhow!(
+                "default model {:?} is not in the catalog",
+                catalog.default_model
+            ));
+        }
+        Ok(catalog)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("reading model catalog {}", path.display()))?;
+        Self::from_json(&json).with_context(|| format!("parsing model catalog {}", path.display()))
+    }
+
+    /// Loads the catalog named by `CODE_AGENT_MODEL_CATALOG`, falling back to
+    /// the built-in models. `CODE_AGENT_MODEL` overrides the default model.
+    pub fn from_env() -> Self {
+        let mut catalog = std::env::var(CATALOG_ENV_VAR)
+            .ok()
+            .and_then(|path| match Self::load(Path::new(&path)) {
+                Ok(catalog) => Some(catalog),
+                Err(error) => {
+                    log::error!("Failed to load model catalog: {error:#}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        if let Ok(model) = std::env::var(MODEL_ENV_VAR) {
+            if catalog.add_model(&model).is_some() {
+                catalog.default_model = model;
+            }
+        }
+        catalog
+    }
+
+    /// Adds a model we know nothing about beyond its id, unless the catalog
+    /// already has it. Returns `None` for an invalid id.
+    pub fn add_model(&mut self, id: &str) -> Option<&ModelInfo> {
+        parse_model_id(id).ok()?;
+        if self.model(id).is_none() {
+            self.models.push(ModelInfo {
+                id: id.to_string(),
+                display_name: None,
+                context_length: ModelInfo::DEFAULT_CONTEXT_LENGTH,
+                capabilities: ModelCapabilities::default(),
+                input_price: None,
+                output_price: None,
+            });
+        }
+        self.model(id)
+    }
+
+    /// Makes `id` the default, adding it if needed. Returns `false` for an
+    /// invalid id.
+    pub fn set_default_model(&mut self, id: &str) -> bool {
+        if self.add_model(id).is_none() {
+            return false;
+        }
+        self.default_model = id.to_string();
+        true
+    }
+
+    /// Adds a model, replacing any entry with the same id.
+    pub fn insert_model(&mut self, model: ModelInfo) {
+        match self.models.iter_mut().find(|existing| existing.id == model.id) {
+            Some(existing) => *existing = model,
+            None => self.models.push(model),
+        }
+    }
+
+    pub fn models(&self) -> &[ModelInfo] {
+        &self.models
+    }
+
+    pub fn model(&self, id: &str) -> Option<&ModelInfo> {
+        self.models.iter().find(|model| model.id == id)
+    }
+
+    pub fn default_model(&self) -> &ModelInfo {
+        self.model(&self.default_model)
+            .or_else(|| self.models.first())
+            .expect("model catalog is never empty")
+    }
+}
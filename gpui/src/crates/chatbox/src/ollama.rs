@@ -0,0 +1,481 @@
+// Ollama provider
+// Speaks Ollama's native `/api/chat`, which streams one JSON object per line,
+// so conversations never leave the machine. Installed models are listed from
+// `/api/tags` for the model picker. Models without tool support reject
+// requests that carry tools, so tools are only sent to models whose
+// `/api/show` lists the capability.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context as _, Result};
+use futures::io::BufReader;
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    AsyncBufReadExt, AsyncReadExt, FutureExt, StreamExt,
+};
+use gpui::{AsyncApp, SharedString};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest, Response};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::backend::{AgentBackend, ChatRequest, CompletionEvent, StopReason, TokenUsage};
+use crate::conversation::{RequestMessage, Role, ToolCall};
+use crate::model_catalog::{parse_model_id, ModelCapabilities, ModelInfo};
+
+pub const PROVIDER_ID: &str = "ollama";
+pub const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+// Ollama's own variable, e.g. `127.0.0.1:11434`
+pub const HOST_ENV_VAR: &str = "OLLAMA_HOST";
+const MAX_CONCURRENT_SHOW_REQUESTS: usize = 4;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OllamaSettings {
+    pub base_url: Option<String>,
+}
+
+impl OllamaSettings {
+    pub fn from_env() -> Self {
+        Self {
+            base_url: std::env::var(HOST_ENV_VAR).ok().filter(|host| !host.is_empty()),
+        }
+    }
+
+    /// Whether a host was set explicitly. Otherwise the default local
+    /// server is probed, and its absence is not an error.
+    pub fn is_configured(&self) -> bool {
+        self.base_url.is_some()
+    }
+
+    pub fn base_url(&self) -> String {
+        let url = self
+            .base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_BASE_URL)
+            .trim_end_matches('/');
+        if url.contains("://") {
+            url.to_string()
+        } else {
+            format!("http://{url}")
+        }
+    }
+}
+
+pub struct OllamaBackend {
+    settings: OllamaSettings,
+    http_client: Arc<dyn HttpClient>,
+    /// Installed models that can call tools, as of the last `list_models`.
+    tool_models: Arc<Mutex<HashSet<String>>>,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<InstalledModel>,
+}
+
+#[derive(Deserialize)]
+struct InstalledModel {
+    name: String,
+}
+
+#[derive(Default, Deserialize)]
+struct ShowResponse {
+    #[serde(default)]
+    capabilities: Vec<String>,
+    #[serde(default)]
+    model_info: serde_json::Map<String, serde_json::Value>,
+}
+
+impl OllamaBackend {
+    pub fn new(settings: OllamaSettings, http_client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            settings,
+            http_client,
+            tool_models: Default::default(),
+        }
+    }
+
+    async fn post(
+        http_client: &Arc<dyn HttpClient>,
+        url: String,
+        body: serde_json::Value,
+    ) -> Result<Response<AsyncBody>> {
+        let request = HttpRequest::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header("Content-Type", "application/json")
+            .body(AsyncBody::from(body.to_string()))?;
+        http_client.send(request).await
+    }
+
+    async fn show(
+        http_client: &Arc<dyn HttpClient>,
+        url: String,
+        model: &str,
+    ) -> Result<ShowResponse> {
+        let mut response = Self::post(http_client, url, json!({ "model": model })).await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("showing {model} failed: {}", response.status()));
+        }
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Lists the installed models as `ollama/<name>` catalog entries. Context
+    /// length and tool support come from `/api/show` when it has them.
+    pub fn list_models(&self) -> BoxFuture<'static, Result<Vec<ModelInfo>>> {
+        let base_url = self.settings.base_url();
+        let http_client = self.http_client.clone();
+        let tool_models = self.tool_models.clone();
+        async move {
+            let request = HttpRequest::builder()
+                .method(Method::GET)
+                .uri(format!("{base_url}/api/tags"))
+                .body(AsyncBody::empty())?;
+            let mut response = http_client
+                .send(request)
+                .await
+                .with_context(|| format!("connecting to Ollama at {base_url}"))?;
+            let mut body = String::new();
+            response.body_mut().read_to_string(&mut body).await?;
+            if !response.status().is_success() {
+                return Err(anyhow!("listing Ollama models failed: {} {}", response.status(), body));
+            }
+            let tags: TagsResponse = serde_json::from_str(&body)?;
+
+            // Fetched a few at a time rather than one after another, since
+            // `/api/show` can be slow for large models
+            let models = stream::iter(tags.models)
+                .map(|installed| {
+                    let http_client = http_client.clone();
+                    let url = format!("{base_url}/api/show");
+                    async move {
+                        let show = Self::show(&http_client, url, &installed.name).await;
+                        model_info(installed.name, show.unwrap_or_default())
+                    }
+                })
+                .buffered(MAX_CONCURRENT_SHOW_REQUESTS)
+                .collect::<Vec<ModelInfo>>()
+                .await;
+            *tool_models.lock().unwrap() = models
+                .iter()
+                .filter(|model| model.capabilities.tools)
+                .filter_map(|model| Some(parse_model_id(&model.id).ok()?.1.to_string()))
+                .collect();
+            Ok(models)
+        }
+        .boxed()
+    }
+}
+
+fn model_info(name: String, show: ShowResponse) -> ModelInfo {
+    // Keys look like `llama.context_length`
+    let context_length = show
+        .model_info
+        .iter()
+        .find(|(key, _)| key.ends_with(".context_length"))
+        .and_then(|(_, value)| value.as_u64())
+        .map_or(ModelInfo::DEFAULT_CONTEXT_LENGTH, |length| length as usize);
+    ModelInfo {
+        id: format!("{PROVIDER_ID}/{name}"),
+        display_name: Some(name),
+        context_length,
+        capabilities: ModelCapabilities {
+            tools: show.capabilities.iter().any(|capability| capability == "tools"),
+            vision: show.capabilities.iter().any(|capability| capability == "vision"),
+            streaming: true,
+        },
+        input_price: None,
+        output_price: None,
+    }
+}
+
+// Ollama has no call ids; tool results name the tool instead
+fn request_messages(messages: &[RequestMessage]) -> Vec<serde_json::Value> {
+    messages
+        .iter()
+        .map(|message| {
+            let mut value = json!({
+                "role": message.role.as_str(),
+                "content": message.text(),
+            });
+            if !message.tool_calls.is_empty() {
+                value["tool_calls"] = message
+                    .tool_calls
+                    .iter()
+                    .map(|call| json!({ "function": { "name": call.name, "arguments": call.arguments } }))
+                    .collect();
+            }
+            if message.role == Role::Tool {
+                let tool_name = messages
+                    .iter()
+                    .flat_map(|message| &message.tool_calls)
+                    .find(|call| Some(&call.id) == message.tool_call_id.as_ref())
+                    .map(|call| call.name.clone());
+                if let Some(tool_name) = tool_name {
+                    value["tool_name"] = json!(tool_name);
+                }
+            }
+            value
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    message: Option<ChunkMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChunkMessage {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<ChunkToolCall>,
+}
+
+#[derive(Deserialize)]
+struct ChunkToolCall {
+    #[serde(default)]
+    id: Option<String>,
+    function: ChunkFunction,
+}
+
+#[derive(Deserialize)]
+struct ChunkFunction {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+/// Maps one NDJSON line of a `/api/chat` stream. Tool calls arrive whole.
+pub fn map_chat_line(line: &str, saw_tool_calls: &mut bool) -> Result<Vec<CompletionEvent>> {
+    let chunk: ChatChunk =
+        serde_json::from_str(line).with_context(|| format!("invalid chunk: {line}"))?;
+    if let Some(error) = chunk.error {
+        return Err(anyhow!("Ollama error: {error}"));
+    }
+    let mut events = Vec::new();
+    if let Some(message) = chunk.message {
+        if !message.content.is_empty() {
+            events.push(CompletionEvent::Text(message.content));
+        }
+        for call in message.tool_calls {
+            *saw_tool_calls = true;
+            let arguments = match call.function.arguments {
+                serde_json::Value::Null => json!({}),
+                // Some models send the arguments JSON-encoded
+                serde_json::Value::String(text) => serde_json::from_str(&text).unwrap_or(json!({})),
+                arguments => arguments,
+            };
+            events.push(CompletionEvent::ToolUse(ToolCall {
+                id: call
+                    .id
+                    .unwrap_or_else(|| format!("ollama-{}", uuid::Uuid::new_v4())),
+                name: call.function.name,
+                arguments,
+                status: Default::default(),
+            }));
+        }
+    }
+    if chunk.done {
+        events.push(CompletionEvent::Usage(TokenUsage {
+            input_tokens: chunk.prompt_eval_count.unwrap_or(0),
+            output_tokens: chunk.eval_count.unwrap_or(0),
+        }));
+        let stop_reason = match chunk.done_reason.as_deref() {
+            _ if *saw_tool_calls => StopReason::ToolUse,
+            Some("length") => StopReason::MaxTokens,
+            _ => StopReason::EndTurn,
+        };
+        events.push(CompletionEvent::Stop(stop_reason));
+    }
+    Ok(events)
+}
+
+impl AgentBackend for OllamaBackend {
+    fn name(&self) -> SharedString {
+        PROVIDER_ID.into()
+    }
+
+    fn stream_completion(
+        &self,
+        request: ChatRequest,
+        _cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let url = format!("{}/api/chat", self.settings.base_url());
+        let http_client = self.http_client.clone();
+        let tool_models = self.tool_models.clone();
+        async move {
+            let (_, model) = parse_model_id(&request.model)?;
+            let supports_tools = tool_models.lock().unwrap().contains(model);
+            let mut options = serde_json::Map::new();
+            if let Some(temperature) = request.temperature {
+                options.insert("temperature".into(), json!(temperature));
+            }
+            if let Some(max_tokens) = request.max_tokens {
+                options.insert("num_predict".into(), json!(max_tokens));
+            }
+            if !request.stop.is_empty() {
+                options.insert("stop".into(), json!(request.stop));
+            }
+            let mut body = json!({
+                "model": model,
+                "messages": request_messages(&request.messages),
+                "stream": true,
+                "options": options,
+            });
+            if supports_tools && !request.tools.is_empty() {
+                body["tools"] = request
+                    .tools
+                    .iter()
+                    .map(|tool| {
+                        json!({
+                            "type": "function",
+                            "function": {
+                                "name": tool.name,
+                                "description": tool.description,
+                                "parameters": tool.input_schema,
+                            },
+                        })
+                    })
+                    .collect();
+            }
+
+            let mut response = Self::post(&http_client, url, body)
+                .await
+                .context("connecting to Ollama")?;
+            if !response.status().is_success() {
+                let mut error = String::new();
+                response.body_mut().read_to_string(&mut error).await?;
+                return Err(anyhow!("Ollama request failed: {} {}", response.status(), error));
+            }
+
+            let lines = BufReader::new(response.into_body()).lines();
+            let state = (lines, false, VecDeque::new(), false);
+            let events = futures::stream::unfold(state, |state| async move {
+                let (mut lines, mut saw_tool_calls, mut queue, mut done) = state;
+                loop {
+                    if let Some(event) = queue.pop_front() {
+                        let stopped = matches!(event, CompletionEvent::Stop(_));
+                        return Some((Ok(event), (lines, saw_tool_calls, queue, done || stopped)));
+                    }
+                    if done {
+                        return None;
+                    }
+                    let batch = match lines.next().await {
+                        Some(Ok(line)) if line.trim().is_empty() => continue,
+                        Some(Ok(line)) => map_chat_line(&line, &mut saw_tool_calls),
+                        Some(Err(error)) => Err(error.into()),
+                        None => {
+                            done = true;
+                            Ok(vec![CompletionEvent::Stop(StopReason::EndTurn)])
+                        }
+                    };
+                    match batch {
+                        Ok(batch) => queue.extend(batch),
+                        Err(error) => {
+                            return Some((Err(error), (lines, saw_tool_calls, queue, true)))
+                        }
+                    }
+                }
+            });
+            Ok(events.boxed())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_lines(lines: &[&str]) -> Vec<CompletionEvent> {
+        let mut saw_tool_calls = false;
+        lines
+            .iter()
+            .flat_map(|line| map_chat_line(line, &mut saw_tool_calls).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn chat_lines_map_to_text_usage_and_stop() {
+        assert_eq!(
+            map_lines(&[
+                r#"{"message":{"role":"assistant","content":"Hello"},"done":false}"#,
+                r#"{"message":{"role":"assistant","content":""},"done":false}"#,
+                r#"{"message":{"role":"assistant","content":" world"},"done":false}"#,
+                r#"{"message":{"role":"assistant","content":""},"done":true,
+                    "done_reason":"length","prompt_eval_count":12,"eval_count":8}"#,
+            ]),
+            vec![
+                CompletionEvent::Text("Hello".into()),
+                CompletionEvent::Text(" world".into()),
+                CompletionEvent::Usage(TokenUsage {
+                    input_tokens: 12,
+                    output_tokens: 8,
+                }),
+                CompletionEvent::Stop(StopReason::MaxTokens),
+            ]
+        );
+    }
+
+    #[test]
+    fn chat_lines_map_tool_calls() {
+        let events = map_lines(&[
+            r#"{"message":{"role":"assistant","content":"","tool_calls":[
+                {"function":{"name":"read_file","arguments":{"path":"a"}}},
+                {"id":"call_2","function":{"name":"list_files","arguments":"{\"path\":\".\"}"}},
+                {"id":"call_3","function":{"name":"git_status"}}
+            ]},"done":false}"#,
+            r#"{"done":true,"done_reason":"stop"}"#,
+        ]);
+        let calls: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                CompletionEvent::ToolUse(call) => Some(call),
+                _ => None,
+            })
+            .collect();
+        let [first, second, third] = calls.as_slice() else {
+            panic!("unexpected events: {events:?}");
+        };
+        // Ollama sends no ids, so one is made up
+        assert!(first.id.starts_with("ollama-"), "{}", first.id);
+        assert_eq!(first.name, "read_file");
+        assert_eq!(first.arguments, json!({ "path": "a" }));
+        // JSON-encoded arguments are decoded, missing ones are empty
+        assert_eq!(second.id, "call_2");
+        assert_eq!(second.arguments, json!({ "path": "." }));
+        assert_eq!(third.arguments, json!({}));
+        assert_eq!(
+            events.last(),
+            Some(&CompletionEvent::Stop(StopReason::ToolUse))
+        );
+    }
+
+    #[test]
+    fn chat_line_errors_are_reported() {
+        let mut saw_tool_calls = false;
+        let error = map_chat_line(r#"{"error":"model not found"}"#, &mut saw_tool_calls);
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "Ollama error: model not found"
+        );
+        assert!(map_chat_line("not json", &mut saw_tool_calls).is_err());
+    }
+}
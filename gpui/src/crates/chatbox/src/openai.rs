@@ -0,0 +1,357 @@
+// OpenAI-compatible chat completions provider
+// Talks to `/chat/completions` directly, so it works with OpenAI itself and
+// with local servers such as llama.cpp or vLLM. Responses are streamed as
+// Server-Sent Events and tool calls arrive as argument fragments that are
+// assembled per call index.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, stream::BoxStream, AsyncReadExt, FutureExt};
+use gpui::{AsyncApp, SharedString};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::assistant_settings::AssistantSettings;
+use crate::backend::{
+    sse_completion_events, AgentBackend, ChatRequest, CompletionEvent, SseEventMapper, StopReason,
+    TokenUsage,
+};
+use crate::conversation::{RequestMessage, Role, ToolCall};
+use crate::model_catalog::parse_model_id;
+use crate::sse::SseEvent;
+
+pub const PROVIDER_ID: &str = "openai";
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+pub const BASE_URL_ENV_VAR: &str = "CODE_AGENT_OPENAI_BASE_URL";
+pub const API_KEY_ENV_VAR: &str = "OPENAI_API_KEY";
+pub const MODEL_ENV_VAR: &str = "CODE_AGENT_OPENAI_MODEL";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpenAiSettings {
+    /// Defaults to the OpenAI API.
+    pub base_url: Option<String>,
+    /// Local servers usually don't need one.
+    pub api_key: Option<String>,
+    /// Model added to the catalog as `openai/<model>`.
+    pub model: Option<String>,
+}
+
+impl OpenAiSettings {
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|value: &String| !value.is_empty());
+        Self {
+            base_url: var(BASE_URL_ENV_VAR),
+            api_key: var(API_KEY_ENV_VAR),
+            model: var(MODEL_ENV_VAR),
+        }
+    }
+
+    /// The assistant settings, falling back to the environment for anything
+    /// they leave unset.
+    pub fn from_settings(settings: &AssistantSettings) -> Self {
+        let env = Self::from_env();
+        Self {
+            base_url: settings.endpoints.openai.clone().or(env.base_url),
+            api_key: settings.openai.api_key.clone().or(env.api_key),
+            model: settings.openai.model.clone().or(env.model),
+        }
+    }
+
+    /// Whether anything points the app at an OpenAI-compatible endpoint.
+    pub fn is_configured(&self) -> bool {
+        self.base_url.is_some() || self.api_key.is_some()
+    }
+
+    pub fn base_url(&self) -> &str {
+        self.base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_BASE_URL)
+            .trim_end_matches('/')
+    }
+
+    pub fn model_id(&self) -> Option<String> {
+        self.model.as_ref().map(|model| format!("{PROVIDER_ID}/{model}"))
+    }
+}
+
+pub struct OpenAiBackend {
+    settings: OpenAiSettings,
+    http_client: Arc<dyn HttpClient>,
+}
+
+impl OpenAiBackend {
+    pub fn new(settings: OpenAiSettings, http_client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            settings,
+            http_client,
+        }
+    }
+}
+
+// Request body types
+
+#[derive(Serialize)]
+struct OpenAiRequest {
+    model: String,
+    messages: Vec<serde_json::Value>,
+    stream: bool,
+    stream_options: StreamOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+fn request_message(message: &RequestMessage) -> serde_json::Value {
+    let mut value = json!({
+        "role": message.role.as_str(),
+        "content": message.text(),
+    });
+    if !message.tool_calls.is_empty() {
+        value["tool_calls"] = message
+            .tool_calls
+            .iter()
+            .map(|call| {
+                json!({
+                    "id": call.id,
+                    "type": "function",
+                    "function": {
+                        "name": call.name,
+                        // Arguments travel as a JSON-encoded string
+                        "arguments": call.arguments.to_string(),
+                    },
+                })
+            })
+            .collect();
+        if message.content.is_empty() {
+            value["content"] = serde_json::Value::Null;
+        }
+    }
+    if message.role == Role::Tool {
+        if let Some(call_id) = &message.tool_call_id {
+            value["tool_call_id"] = json!(call_id);
+        }
+    }
+    value
+}
+
+// Streamed chunk types
+
+#[derive(Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    #[serde(default)]
+    usage: Option<ChunkUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    delta: ChunkDelta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct ChunkDelta {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+#[derive(Deserialize)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: Option<FunctionDelta>,
+}
+
+#[derive(Deserialize)]
+struct FunctionDelta {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChunkUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+#[derive(Default)]
+struct PendingToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Turns streamed chunks into completion events. Tool calls are held until
+/// the choice finishes, since their arguments arrive in fragments.
+#[derive(Default)]
+pub struct OpenAiEventMapper {
+    tool_calls: BTreeMap<usize, PendingToolCall>,
+    stop_reason: Option<StopReason>,
+}
+
+impl OpenAiEventMapper {
+    /// Maps the JSON payload of one event.
+    pub fn map_chunk(&mut self, data: &str) -> Result<Vec<CompletionEvent>> {
+        let chunk: StreamChunk =
+            serde_json::from_str(data).with_context(|| format!("invalid chunk: {data}"))?;
+        let mut events = Vec::new();
+        for choice in chunk.choices {
+            if let Some(text) = choice.delta.content.filter(|text| !text.is_empty()) {
+                events.push(CompletionEvent::Text(text));
+            }
+            for delta in choice.delta.tool_calls {
+                let call = self.tool_calls.entry(delta.index).or_default();
+                if let Some(id) = delta.id {
+                    call.id = id;
+                }
+                if let Some(function) = delta.function {
+                    if let Some(name) = function.name {
+                        call.name.push_str(&name);
+                    }
+                    if let Some(arguments) = function.arguments {
+                        call.arguments.push_str(&arguments);
+                    }
+                }
+            }
+            if let Some(reason) = choice.finish_reason {
+                self.stop_reason = Some(match reason.as_str() {
+                    "length" => StopReason::MaxTokens,
+                    "tool_calls" | "function_call" => StopReason::ToolUse,
+                    _ => StopReason::EndTurn,
+                });
+                events.extend(self.flush_tool_calls()?);
+            }
+        }
+        if let Some(usage) = chunk.usage {
+            events.push(CompletionEvent::Usage(TokenUsage {
+                input_tokens: usage.prompt_tokens,
+                output_tokens: usage.completion_tokens,
+            }));
+        }
+        Ok(events)
+    }
+
+    fn flush_tool_calls(&mut self) -> Result<Vec<CompletionEvent>> {
+        std::mem::take(&mut self.tool_calls)
+            .into_values()
+            .map(|call| {
+                let arguments = if call.arguments.trim().is_empty() {
+                    json!({})
+                } else {
+                    serde_json::from_str(&call.arguments).with_context(|| {
+                        format!("invalid arguments for tool call {}: {}", call.name, call.arguments)
+                    })?
+                };
+                Ok(CompletionEvent::ToolUse(ToolCall {
+                    id: call.id,
+                    name: call.name,
+                    arguments,
+                    status: Default::default(),
+                }))
+            })
+            .collect()
+    }
+}
+
+impl SseEventMapper for OpenAiEventMapper {
+    fn map_event(&mut self, event: SseEvent) -> Result<Vec<CompletionEvent>> {
+        if event.data == "[DONE]" {
+            return self.finish();
+        }
+        self.map_chunk(&event.data)
+    }
+
+    /// Flushes calls that never saw a finish reason.
+    fn finish(&mut self) -> Result<Vec<CompletionEvent>> {
+        let mut events = self.flush_tool_calls()?;
+        let stop_reason = self.stop_reason.take().unwrap_or(StopReason::EndTurn);
+        events.push(CompletionEvent::Stop(stop_reason));
+        Ok(events)
+    }
+}
+
+impl AgentBackend for OpenAiBackend {
+    fn name(&self) -> SharedString {
+        PROVIDER_ID.into()
+    }
+
+    fn stream_completion(
+        &self,
+        request: ChatRequest,
+        _cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let url = format!("{}/chat/completions", self.settings.base_url());
+        let api_key = self.settings.api_key.clone();
+        let http_client = self.http_client.clone();
+        async move {
+            let (_, model) = parse_model_id(&request.model)?;
+            let body = serde_json::to_string(&OpenAiRequest {
+                model: model.to_string(),
+                messages: request.messages.iter().map(request_message).collect(),
+                stream: true,
+                stream_options: StreamOptions {
+                    include_usage: true,
+                },
+                temperature: request.temperature,
+                max_tokens: request.max_tokens,
+                stop: request.stop,
+                tools: request
+                    .tools
+                    .iter()
+                    .map(|tool| {
+                        json!({
+                            "type": "function",
+                            "function": {
+                                "name": tool.name,
+                                "description": tool.description,
+                                "parameters": tool.input_schema,
+                            },
+                        })
+                    })
+                    .collect(),
+            })?;
+            let mut request_builder = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(url)
+                .header("Content-Type", "application/json")
+                .header("Accept", "text/event-stream");
+            if let Some(api_key) = api_key {
+                request_builder = request_builder.header("Authorization", format!("Bearer {api_key}"));
+            }
+            let mut response = http_client.send(request_builder.body(AsyncBody::from(body))?).await?;
+            if !response.status().is_success() {
+                let mut body = String::new();
+                response.body_mut().read_to_string(&mut body).await?;
+                return Err(anyhow!("OpenAI request failed: {} {}", response.status(), body));
+            }
+
+            Ok(sse_completion_events(response.into_body(), OpenAiEventMapper::default()))
+        }
+        .boxed()
+    }
+}
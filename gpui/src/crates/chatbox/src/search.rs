@@ -0,0 +1,478 @@
+// Full-text search across stored conversations
+// Messages are split into prose, fenced code blocks and tool results, which
+// are indexed as separate documents and ranked with BM25.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+
+use crate::conversation::{Conversation, ConversationId, MessageId, Role};
+
+// BM25 parameters
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+// Bonus applied when the whole query appears verbatim
+const PHRASE_BOOST: f32 = 1.5;
+const SNIPPET_CONTEXT_CHARS: usize = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchSource {
+    Message,
+    CodeBlock,
+    ToolResult,
+}
+
+impl MatchSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MatchSource::Message => "message",
+            MatchSource::CodeBlock => "code",
+            MatchSource::ToolResult => "tool result",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Document {
+    conversation_id: ConversationId,
+    message_id: MessageId,
+    source: MatchSource,
+    text: String,
+    length: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub conversation_id: ConversationId,
+    pub conversation_title: String,
+    pub updated_at: DateTime<Utc>,
+    pub message_id: MessageId,
+    pub source: MatchSource,
+    pub score: f32,
+    pub snippet: String,
+    /// Byte ranges of matched terms within `snippet`.
+    pub highlights: Vec<Range<usize>>,
+}
+
+#[derive(Clone, Debug)]
+struct ConversationInfo {
+    title: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct SearchIndex {
+    documents: Vec<Option<Document>>,
+    // term -> (document index, term frequency)
+    postings: HashMap<String, Vec<(usize, usize)>>,
+    conversations: HashMap<ConversationId, ConversationInfo>,
+    total_length: usize,
+    document_count: usize,
+}
+
+/// Splits text into lowercase word tokens with their byte ranges. CJK
+/// characters are indexed one per token since they are not space-separated.
+pub fn tokenize(text: &str) -> Vec<(Range<usize>, String)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (ix, ch) in text.char_indices() {
+        let is_cjk = ('\u{2e80}'..='\u{9fff}').contains(&ch) || ('\u{ac00}'..='\u{d7af}').contains(&ch);
+        if ch.is_alphanumeric() && !is_cjk {
+            start.get_or_insert(ix);
+            continue;
+        }
+        if let Some(start) = start.take() {
+            tokens.push((start..ix, text[start..ix].to_lowercase()));
+        }
+        if is_cjk {
+            let end = ix + ch.len_utf8();
+            tokens.push((ix..end, text[ix..end].to_string()));
+        }
+    }
+    if let Some(start) = start {
+        tokens.push((start..text.len(), text[start..].to_lowercase()));
+    }
+    tokens
+}
+
+// Splits message content into prose and fenced code blocks
+fn split_code_blocks(content: &str) -> Vec<(MatchSource, String)> {
+    let mut parts = Vec::new();
+    let mut prose = String::new();
+    let mut code: Option<String> = None;
+    for line in content.lines() {
+        let is_fence = line.trim_start().starts_with("```");
+        match code.as_mut() {
+            Some(block) if is_fence => {
+                parts.push((MatchSource::CodeBlock, std::mem::take(block)));
+                code = None;
+            }
+            Some(block) => {
+                block.push_str(line);
+                block.push('\n');
+            }
+            None if is_fence => code = Some(String::new()),
+            None => {
+                prose.push_str(line);
+                prose.push('\n');
+            }
+        }
+    }
+    if let Some(block) = code {
+        parts.push((MatchSource::CodeBlock, block));
+    }
+    if !prose.trim().is_empty() {
+        parts.insert(0, (MatchSource::Message, prose));
+    }
+    parts
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn build<'a>(conversations: impl IntoIterator<Item = &'a Conversation>) -> Self {
+        let mut index = Self::new();
+        for conversation in conversations {
+            index.add_conversation(conversation);
+        }
+        index
+    }
+
+    pub fn add_conversation(&mut self, conversation: &Conversation) {
+        self.remove_conversation(conversation.id());
+        self.conversations.insert(
+            conversation.id().clone(),
+            ConversationInfo {
+                title: conversation.title(),
+                updated_at: conversation.updated_at(),
+            },
+        );
+
+        for message in conversation.all_messages() {
+            let parts = if message.role == Role::Tool {
+                vec![(MatchSource::ToolResult, message.content.clone())]
+            } else {
+                split_code_blocks(&message.content)
+            };
+            for (source, text) in parts {
+                self.add_document(Document {
+                    conversation_id: conversation.id().clone(),
+                    message_id: message.id,
+                    source,
+                    length: 0,
+                    text,
+                });
+            }
+        }
+    }
+
+    fn add_document(&mut self, mut document: Document) {
+        let tokens = tokenize(&document.text);
+        if tokens.is_empty() {
+            return;
+        }
+        let doc_ix = self.documents.len();
+        let mut frequencies: HashMap<String, usize> = HashMap::new();
+        for (_, term) in &tokens {
+            *frequencies.entry(term.clone()).or_default() += 1;
+        }
+        for (term, frequency) in frequencies {
+            self.postings.entry(term).or_default().push((doc_ix, frequency));
+        }
+        document.length = tokens.len();
+        self.total_length += document.length;
+        self.document_count += 1;
+        self.documents.push(Some(document));
+    }
+
+    pub fn remove_conversation(&mut self, id: &ConversationId) {
+        if self.conversations.remove(id).is_none() {
+            return;
+        }
+        // Documents are tombstoned so posting indices stay valid
+        let mut removed = HashSet::new();
+        for (ix, slot) in self.documents.iter_mut().enumerate() {
+            if slot.as_ref().is_some_and(|doc| &doc.conversation_id == id) {
+                let document = slot.take().unwrap();
+                self.total_length -= document.length;
+                self.document_count -= 1;
+                removed.insert(ix);
+            }
+        }
+        for postings in self.postings.values_mut() {
+            postings.retain(|(ix, _)| !removed.contains(ix));
+        }
+        self.postings.retain(|_, postings| !postings.is_empty());
+    }
+
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        let query_terms: Vec<String> = tokenize(query).into_iter().map(|(_, term)| term).collect();
+        if query_terms.is_empty() || self.document_count == 0 {
+            return Vec::new();
+        }
+
+        // The last term also matches as a prefix, for search-as-you-type
+        let mut expanded_terms: Vec<&str> = Vec::new();
+        for (ix, term) in query_terms.iter().enumerate() {
+            if ix + 1 == query_terms.len() {
+                expanded_terms.extend(
+                    self.postings
+                        .keys()
+                        .filter(|candidate| candidate.starts_with(term.as_str()))
+                        .map(String::as_str),
+                );
+            } else if self.postings.contains_key(term) {
+                expanded_terms.push(term);
+            }
+        }
+
+        let average_length = self.total_length as f32 / self.document_count as f32;
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        for term in &expanded_terms {
+            let Some(postings) = self.postings.get(*term) else {
+                continue;
+            };
+            let n = postings.len() as f32;
+            let idf = ((self.document_count as f32 - n + 0.5) / (n + 0.5) + 1.0).ln();
+            for &(doc_ix, frequency) in postings {
+                let Some(document) = self.documents[doc_ix].as_ref() else {
+                    continue;
+                };
+                let tf = frequency as f32;
+                let norm = K1 * (1.0 - B + B * document.length as f32 / average_length);
+                *scores.entry(doc_ix).or_default() += idf * tf * (K1 + 1.0) / (tf + norm);
+            }
+        }
+
+        let query_lower = query.trim().to_lowercase();
+        let matched_terms: HashSet<&str> = expanded_terms.iter().copied().collect();
+        let mut results: Vec<SearchResult> = scores
+            .into_iter()
+            .filter_map(|(doc_ix, mut score)| {
+                let document = self.documents[doc_ix].as_ref()?;
+                let info = self.conversations.get(&document.conversation_id)?;
+                if query_terms.len() > 1 && document.text.to_lowercase().contains(&query_lower) {
+                    score *= PHRASE_BOOST;
+                }
+                let (snippet, highlights) = snippet(&document.text, &matched_terms);
+                Some(SearchResult {
+                    conversation_id: document.conversation_id.clone(),
+                    conversation_title: info.title.clone(),
+                    updated_at: info.updated_at,
+                    message_id: document.message_id,
+                    source: document.source,
+                    score,
+                    snippet,
+                    highlights,
+                })
+            })
+            .collect();
+
+        results.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| b.updated_at.cmp(&a.updated_at))
+        });
+        results.truncate(limit);
+        results
+    }
+}
+
+// Cuts a window of text around the first match and returns the highlight
+// ranges relative to that window
+fn snippet(text: &str, terms: &HashSet<&str>) -> (String, Vec<Range<usize>>) {
+    let matches: Vec<Range<usize>> = tokenize(text)
+        .into_iter()
+        .filter(|(_, token)| terms.contains(token.as_str()))
+        .map(|(range, _)| range)
+        .collect();
+    let first = matches.first().map_or(0, |range| range.start);
+
+    let mut start = first;
+    for _ in 0..SNIPPET_CONTEXT_CHARS {
+        match text[..start].chars().next_back() {
+            Some(ch) => start -= ch.len_utf8(),
+            None => break,
+        }
+    }
+    let mut end = first;
+    for _ in 0..SNIPPET_CONTEXT_CHARS * 2 {
+        match text[end..].chars().next() {
+            Some(ch) => end += ch.len_utf8(),
+            None => break,
+        }
+    }
+
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if end < text.len() { "…" } else { "" };
+    let body = &text[start..end];
+    // Snippets are shown on one line
+    let snippet = format!("{prefix}{}{suffix}", body.replace(['\n', '\t'], " "));
+    let offset = prefix.len();
+    let highlights = matches
+        .into_iter()
+        .filter(|range| range.start >= start && range.end <= end)
+        .map(|range| range.start - start + offset..range.end - start + offset)
+        .collect();
+    (snippet, highlights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation(messages: &[&str]) -> Conversation {
+        let mut conversation = Conversation::new();
+        for content in messages {
+            conversation.push(Role::User, *content);
+        }
+        conversation
+    }
+
+    fn contents(results: &[SearchResult], conversations: &[Conversation]) -> Vec<String> {
+        results
+            .iter()
+            .map(|result| {
+                let conversation = conversations
+                    .iter()
+                    .find(|conversation| conversation.id() == &result.conversation_id)
+                    .unwrap();
+                conversation
+                    .message(result.message_id)
+                    .unwrap()
+                    .content
+                    .clone()
+            })
+            .collect()
+    }
+
+    fn highlighted(result: &SearchResult) -> Vec<&str> {
+        result
+            .highlights
+            .iter()
+            .map(|range| &result.snippet[range.clone()])
+            .collect()
+    }
+
+    #[test]
+    fn ranks_with_bm25() {
+        let conversations = [
+            conversation(&["tokio runtime setup"]),
+            conversation(&["tokio tokio tokio runtime setup"]),
+            conversation(&["a much longer note that mentions tokio once among many other words"]),
+            conversation(&["runtime setup without the crate", "unrelated"]),
+        ];
+        let index = SearchIndex::build(&conversations);
+
+        // More occurrences and shorter documents score higher
+        let results = index.search("tokio", 10);
+        assert_eq!(
+            contents(&results, &conversations),
+            [
+                "tokio tokio tokio runtime setup",
+                "tokio runtime setup",
+                "a much longer note that mentions tokio once among many other words",
+            ]
+        );
+        assert!(results
+            .windows(2)
+            .all(|pair| pair[0].score >= pair[1].score));
+
+        // A rare term outweighs a common one
+        let results = index.search("setup unrelated", 10);
+        assert_eq!(contents(&results, &conversations)[0], "unrelated");
+        assert_eq!(index.search("tokio", 2).len(), 2);
+    }
+
+    #[test]
+    fn verbatim_phrases_rank_first() {
+        let conversations = [
+            conversation(&["runtime first, then async"]),
+            conversation(&["async runtime first, then"]),
+        ];
+        let index = SearchIndex::build(&conversations);
+        let results = index.search("async runtime", 10);
+        assert_eq!(
+            contents(&results, &conversations)[0],
+            "async runtime first, then"
+        );
+        assert!(results[0].score > results[1].score);
+    }
+
+    #[test]
+    fn only_the_last_term_matches_as_a_prefix() {
+        let conversations = [
+            conversation(&["Configure the tokio runtime"]),
+            conversation(&["Tokens are counted per message"]),
+            conversation(&["Use a runtime"]),
+        ];
+        let index = SearchIndex::build(&conversations);
+        let mut matches = contents(&index.search("TOK", 10), &conversations);
+        matches.sort();
+        assert_eq!(
+            matches,
+            [
+                "Configure the tokio runtime",
+                "Tokens are counted per message"
+            ]
+        );
+        let results = index.search("TOK", 10);
+        assert_eq!(highlighted(&results[0]).len(), 1);
+
+        // `tok` isn't expanded when another term follows it
+        let mut matches = contents(&index.search("tok runt", 10), &conversations);
+        matches.sort();
+        assert_eq!(matches, ["Configure the tokio runtime", "Use a runtime"]);
+        let results = index.search("tok runt", 10);
+        assert!(results
+            .iter()
+            .all(|result| highlighted(result) == ["runtime"]));
+        assert!(index.search("xyz", 10).is_empty());
+        assert!(index.search("  ", 10).is_empty());
+    }
+
+    #[test]
+    fn highlights_line_up_with_multibyte_text() {
+        let conversations = [
+            conversation(&["Ünïcödé naïve Café, then CAFÉ again"]),
+            conversation(&["日本語のテキスト"]),
+            conversation(&[&format!("{} needle {}", "é".repeat(100), "ü".repeat(200))]),
+        ];
+        let index = SearchIndex::build(&conversations);
+
+        let results = index.search("café", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(highlighted(&results[0]), ["Café", "CAFÉ"]);
+
+        let results = index.search("日本", 10);
+        assert_eq!(highlighted(&results[0]), ["日", "本"]);
+
+        // Cut on both sides, with the highlight shifted past the ellipsis
+        let results = index.search("needle", 10);
+        let snippet = &results[0].snippet;
+        assert!(
+            snippet.starts_with('…') && snippet.ends_with('…'),
+            "{snippet}"
+        );
+        assert_eq!(highlighted(&results[0]), ["needle"]);
+    }
+
+    #[test]
+    fn code_blocks_and_tool_results_are_separate_documents() {
+        let mut conversation =
+            conversation(&["Try this:\n```rust\nlet parser = Parser::new();\n```"]);
+        conversation.push(Role::Tool, "parser finished");
+        let index = SearchIndex::build([&conversation]);
+        let mut sources: Vec<_> = index
+            .search("parser", 10)
+            .iter()
+            .map(|result| result.source.label())
+            .collect();
+        sources.sort();
+        assert_eq!(sources, ["code", "tool result"]);
+    }
+}
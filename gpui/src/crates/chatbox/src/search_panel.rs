@@ -0,0 +1,268 @@
+// Search panel
+// Searches every stored conversation and opens the selected match
+
+use std::sync::Arc;
+
+use gpui::{
+    actions, div, px, rgb, App, Context, Entity, EventEmitter, FocusHandle, Focusable, FontWeight,
+    IntoElement, ParentElement, Render, ScrollHandle, Styled, Subscription, Task, Window,
+    prelude::*,
+};
+
+use crate::conversation::{ConversationId, MessageId};
+use crate::conversation_store::ConversationStore;
+use crate::find_bar::highlighted_text;
+use crate::interactive_chatbox::{ChatInputEvent, InteractiveChatInput, Send};
+use crate::search::{SearchIndex, SearchResult};
+
+const MAX_RESULTS: usize = 50;
+
+actions!(search_panel, [SelectNextResult, SelectPreviousResult]);
+
+#[derive(Clone, Debug)]
+pub enum SearchPanelEvent {
+    Open {
+        conversation_id: ConversationId,
+        message_id: MessageId,
+    },
+    Dismissed,
+}
+
+pub struct ConversationSearchPanel {
+    query_input: Entity<InteractiveChatInput>,
+    index: Option<Arc<SearchIndex>>,
+    results: Vec<SearchResult>,
+    selected_ix: usize,
+    results_scroll_handle: ScrollHandle,
+    error: Option<String>,
+    focus_handle: FocusHandle,
+    _load_index: Task<()>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl EventEmitter<SearchPanelEvent> for ConversationSearchPanel {}
+
+impl ConversationSearchPanel {
+    pub fn new(store: ConversationStore, cx: &mut Context<Self>) -> Self {
+        let query_input = cx.new(|cx| {
+            let mut input = InteractiveChatInput::new(cx);
+            input.set_placeholder("Search all conversations...");
+            input
+        });
+        let subscriptions = vec![cx.subscribe(&query_input, |this, _, event, cx| {
+            if *event == ChatInputEvent::Edited {
+                this.update_results(cx);
+            }
+        })];
+
+        // Index on the background executor; conversations are read from disk
+        let load_index = cx.spawn(async move |this, cx| {
+            let result = cx
+                .background_spawn(async move {
+                    store
+                        .load_all()
+                        .map(|conversations| SearchIndex::build(&conversations))
+                })
+                .await;
+            this.update(cx, |this, cx| {
+                match result {
+                    Ok(index) => this.index = Some(Arc::new(index)),
+                    Err(error) => this.error = Some(format!("Failed to load conversations: {error:#}")),
+                }
+                this.update_results(cx);
+            })
+            .ok();
+        });
+
+        Self {
+            query_input,
+            index: None,
+            results: Vec::new(),
+            selected_ix: 0,
+            results_scroll_handle: ScrollHandle::new(),
+            error: None,
+            focus_handle: cx.focus_handle(),
+            _load_index: load_index,
+            _subscriptions: subscriptions,
+        }
+    }
+
+    pub fn query_input(&self) -> &Entity<InteractiveChatInput> {
+        &self.query_input
+    }
+
+    fn update_results(&mut self, cx: &mut Context<Self>) {
+        let query = self.query_input.read(cx).get_text();
+        self.results = self
+            .index
+            .as_ref()
+            .map(|index| index.search(&query, MAX_RESULTS))
+            .unwrap_or_default();
+        self.selected_ix = 0;
+        cx.notify();
+    }
+
+    fn select_result(&mut self, ix: usize, cx: &mut Context<Self>) {
+        if ix < self.results.len() {
+            self.selected_ix = ix;
+            self.results_scroll_handle.scroll_to_item(ix);
+            cx.notify();
+        }
+    }
+
+    fn select_next(&mut self, _: &SelectNextResult, _: &mut Window, cx: &mut Context<Self>) {
+        self.select_result(self.selected_ix + 1, cx);
+    }
+
+    fn select_previous(
+        &mut self,
+        _: &SelectPreviousResult,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if let Some(ix) = self.selected_ix.checked_sub(1) {
+            self.select_result(ix, cx);
+        }
+    }
+
+    pub fn open_result(&mut self, ix: usize, cx: &mut Context<Self>) {
+        if let Some(result) = self.results.get(ix) {
+            cx.emit(SearchPanelEvent::Open {
+                conversation_id: result.conversation_id.clone(),
+                message_id: result.message_id,
+            });
+        }
+    }
+
+    pub fn dismiss(&mut self, cx: &mut Context<Self>) {
+        cx.emit(SearchPanelEvent::Dismissed);
+    }
+
+    fn on_send(&mut self, _: &Send, _window: &mut Window, cx: &mut Context<Self>) {
+        self.open_result(self.selected_ix, cx);
+    }
+}
+
+impl Focusable for ConversationSearchPanel {
+    fn focus_handle(&self, _: &App) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Render for ConversationSearchPanel {
+    fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let query_is_empty = self.query_input.read(cx).get_text().trim().is_empty();
+        let status = if let Some(error) = &self.error {
+            Some(error.clone())
+        } else if self.index.is_none() {
+            Some("Indexing conversations...".to_string())
+        } else if !query_is_empty && self.results.is_empty() {
+            Some("No matches".to_string())
+        } else {
+            None
+        };
+
+        let results: Vec<_> = self
+            .results
+            .iter()
+            .enumerate()
+            .map(|(ix, result)| {
+                div()
+                    .id(("search-result", ix))
+                    .cursor_pointer()
+                    .flex()
+                    .flex_col()
+                    .gap_1()
+                    .px_3()
+                    .py_2()
+                    .rounded_md()
+                    .when(ix == self.selected_ix, |this| this.bg(rgb(0xeff6ff)))
+                    .hover(|this| this.bg(rgb(0xf3f4f6)))
+                    .on_click(cx.listener(move |this, _, _, cx| this.open_result(ix, cx)))
+                    .child(
+                        div()
+                            .flex()
+                            .justify_between()
+                            .child(
+                                div()
+                                    .text_color(rgb(0x111827))
+                                    .text_size(px(13.0))
+                                    .font_weight(FontWeight::MEDIUM)
+                                    .child(result.conversation_title.clone())
+                            )
+                            .child(
+                                div()
+                                    .text_color(rgb(0x6b7280))
+                                    .text_size(px(11.0))
+                                    .child(format!(
+                                        "{} · {}",
+                                        result.source.label(),
+                                        result.updated_at.format("%Y-%m-%d")
+                                    ))
+                            )
+                    )
+                    .child(
+                        div()
+                            .text_size(px(12.0))
+                            .child(highlighted_text(
+                                result.snippet.clone(),
+                                rgb(0x374151).into(),
+                                result
+                                    .highlights
+                                    .iter()
+                                    .map(|range| (range.clone(), rgb(0xfde68a).into())),
+                                window,
+                            ))
+                    )
+            })
+            .collect();
+
+        div()
+            .key_context("ConversationSearchPanel")
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::on_send))
+            .on_action(cx.listener(Self::select_next))
+            .on_action(cx.listener(Self::select_previous))
+            .flex()
+            .flex_col()
+            .gap_2()
+            .p_4()
+            .max_h(px(360.0))
+            .bg(rgb(0xffffff))
+            .border_b_1()
+            .border_color(rgb(0xe5e7eb))
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .gap_2()
+                    .child(div().flex_1().child(self.query_input.clone()))
+                    .child(
+                        div()
+                            .id("close-search")
+                            .cursor_pointer()
+                            .px_2()
+                            .text_color(rgb(0x6b7280))
+                            .on_click(cx.listener(|this, _, _, cx| this.dismiss(cx)))
+                            .child("✕")
+                    )
+            )
+            .when_some(status, |this, status| {
+                this.child(
+                    div()
+                        .text_color(rgb(0x6b7280))
+                        .text_size(px(12.0))
+                        .child(status)
+                )
+            })
+            .child(
+                div()
+                    .id("search-results")
+                    .flex()
+                    .flex_col()
+                    .overflow_y_scroll()
+                    .track_scroll(&self.results_scroll_handle)
+                    .children(results)
+            )
+    }
+}
@@ -0,0 +1,272 @@
+// Incremental Server-Sent Events decoder
+// Follows the WHATWG event stream rules: lines end in CRLF, LF or CR, `data:`
+// lines accumulate, `:` lines are comments and a blank line dispatches the
+// event. Bytes are buffered until a line is complete, so chunks may split
+// lines, line endings or UTF-8 sequences anywhere.
+
+use anyhow::Result;
+use futures::stream::{self, BoxStream};
+use futures::{AsyncRead, AsyncReadExt, StreamExt};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    /// The `event:` field, or `None` for the default `message` type.
+    pub event: Option<String>,
+    /// `data:` lines joined with `\n`.
+    pub data: String,
+    /// The last `id:` seen on the stream, which carries over between events.
+    pub id: Option<String>,
+    /// Reconnection time in milliseconds, if this event set one.
+    pub retry: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    // A CR ended the last line; a LF right after it belongs to that ending
+    after_cr: bool,
+    started: bool,
+    event: Option<String>,
+    data: String,
+    has_data: bool,
+    last_id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the next chunk of the stream, returning the events it completes.
+    pub fn push(&mut self, mut bytes: &[u8]) -> Vec<SseEvent> {
+        if self.after_cr && !bytes.is_empty() {
+            if bytes[0] == b'\n' {
+                bytes = &bytes[1..];
+            }
+            self.after_cr = false;
+        }
+        self.buffer.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        let mut start = 0;
+        let mut ix = 0;
+        while ix < self.buffer.len() {
+            let byte = self.buffer[ix];
+            if byte != b'\n' && byte != b'\r' {
+                ix += 1;
+                continue;
+            }
+            let line = String::from_utf8_lossy(&self.buffer[start..ix]).into_owned();
+            let mut next = ix + 1;
+            if byte == b'\r' {
+                match self.buffer.get(next) {
+                    Some(b'\n') => next += 1,
+                    Some(_) => {}
+                    // Can't tell yet whether a LF follows
+                    None => self.after_cr = true,
+                }
+            }
+            events.extend(self.process_line(&line));
+            start = next;
+            ix = next;
+        }
+        self.buffer.drain(..start);
+        events
+    }
+
+    /// Ends the stream. The spec drops an event that wasn't followed by a
+    /// blank line; we dispatch it instead, since some servers close the
+    /// connection right after the last `data:` line.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned();
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, mut line: &str) -> Option<SseEvent> {
+        if !self.started {
+            self.started = true;
+            line = line.strip_prefix('\u{feff}').unwrap_or(line);
+        }
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(value);
+                self.has_data = true;
+            }
+            "event" => self.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(retry) = value.parse() {
+                    self.retry = Some(retry);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        let retry = self.retry.take();
+        if !std::mem::take(&mut self.has_data) {
+            return None;
+        }
+        Some(SseEvent {
+            event: event.filter(|event| !event.is_empty()),
+            data: std::mem::take(&mut self.data),
+            id: self.last_id.clone(),
+            retry,
+        })
+    }
+}
+
+/// Decodes an SSE response body into a stream of events.
+pub fn sse_events(
+    reader: impl AsyncRead + Unpin + Send + 'static,
+) -> BoxStream<'static, Result<SseEvent>> {
+    let state = (reader, SseDecoder::new(), Vec::new().into_iter(), false);
+    stream::unfold(state, |state| async move {
+        let (mut reader, mut decoder, mut pending, mut done) = state;
+        loop {
+            if let Some(event) = pending.next() {
+                return Some((Ok(event), (reader, decoder, pending, done)));
+            }
+            if done {
+                return None;
+            }
+            let mut buffer = [0; 8192];
+            match reader.read(&mut buffer).await {
+                Ok(0) => {
+                    pending = decoder.finish().into_iter().collect::<Vec<_>>().into_iter();
+                    done = true;
+                }
+                Ok(read) => pending = decoder.push(&buffer[..read]).into_iter(),
+                Err(error) => return Some((Err(error.into()), (reader, decoder, pending, true))),
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(data: &str) -> SseEvent {
+        SseEvent {
+            data: data.to_string(),
+            ..SseEvent::default()
+        }
+    }
+
+    fn decode(chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        let mut events = Vec::new();
+        for chunk in chunks {
+            events.extend(decoder.push(chunk));
+        }
+        events.extend(decoder.finish());
+        events
+    }
+
+    // Decodes `input` whole, then split in two at every byte and one byte
+    // at a time, checking every split gives the same events
+    fn decode_every_split(input: &[u8]) -> Vec<SseEvent> {
+        let events = decode(&[input]);
+        for ix in 0..=input.len() {
+            let (head, tail) = input.split_at(ix);
+            assert_eq!(decode(&[head, tail]), events, "split at byte {ix}");
+        }
+        let bytes = input.chunks(1).collect::<Vec<_>>();
+        assert_eq!(decode(&bytes), events, "split at every byte");
+        events
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        let events = decode_every_split(b"data: first\ndata: second\ndata\ndata:third\n\n");
+        assert_eq!(events, vec![data("first\nsecond\n\nthird")]);
+    }
+
+    #[test]
+    fn accepts_every_line_ending() {
+        let expected = vec![data("a"), data("b"), data("c")];
+        assert_eq!(decode_every_split(b"data: a\r\n\r\ndata: b\r\n\r\ndata: c\r\n\r\n"), expected);
+        assert_eq!(decode_every_split(b"data: a\r\rdata: b\r\rdata: c\r\r"), expected);
+        assert_eq!(decode_every_split(b"data: a\n\rdata: b\r\n\ndata: c\r\r\n"), expected);
+    }
+
+    #[test]
+    fn cr_split_from_its_lf_is_one_line_ending() {
+        let events = decode(&[b"data: a\r", b"\ndata: b\r", b"\n\r", b"\n"]);
+        assert_eq!(events, vec![data("a\nb")]);
+    }
+
+    #[test]
+    fn keeps_utf8_split_across_chunks() {
+        let input = "data: héllo 👋\n\n".as_bytes();
+        let events = decode_every_split(input);
+        assert_eq!(events, vec![data("héllo 👋")]);
+    }
+
+    #[test]
+    fn skips_comments_and_unknown_fields() {
+        let events = decode_every_split(b": keep-alive\n\n:\nfoo: bar\ndata: x\n: note\n\n");
+        assert_eq!(events, vec![data("x")]);
+    }
+
+    #[test]
+    fn reads_event_type_and_retry() {
+        let events = decode_every_split(b"event: delta\nretry: 250\ndata: 1\n\ndata: 2\n\n");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("delta".to_string()),
+                    retry: Some(250),
+                    ..data("1")
+                },
+                data("2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn carries_the_last_id_over() {
+        let events =
+            decode_every_split(b"id: 7\ndata: a\n\ndata: b\n\nid: 8\ndata: c\n\nid\ndata: d\n\n");
+        let ids = events.iter().map(|event| event.id.as_deref()).collect::<Vec<_>>();
+        assert_eq!(ids, vec![Some("7"), Some("7"), Some("8"), Some("")]);
+    }
+
+    #[test]
+    fn dispatches_an_unterminated_final_event() {
+        assert_eq!(decode_every_split(b"data: a\n\ndata: b"), vec![data("a"), data("b")]);
+        assert_eq!(decode_every_split(b"data: a\n\ndata: b\n"), vec![data("a"), data("b")]);
+        assert_eq!(decode_every_split(b"data: a\n\nevent: ping\n"), vec![data("a")]);
+    }
+
+    #[test]
+    fn strips_a_leading_bom() {
+        let events = decode_every_split("\u{feff}data: x\n\n".as_bytes());
+        assert_eq!(events, vec![data("x")]);
+    }
+}
@@ -0,0 +1,366 @@
+// Tool card
+// Shows a tool call in the transcript. While the call awaits approval the
+// card lists its arguments and offers the permission choices; once it has
+// run, the card previews the result. Tools that stream output, like
+// run_command, get a terminal view with ANSI colors instead.
+
+use std::rc::Rc;
+
+use gpui::{
+    div, px, rgb, App, ElementId, FontStyle, FontWeight, Hsla, IntoElement, ParentElement,
+    RenderOnce, SharedString, StyledText, Styled, TextRun, UnderlineStyle, Window, prelude::*,
+};
+
+use crate::ansi::{AnsiOutput, AnsiText};
+use crate::conversation::{ToolCall, ToolStatus};
+use crate::tool_permissions::{command_context, suggested_pattern, PermissionDecision};
+
+const OUTPUT_PREVIEW_LINES: usize = 12;
+const TERMINAL_LINES: usize = 40;
+const TERMINAL_FOREGROUND: u32 = 0xe5e7eb;
+
+type DecisionHandler = Rc<dyn Fn(PermissionDecision, &mut Window, &mut App)>;
+type KillHandler = Rc<dyn Fn(&mut Window, &mut App)>;
+
+#[derive(IntoElement)]
+pub struct ToolCard {
+    call: ToolCall,
+    output: Option<String>,
+    live_output: Option<AnsiText>,
+    summary: Option<String>,
+    on_decision: Option<DecisionHandler>,
+    on_kill: Option<KillHandler>,
+}
+
+// Renders the tail of terminal output with its ANSI styling
+fn terminal_text(output: AnsiText, window: &Window) -> StyledText {
+    let text = output.text.trim_end_matches('\n');
+    let font = window.text_style().font();
+    let default_run = TextRun {
+        len: 0,
+        font: font.clone(),
+        color: rgb(TERMINAL_FOREGROUND).into(),
+        background_color: None,
+        underline: None,
+        strikethrough: None,
+    };
+
+    let mut runs = Vec::new();
+    let mut offset = 0;
+    for (range, style) in &output.spans {
+        let range = range.start..range.end.min(text.len());
+        if range.start >= range.end {
+            continue;
+        }
+        if range.start > offset {
+            runs.push(TextRun {
+                len: range.start - offset,
+                ..default_run.clone()
+            });
+        }
+        let mut color: Hsla = rgb(style.foreground.unwrap_or(TERMINAL_FOREGROUND)).into();
+        if style.dim {
+            color.a *= 0.6;
+        }
+        let mut font = font.clone();
+        if style.bold {
+            font.weight = FontWeight::BOLD;
+        }
+        if style.italic {
+            font.style = FontStyle::Italic;
+        }
+        runs.push(TextRun {
+            len: range.len(),
+            font,
+            color,
+            background_color: style.background.map(|background| rgb(background).into()),
+            underline: style.underline.then(|| UnderlineStyle {
+                thickness: px(1.0),
+                color: Some(color),
+                wavy: false,
+            }),
+            strikethrough: None,
+        });
+        offset = range.end;
+    }
+    if offset < text.len() {
+        runs.push(TextRun {
+            len: text.len() - offset,
+            ..default_run
+        });
+    }
+
+    StyledText::new(text.to_string()).with_runs(runs)
+}
+
+// `NAME=value` lines for the variables a command call sets. They can change
+// what an approved program does, so they are listed apart from the arguments.
+fn command_environment(call: &ToolCall) -> Option<Vec<String>> {
+    call.arguments.get("command")?;
+    let (_, env) = command_context(call);
+    if env.is_empty() {
+        return None;
+    }
+    Some(env.iter().map(|(name, value)| format!("{name}={value}")).collect())
+}
+
+impl ToolCard {
+    pub fn new(call: ToolCall) -> Self {
+        Self {
+            call,
+            output: None,
+            live_output: None,
+            summary: None,
+            on_decision: None,
+            on_kill: None,
+        }
+    }
+
+    /// The result the tool returned to the model.
+    pub fn output(mut self, output: Option<String>) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Output streamed while the tool ran, shown as a terminal in place of
+    /// the result preview.
+    pub fn live_output(mut self, output: Option<&AnsiOutput>, summary: Option<String>) -> Self {
+        self.live_output = output
+            .filter(|output| !output.is_empty())
+            .map(|output| output.tail(TERMINAL_LINES));
+        self.summary = summary;
+        self
+    }
+
+    /// Shows a Kill button while the tool is running.
+    pub fn on_kill(mut self, handler: impl Fn(&mut Window, &mut App) + 'static) -> Self {
+        self.on_kill = Some(Rc::new(handler));
+        self
+    }
+
+    pub fn on_decision(
+        mut self,
+        handler: impl Fn(PermissionDecision, &mut Window, &mut App) + 'static,
+    ) -> Self {
+        self.on_decision = Some(Rc::new(handler));
+        self
+    }
+
+    fn render_button(
+        &self,
+        label: SharedString,
+        decision: PermissionDecision,
+        primary: bool,
+    ) -> impl IntoElement {
+        let id = ElementId::Name(format!("{}-{:?}", self.call.id, decision).into());
+        let handler = self.on_decision.clone();
+        div()
+            .id(id)
+            .cursor_pointer()
+            .px_2()
+            .py_1()
+            .rounded_md()
+            .text_size(px(12.0))
+            .when(primary, |this| this.bg(rgb(0x2563eb)).text_color(rgb(0xffffff)))
+            .when(!primary, |this| {
+                this.border_1().border_color(rgb(0xd1d5db)).text_color(rgb(0x374151))
+            })
+            .on_click(move |_, window, cx| {
+                if let Some(handler) = handler.as_ref() {
+                    handler(decision.clone(), window, cx);
+                }
+            })
+            .child(label)
+    }
+}
+
+impl RenderOnce for ToolCard {
+    fn render(mut self, window: &mut Window, _cx: &mut App) -> impl IntoElement {
+        let status = self.call.status;
+        let status_color = match status {
+            ToolStatus::AwaitingApproval => rgb(0xb45309),
+            ToolStatus::Completed => rgb(0x047857),
+            ToolStatus::Failed | ToolStatus::Denied => rgb(0xdc2626),
+            ToolStatus::Pending | ToolStatus::Running => rgb(0x6b7280),
+        };
+        let arguments = serde_json::to_string_pretty(&self.call.arguments)
+            .unwrap_or_else(|_| self.call.arguments.to_string());
+        let awaiting_approval = status == ToolStatus::AwaitingApproval;
+        let environment = command_environment(&self.call);
+        let kill_handler = self
+            .on_kill
+            .clone()
+            .filter(|_| status == ToolStatus::Running);
+        let has_live_output = self.live_output.is_some();
+        let terminal = self
+            .live_output
+            .take()
+            .map(|output| terminal_text(output, window));
+
+        div()
+            .flex()
+            .flex_col()
+            .gap_2()
+            .mt_2()
+            .px_3()
+            .py_2()
+            .rounded_md()
+            .border_1()
+            .border_color(if awaiting_approval { rgb(0xf59e0b) } else { rgb(0xd1d5db) })
+            .bg(rgb(0xffffff))
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .justify_between()
+                    .child(
+                        div()
+                            .text_size(px(13.0))
+                            .font_weight(FontWeight::MEDIUM)
+                            .text_color(rgb(0x111827))
+                            .child(format!("🔧 {}", self.call.name))
+                    )
+                    .child(
+                        div()
+                            .flex()
+                            .items_center()
+                            .gap_2()
+                            .when_some(self.summary.clone(), |this, summary| {
+                                this.child(
+                                    div()
+                                        .text_size(px(11.0))
+                                        .text_color(rgb(0x6b7280))
+                                        .child(summary)
+                                )
+                            })
+                            .child(
+                                div()
+                                    .text_size(px(11.0))
+                                    .text_color(status_color)
+                                    .child(status.label())
+                            )
+                            .when_some(kill_handler, |this, handler| {
+                                this.child(
+                                    div()
+                                        .id(ElementId::Name(format!("{}-kill", self.call.id).into()))
+                                        .cursor_pointer()
+                                        .px_2()
+                                        .rounded_md()
+                                        .text_size(px(11.0))
+                                        .border_1()
+                                        .border_color(rgb(0xfca5a5))
+                                        .text_color(rgb(0xdc2626))
+                                        .on_click(move |_, window, cx| handler(window, cx))
+                                        .child("Kill")
+                                )
+                            })
+                    )
+            )
+            .when(awaiting_approval || status == ToolStatus::Pending, |this| {
+                this.child(
+                    div()
+                        .px_2()
+                        .py_1()
+                        .rounded_sm()
+                        .bg(rgb(0xf3f4f6))
+                        .text_size(px(12.0))
+                        .text_color(rgb(0x374151))
+                        .child(arguments)
+                )
+            })
+            .when_some(environment.filter(|_| awaiting_approval), |this, environment| {
+                this.child(
+                    div()
+                        .flex()
+                        .flex_col()
+                        .px_2()
+                        .py_1()
+                        .rounded_sm()
+                        .border_1()
+                        .border_color(rgb(0xf59e0b))
+                        .bg(rgb(0xfffbeb))
+                        .text_size(px(12.0))
+                        .text_color(rgb(0x92400e))
+                        .child(
+                            div()
+                                .font_weight(FontWeight::MEDIUM)
+                                .child("Sets environment variables:")
+                        )
+                        .children(environment)
+                )
+            })
+            .when_some(terminal, |this, terminal| {
+                this.child(
+                    div()
+                        .px_2()
+                        .py_1()
+                        .rounded_sm()
+                        .bg(rgb(0x111827))
+                        .text_size(px(12.0))
+                        .child(terminal)
+                )
+            })
+            .when_some(self.output.as_ref().filter(|_| !has_live_output), |this, output| {
+                let line_count = output.lines().count();
+                let mut preview = output
+                    .lines()
+                    .take(OUTPUT_PREVIEW_LINES)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if line_count > OUTPUT_PREVIEW_LINES {
+                    preview.push_str(&format!(
+                        "\n… {} more lines",
+                        line_count - OUTPUT_PREVIEW_LINES
+                    ));
+                }
+                this.child(
+                    div()
+                        .px_2()
+                        .py_1()
+                        .rounded_sm()
+                        .bg(rgb(0x1f2937))
+                        .text_size(px(12.0))
+                        .text_color(rgb(0xe5e7eb))
+                        .child(preview)
+                )
+            })
+            .when(awaiting_approval, |this| {
+                let pattern = suggested_pattern(&self.call);
+                this.child(
+                    div()
+                        .flex()
+                        .flex_wrap()
+                        .gap_2()
+                        .child(self.render_button(
+                            "Allow once".into(),
+                            PermissionDecision::AllowOnce,
+                            true,
+                        ))
+                        .child(self.render_button(
+                            format!("Always allow {}", self.call.name).into(),
+                            PermissionDecision::AlwaysAllowTool,
+                            false,
+                        ))
+                        .when_some(pattern, |this, pattern| {
+                            // Command rules only cover this directory and environment
+                            let mut label = format!("Always allow `{pattern}`");
+                            if self.call.arguments.get("command").is_some() {
+                                let (cwd, env) = command_context(&self.call);
+                                if let Some(cwd) = cwd {
+                                    label.push_str(&format!(" in {cwd}"));
+                                }
+                                if !env.is_empty() {
+                                    label.push_str(" with these variables");
+                                }
+                            }
+                            this.child(self.render_button(
+                                label.into(),
+                                PermissionDecision::AlwaysAllowPattern(pattern),
+                                false,
+                            ))
+                        })
+                        .child(self.render_button("Deny".into(), PermissionDecision::Deny, false))
+                )
+            })
+    }
+}
@@ -0,0 +1,147 @@
+// Agent tools
+// A tool has a name, a JSON schema for its arguments and an async `run`.
+// The registry holds the tools offered to the model and routes calls to them.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::channel::{mpsc, oneshot};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use gpui::{AsyncApp, SharedString};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ToolActivityEvent {
+    /// Output produced while running, which may contain ANSI escapes.
+    Output(String),
+    /// A one-line status shown once the tool is done, e.g. an exit code.
+    Summary(String),
+}
+
+/// Given to a running tool to report live output and to notice when the
+/// user asks to stop it. Tools that finish quickly can ignore it.
+pub struct ToolActivity {
+    events: mpsc::UnboundedSender<ToolActivityEvent>,
+    kill: Option<oneshot::Receiver<()>>,
+}
+
+impl ToolActivity {
+    /// Returns the activity, the receiving end of its events and the
+    /// sender that requests a kill.
+    pub fn new() -> (
+        Self,
+        mpsc::UnboundedReceiver<ToolActivityEvent>,
+        oneshot::Sender<()>,
+    ) {
+        let (events_tx, events_rx) = mpsc::unbounded();
+        let (kill_tx, kill_rx) = oneshot::channel();
+        let activity = Self {
+            events: events_tx,
+            kill: Some(kill_rx),
+        };
+        (activity, events_rx, kill_tx)
+    }
+
+    pub fn output(&self, text: impl Into<String>) {
+        self.events
+            .unbounded_send(ToolActivityEvent::Output(text.into()))
+            .ok();
+    }
+
+    pub fn summary(&self, text: impl Into<String>) {
+        self.events
+            .unbounded_send(ToolActivityEvent::Summary(text.into()))
+            .ok();
+    }
+
+    /// A future that resolves when a kill is requested. It never resolves
+    /// if the kill sender is dropped without sending, or when taken twice.
+    pub fn take_kill_signal(&mut self) -> BoxFuture<'static, ()> {
+        let kill = self.kill.take();
+        async move {
+            let requested = match kill {
+                Some(kill) => kill.await.is_ok(),
+                None => false,
+            };
+            if !requested {
+                futures::future::pending::<()>().await;
+            }
+        }
+        .boxed()
+    }
+}
+
+pub trait Tool: Send + Sync + 'static {
+    fn name(&self) -> SharedString;
+
+    fn description(&self) -> SharedString;
+
+    /// JSON schema of the arguments object.
+    fn input_schema(&self) -> serde_json::Value;
+
+    /// Whether the tool has side effects that need the user's approval.
+    fn needs_approval(&self) -> bool {
+        false
+    }
+
+    /// Runs the tool, returning the text sent back to the model.
+    fn run(
+        &self,
+        arguments: serde_json::Value,
+        activity: ToolActivity,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>>;
+}
+
+// What the model is told about a tool
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub input_schema: serde_json::Value,
+}
+
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: BTreeMap<SharedString, Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a tool, replacing any tool with the same name.
+    pub fn register(&mut self, tool: Arc<dyn Tool>) {
+        self.tools.insert(tool.name(), tool);
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.remove(name)
+    }
+
+    pub fn tool(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.get(name).cloned()
+    }
+
+    pub fn tools(&self) -> impl Iterator<Item = &Arc<dyn Tool>> {
+        self.tools.values()
+    }
+
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools
+            .values()
+            .map(|tool| ToolDefinition {
+                name: tool.name().to_string(),
+                description: tool.description().to_string(),
+                input_schema: tool.input_schema(),
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+}
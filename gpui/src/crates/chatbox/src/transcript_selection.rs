@@ -0,0 +1,223 @@
+// Text selection across transcript messages
+// Points are byte offsets into message content, so a selection survives
+// re-rendering and streaming appends.
+
+use std::ops::Range;
+
+use crate::conversation::ChatMessage;
+use crate::export::role_heading;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TranscriptPoint {
+    pub message_ix: usize,
+    pub offset: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TranscriptSelection {
+    pub anchor: TranscriptPoint,
+    pub head: TranscriptPoint,
+}
+
+impl TranscriptSelection {
+    pub fn new(point: TranscriptPoint) -> Self {
+        Self {
+            anchor: point,
+            head: point,
+        }
+    }
+
+    pub fn start(&self) -> TranscriptPoint {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> TranscriptPoint {
+        self.anchor.max(self.head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    /// The selected byte range within the message at `message_ix`, if any.
+    pub fn range_in_message(&self, message_ix: usize, content: &str) -> Option<Range<usize>> {
+        let (start, end) = (self.start(), self.end());
+        if message_ix < start.message_ix || message_ix > end.message_ix {
+            return None;
+        }
+        let range_start = if message_ix == start.message_ix {
+            clip_offset(content, start.offset)
+        } else {
+            0
+        };
+        let range_end = if message_ix == end.message_ix {
+            clip_offset(content, end.offset)
+        } else {
+            content.len()
+        };
+        (range_start < range_end).then_some(range_start..range_end)
+    }
+
+    fn pieces<'a>(&self, messages: &'a [ChatMessage]) -> Vec<(&'a ChatMessage, Range<usize>)> {
+        let end = self.end().message_ix.min(messages.len().saturating_sub(1));
+        messages
+            .iter()
+            .enumerate()
+            .take(end + 1)
+            .skip(self.start().message_ix)
+            .filter_map(|(ix, message)| {
+                self.range_in_message(ix, &message.content)
+                    .map(|range| (message, range))
+            })
+            .collect()
+    }
+
+    /// The selection as plain text, with code fence markers removed.
+    pub fn selected_text(&self, messages: &[ChatMessage]) -> String {
+        self.pieces(messages)
+            .into_iter()
+            .map(|(message, range)| {
+                message.content[range]
+                    .split_inclusive('\n')
+                    .filter(|line| !is_fence(line))
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// The selection as Markdown. Code blocks cut by the selection are
+    /// re-fenced, and a role heading precedes each message when the
+    /// selection spans several.
+    pub fn selected_markdown(&self, messages: &[ChatMessage]) -> String {
+        let pieces = self.pieces(messages);
+        let with_headings = pieces.len() > 1;
+        pieces
+            .into_iter()
+            .map(|(message, range)| {
+                let mut markdown = String::new();
+                if with_headings {
+                    markdown.push_str(&format!("### {}\n\n", role_heading(message.role)));
+                }
+                let content = &message.content;
+                if let Some(opener) = open_fence_at(&content[..range.start]) {
+                    markdown.push_str(opener);
+                    markdown.push('\n');
+                }
+                markdown.push_str(content[range.clone()].trim_end());
+                if let Some(opener) = open_fence_at(&content[..range.end]) {
+                    let backticks = opener.len() - opener.trim_start_matches('`').len();
+                    markdown.push('\n');
+                    markdown.push_str(&"`".repeat(backticks));
+                }
+                markdown
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+fn clip_offset(text: &str, mut offset: usize) -> usize {
+    offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+fn is_fence(line: &str) -> bool {
+    line.trim_start().starts_with("```")
+}
+
+// Returns the opening fence line if `text` ends inside a code block. Only
+// complete lines are considered.
+fn open_fence_at(text: &str) -> Option<&str> {
+    let complete = &text[..text.rfind('\n').map_or(0, |ix| ix + 1)];
+    let mut opener = None;
+    for line in complete.lines() {
+        if is_fence(line) {
+            opener = match opener {
+                Some(_) => None,
+                None => Some(line.trim()),
+            };
+        }
+    }
+    opener
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::{Conversation, Role};
+
+    const CODE_REPLY: &str = "Here:\n```rust\nfn main() {}\nlet x = 1;\n```\nDone.";
+
+    fn transcript() -> Conversation {
+        let mut conversation = Conversation::new();
+        conversation.push(Role::User, "Hello world\nsecond line");
+        conversation.push(Role::Assistant, CODE_REPLY);
+        conversation
+    }
+
+    fn selection(anchor: (usize, usize), head: (usize, usize)) -> TranscriptSelection {
+        let point = |(message_ix, offset)| TranscriptPoint { message_ix, offset };
+        TranscriptSelection {
+            anchor: point(anchor),
+            head: point(head),
+        }
+    }
+
+    fn offset_of(text: &str) -> usize {
+        CODE_REPLY.find(text).unwrap()
+    }
+
+    #[test]
+    fn copies_plain_text_across_messages() {
+        let transcript = transcript();
+        let messages = transcript.messages();
+        let forward = selection((0, 6), (1, offset_of("let")));
+        assert_eq!(
+            forward.selected_text(messages),
+            "world\nsecond line\n\nHere:\nfn main() {}"
+        );
+        // Dragging backwards selects the same text
+        let backward = selection((1, offset_of("let")), (0, 6));
+        assert_eq!(
+            backward.selected_text(messages),
+            forward.selected_text(messages)
+        );
+
+        // Offsets past the end or inside a character are clipped
+        let all = selection((0, 0), (9, usize::MAX));
+        assert!(all.selected_text(messages).ends_with("let x = 1;\nDone."));
+        let mut conversation = Conversation::new();
+        conversation.push(Role::User, "héllo");
+        let inside_char = selection((0, 2), (0, 4));
+        assert_eq!(inside_char.selected_text(conversation.messages()), "él");
+        assert_eq!(selection((0, 3), (0, 3)).selected_text(messages), "");
+    }
+
+    #[test]
+    fn copies_markdown_with_headings_and_closed_fences() {
+        let transcript = transcript();
+        let messages = transcript.messages();
+        let across = selection((0, 6), (1, offset_of("let")));
+        assert_eq!(
+            across.selected_markdown(messages),
+            "### User\n\nworld\nsecond line\n\n### Assistant\n\nHere:\n```rust\nfn main() {}\n```"
+        );
+
+        // A selection that starts inside a code block reopens it
+        let inside = selection((1, offset_of("fn")), (1, offset_of("\n```\nDone")));
+        assert_eq!(
+            inside.selected_markdown(messages),
+            "```rust\nfn main() {}\nlet x = 1;\n```"
+        );
+
+        // A whole message is copied as it is
+        let whole = selection((1, 0), (1, CODE_REPLY.len()));
+        assert_eq!(whole.selected_markdown(messages), CODE_REPLY);
+    }
+}
@@ -0,0 +1,631 @@
+// Built-in workspace tools
+// Every path argument is resolved against the workspace root, and paths that
+// would leave it (through `..` or symlinks) are rejected.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{BufRead as _, BufReader};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use futures::{future::BoxFuture, FutureExt};
+use gpui::{AsyncApp, SharedString};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::find::{FindOptions, FindQuery};
+use crate::tools::{Tool, ToolActivity, ToolRegistry};
+
+const MAX_READ_BYTES: u64 = 256 * 1024;
+// Symlinks followed before a path counts as a loop, as on Linux
+const MAX_SYMLINK_HOPS: usize = 40;
+const MAX_LISTED_ENTRIES: usize = 500;
+const MAX_SEARCH_FILE_BYTES: u64 = 1024 * 1024;
+const DEFAULT_MAX_SEARCH_RESULTS: usize = 100;
+// Directories that are never worth searching
+const SKIPPED_DIRS: &[&str] = &[".git", "target", "node_modules", ".agent-ui"];
+
+/// Registers read_file, list_directory, search_codebase and write_file.
+pub fn register_workspace_tools(registry: &mut ToolRegistry, workspace_root: &Path) {
+    let root = Arc::new(workspace_root.to_path_buf());
+    registry.register(Arc::new(ReadFileTool { root: root.clone() }));
+    registry.register(Arc::new(ListDirectoryTool { root: root.clone() }));
+    registry.register(Arc::new(SearchCodebaseTool { root: root.clone() }));
+    registry.register(Arc::new(WriteFileTool { root }));
+}
+
+/// Resolves `path` inside `root`, failing if it points outside of it.
+pub fn resolve_in_workspace(root: &Path, path: &str) -> Result<PathBuf> {
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("workspace root {} is not accessible", root.display()))?;
+    let requested = Path::new(path);
+    let relative = requested.strip_prefix(&root).unwrap_or(requested);
+
+    // Normalize lexically first so `..` can't climb above the root
+    let mut resolved = root.clone();
+    for component in relative.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(part) => resolved.push(part),
+            Component::ParentDir => {
+                if resolved == root || !resolved.pop() {
+                    bail!("{path} is outside the workspace");
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => bail!("{path} is outside the workspace"),
+        }
+    }
+
+    // Then check where symlinks actually lead, dangling ones included
+    if !real_path(&resolved)?.starts_with(&root) {
+        bail!("{path} is outside the workspace");
+    }
+    Ok(resolved)
+}
+
+// Follows every symlink along `path` the way opening it would, including a
+// dangling link at the end, and returns where it leads. Components that
+// don't exist are kept as they are.
+fn real_path(path: &Path) -> Result<PathBuf> {
+    // Components still to visit, in reverse so the next one is at the end
+    fn push_components(pending: &mut Vec<OsString>, path: &Path) {
+        let components: Vec<_> = path.components().map(|c| c.as_os_str().to_owned()).collect();
+        pending.extend(components.into_iter().rev());
+    }
+
+    let mut real = PathBuf::new();
+    let mut pending = Vec::new();
+    push_components(&mut pending, path);
+    let mut hops = 0;
+    while let Some(part) = pending.pop() {
+        match Path::new(&part).components().next() {
+            None | Some(Component::CurDir) => {}
+            Some(Component::ParentDir) => {
+                real.pop();
+            }
+            Some(Component::RootDir | Component::Prefix(_)) => real.push(&part),
+            Some(Component::Normal(_)) => {
+                let candidate = real.join(&part);
+                let is_symlink = fs::symlink_metadata(&candidate)
+                    .is_ok_and(|metadata| metadata.file_type().is_symlink());
+                if !is_symlink {
+                    real = candidate;
+                    continue;
+                }
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    bail!("too many levels of symbolic links in {}", path.display());
+                }
+                let target = fs::read_link(&candidate)?;
+                // An absolute target starts over from its own root
+                if target.is_absolute() {
+                    real = PathBuf::new();
+                }
+                push_components(&mut pending, &target);
+            }
+        }
+    }
+    Ok(real)
+}
+
+/// Like `resolve_in_workspace`, but also refuses the workspace's `.agent-ui`
+/// directory so tools can't rewrite the assistant's own configuration.
+pub fn resolve_writable_in_workspace(root: &Path, path: &str) -> Result<PathBuf> {
+    let resolved = resolve_in_workspace(root, path)?;
+    let root = root.canonicalize()?;
+    // Where the path really leads once symlinks are followed
+    let real = real_path(&resolved)?;
+    for candidate in [&resolved, &real] {
+        let first = candidate
+            .strip_prefix(&root)
+            .ok()
+            .and_then(|relative| relative.components().next());
+        if first.is_some_and(|first| {
+            first
+                .as_os_str()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(".agent-ui")
+        }) {
+            bail!("{path} is inside .agent-ui, which tools may not modify");
+        }
+    }
+    Ok(resolved)
+}
+
+fn display_path(root: &Path, path: &Path) -> String {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    path.strip_prefix(&root)
+        .map(|relative| relative.display().to_string())
+        .unwrap_or_else(|_| path.display().to_string())
+}
+
+pub(crate) fn parse_arguments<T: for<'de> Deserialize<'de>>(arguments: serde_json::Value) -> Result<T> {
+    serde_json::from_value(arguments).map_err(|error| anyhow!("invalid arguments: {error}"))
+}
+
+// Runs blocking file system work on the background executor
+fn run_blocking(
+    cx: &AsyncApp,
+    work: impl FnOnce() -> Result<String> + Send + 'static,
+) -> BoxFuture<'static, Result<String>> {
+    cx.background_spawn(async move { work() }).boxed()
+}
+
+struct ReadFileTool {
+    root: Arc<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct ReadFileArguments {
+    path: String,
+    #[serde(default)]
+    start_line: Option<usize>,
+    #[serde(default)]
+    end_line: Option<usize>,
+}
+
+impl Tool for ReadFileTool {
+    fn name(&self) -> SharedString {
+        "read_file".into()
+    }
+
+    fn description(&self) -> SharedString {
+        "Reads a text file in the workspace, optionally limited to a 1-based, inclusive line range."
+            .into()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path relative to the workspace root" },
+                "start_line": { "type": "integer", "minimum": 1 },
+                "end_line": { "type": "integer", "minimum": 1 }
+            },
+            "required": ["path"]
+        })
+    }
+
+    fn run(
+        &self,
+        arguments: serde_json::Value,
+        _activity: ToolActivity,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>> {
+        let root = self.root.clone();
+        run_blocking(cx, move || read_file(&root, parse_arguments(arguments)?))
+    }
+}
+
+// Whole files are capped at `MAX_READ_BYTES`. A line range is read line by
+// line, so any part of a larger file can be read; the range itself is cut
+// off at the same cap.
+fn read_file(root: &Path, arguments: ReadFileArguments) -> Result<String> {
+    let path = resolve_in_workspace(root, &arguments.path)?;
+    if arguments.start_line.is_none() && arguments.end_line.is_none() {
+        let size = fs::metadata(&path)
+            .with_context(|| format!("reading {}", arguments.path))?
+            .len();
+        if size > MAX_READ_BYTES {
+            bail!("{} is {size} bytes; read a line range instead", arguments.path);
+        }
+        return fs::read_to_string(&path).with_context(|| format!("reading {}", arguments.path));
+    }
+
+    let start = arguments.start_line.unwrap_or(1).max(1);
+    let end = arguments.end_line.unwrap_or(usize::MAX);
+    let file = fs::File::open(&path).with_context(|| format!("reading {}", arguments.path))?;
+    let mut lines = Vec::new();
+    let mut bytes = 0;
+    for (line_ix, line) in BufReader::new(file).lines().enumerate() {
+        let line_number = line_ix + 1;
+        if line_number > end {
+            break;
+        }
+        let line = line.with_context(|| format!("reading {}", arguments.path))?;
+        if line_number < start {
+            continue;
+        }
+        bytes += line.len() as u64 + 1;
+        if bytes > MAX_READ_BYTES {
+            lines.push(format!("(stopped at line {line_number}; read from there for more)"));
+            break;
+        }
+        lines.push(line);
+    }
+    Ok(lines.join("\n"))
+}
+
+struct ListDirectoryTool {
+    root: Arc<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct ListDirectoryArguments {
+    #[serde(default)]
+    path: Option<String>,
+}
+
+impl Tool for ListDirectoryTool {
+    fn name(&self) -> SharedString {
+        "list_directory".into()
+    }
+
+    fn description(&self) -> SharedString {
+        "Lists the entries of a workspace directory. Directories end with `/`.".into()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Directory relative to the workspace root; defaults to the root" }
+            }
+        })
+    }
+
+    fn run(
+        &self,
+        arguments: serde_json::Value,
+        _activity: ToolActivity,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>> {
+        let root = self.root.clone();
+        run_blocking(cx, move || {
+            let arguments: ListDirectoryArguments = parse_arguments(arguments)?;
+            let requested = arguments.path.unwrap_or_else(|| ".".to_string());
+            let path = resolve_in_workspace(&root, &requested)?;
+            let mut entries = Vec::new();
+            for entry in fs::read_dir(&path).with_context(|| format!("listing {requested}"))? {
+                let entry = entry?;
+                let mut name = entry.file_name().to_string_lossy().into_owned();
+                if entry.file_type()?.is_dir() {
+                    name.push('/');
+                }
+                entries.push(name);
+            }
+            entries.sort();
+            let total = entries.len();
+            entries.truncate(MAX_LISTED_ENTRIES);
+            let mut output = entries.join("\n");
+            if total > MAX_LISTED_ENTRIES {
+                output.push_str(&format!("\n… {} more entries", total - MAX_LISTED_ENTRIES));
+            }
+            if output.is_empty() {
+                output = format!("{requested} is empty");
+            }
+            Ok(output)
+        })
+    }
+}
+
+struct SearchCodebaseTool {
+    root: Arc<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct SearchCodebaseArguments {
+    query: String,
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    regex: bool,
+    #[serde(default)]
+    case_sensitive: bool,
+    #[serde(default)]
+    max_results: Option<usize>,
+}
+
+impl Tool for SearchCodebaseTool {
+    fn name(&self) -> SharedString {
+        "search_codebase".into()
+    }
+
+    fn description(&self) -> SharedString {
+        "Searches workspace files line by line, like grep. Returns `path:line: text` matches."
+            .into()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "Text or regular expression to find" },
+                "path": { "type": "string", "description": "Directory to search; defaults to the workspace root" },
+                "regex": { "type": "boolean", "default": false },
+                "case_sensitive": { "type": "boolean", "default": false },
+                "max_results": { "type": "integer", "minimum": 1, "default": DEFAULT_MAX_SEARCH_RESULTS }
+            },
+            "required": ["query"]
+        })
+    }
+
+    fn run(
+        &self,
+        arguments: serde_json::Value,
+        _activity: ToolActivity,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>> {
+        let root = self.root.clone();
+        run_blocking(cx, move || {
+            let arguments: SearchCodebaseArguments = parse_arguments(arguments)?;
+            let query = FindQuery::new(
+                &arguments.query,
+                FindOptions {
+                    case_sensitive: arguments.case_sensitive,
+                    whole_word: false,
+                    regex: arguments.regex,
+                },
+            )?
+            .ok_or_else(|| anyhow!("query is empty"))?;
+            let max_results = arguments.max_results.unwrap_or(DEFAULT_MAX_SEARCH_RESULTS);
+            let start = resolve_in_workspace(&root, arguments.path.as_deref().unwrap_or("."))?;
+
+            let mut results = Vec::new();
+            let mut pending_dirs = vec![start];
+            'walk: while let Some(dir) = pending_dirs.pop() {
+                let mut entries: Vec<_> = fs::read_dir(&dir)?.filter_map(|entry| entry.ok()).collect();
+                entries.sort_by_key(|entry| entry.file_name());
+                for entry in entries {
+                    let path = entry.path();
+                    let Ok(file_type) = entry.file_type() else {
+                        continue;
+                    };
+                    if file_type.is_dir() {
+                        let name = entry.file_name();
+                        if !SKIPPED_DIRS.iter().any(|skipped| name == *skipped) {
+                            pending_dirs.push(path);
+                        }
+                        continue;
+                    }
+                    let small_enough = entry
+                        .metadata()
+                        .is_ok_and(|metadata| metadata.len() <= MAX_SEARCH_FILE_BYTES);
+                    if !file_type.is_file() || !small_enough {
+                        continue;
+                    }
+                    // Skips binary and non-UTF-8 files
+                    let Ok(content) = fs::read_to_string(&path) else {
+                        continue;
+                    };
+                    if content.contains('\0') {
+                        continue;
+                    }
+                    for (line_ix, line) in content.lines().enumerate() {
+                        if query.find_in(line).is_empty() {
+                            continue;
+                        }
+                        results.push(format!(
+                            "{}:{}: {}",
+                            display_path(&root, &path),
+                            line_ix + 1,
+                            line.trim()
+                        ));
+                        if results.len() >= max_results {
+                            results.push(format!("(stopped after {max_results} matches)"));
+                            break 'walk;
+                        }
+                    }
+                }
+            }
+
+            if results.is_empty() {
+                return Ok(format!("No matches for `{}`", arguments.query));
+            }
+            Ok(results.join("\n"))
+        })
+    }
+}
+
+struct WriteFileTool {
+    root: Arc<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct WriteFileArguments {
+    path: String,
+    content: String,
+}
+
+impl Tool for WriteFileTool {
+    fn name(&self) -> SharedString {
+        "write_file".into()
+    }
+
+    fn description(&self) -> SharedString {
+        "Creates or overwrites a file in the workspace with the given content.".into()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path relative to the workspace root" },
+                "content": { "type": "string" }
+            },
+            "required": ["path", "content"]
+        })
+    }
+
+    fn needs_approval(&self) -> bool {
+        true
+    }
+
+    fn run(
+        &self,
+        arguments: serde_json::Value,
+        _activity: ToolActivity,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>> {
+        let root = self.root.clone();
+        run_blocking(cx, move || {
+            let arguments: WriteFileArguments = parse_arguments(arguments)?;
+            let path = resolve_writable_in_workspace(&root, &arguments.path)?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("creating {}", parent.display()))?;
+            }
+            fs::write(&path, &arguments.content)
+                .with_context(|| format!("writing {}", arguments.path))?;
+            Ok(format!("Wrote {} bytes to {}", arguments.content.len(), arguments.path))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh workspace inside a fresh directory, so tests can put files
+    // next to it that the workspace must not reach
+    struct Fixture {
+        dir: PathBuf,
+        root: PathBuf,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("workspace-tools-{}", uuid::Uuid::new_v4()));
+            let root = dir.join("workspace");
+            fs::create_dir_all(root.join("src")).unwrap();
+            fs::write(root.join("src/lib.rs"), "one\ntwo\nthree\n").unwrap();
+            fs::write(dir.join("secret.txt"), "outside").unwrap();
+            // The temp dir itself may sit behind a symlink (macOS /var)
+            let dir = dir.canonicalize().unwrap();
+            let root = dir.join("workspace");
+            Self { dir, root }
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    fn read(
+        root: &Path,
+        path: &str,
+        start_line: Option<usize>,
+        end_line: Option<usize>,
+    ) -> Result<String> {
+        read_file(
+            root,
+            ReadFileArguments {
+                path: path.to_string(),
+                start_line,
+                end_line,
+            },
+        )
+    }
+
+    #[test]
+    fn parent_components_stay_inside_the_workspace() {
+        let fixture = Fixture::new();
+        let root = &fixture.root;
+        assert_eq!(
+            resolve_in_workspace(root, "src/../src/lib.rs").unwrap(),
+            root.join("src/lib.rs")
+        );
+        assert!(resolve_in_workspace(root, "../secret.txt").is_err());
+        assert!(resolve_in_workspace(root, "src/../../secret.txt").is_err());
+        assert!(resolve_writable_in_workspace(root, "../new.txt").is_err());
+    }
+
+    #[test]
+    fn absolute_paths_must_be_inside_the_workspace() {
+        let fixture = Fixture::new();
+        let root = &fixture.root;
+        let inside = root.join("src/lib.rs");
+        assert_eq!(
+            resolve_in_workspace(root, inside.to_str().unwrap()).unwrap(),
+            inside
+        );
+        let outside = fixture.dir.join("secret.txt");
+        assert!(resolve_in_workspace(root, outside.to_str().unwrap()).is_err());
+        assert!(resolve_writable_in_workspace(root, outside.to_str().unwrap()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_that_escape_are_refused() {
+        use std::os::unix::fs::symlink;
+
+        let fixture = Fixture::new();
+        let root = &fixture.root;
+        symlink(fixture.dir.join("secret.txt"), root.join("live")).unwrap();
+        symlink(fixture.dir.join("missing.txt"), root.join("dangling")).unwrap();
+        symlink(&fixture.dir, root.join("parent")).unwrap();
+        symlink("../../missing.txt", root.join("src/relative")).unwrap();
+        for path in [
+            "live",
+            "dangling",
+            "parent/secret.txt",
+            "parent/new.txt",
+            "src/relative",
+        ] {
+            assert!(resolve_in_workspace(root, path).is_err(), "{path}");
+            assert!(resolve_writable_in_workspace(root, path).is_err(), "{path}");
+        }
+        assert!(!fixture.dir.join("missing.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_inside_the_workspace_are_followed() {
+        use std::os::unix::fs::symlink;
+
+        let fixture = Fixture::new();
+        let root = &fixture.root;
+        symlink("src/lib.rs", root.join("live")).unwrap();
+        symlink("src/new.rs", root.join("dangling")).unwrap();
+        symlink(".agent-ui", root.join("config")).unwrap();
+        assert!(resolve_in_workspace(root, "live").is_ok());
+        assert!(resolve_writable_in_workspace(root, "dangling").is_ok());
+        assert!(resolve_writable_in_workspace(root, "config/tool_policy.json").is_err());
+
+        symlink("loop", root.join("loop")).unwrap();
+        assert!(resolve_in_workspace(root, "loop").is_err());
+    }
+
+    #[test]
+    fn line_ranges_are_inclusive_and_one_based() {
+        let fixture = Fixture::new();
+        let root = &fixture.root;
+        assert_eq!(
+            read(root, "src/lib.rs", None, None).unwrap(),
+            "one\ntwo\nthree\n"
+        );
+        assert_eq!(
+            read(root, "src/lib.rs", Some(2), Some(3)).unwrap(),
+            "two\nthree"
+        );
+        assert_eq!(read(root, "src/lib.rs", None, Some(1)).unwrap(), "one");
+        assert_eq!(read(root, "src/lib.rs", Some(3), None).unwrap(), "three");
+        assert_eq!(read(root, "src/lib.rs", Some(9), None).unwrap(), "");
+    }
+
+    #[test]
+    fn large_files_can_be_read_by_line_range() {
+        let fixture = Fixture::new();
+        let root = &fixture.root;
+        let line = "x".repeat(99);
+        let content = vec![line.as_str(); 5_000].join("\n");
+        fs::write(root.join("large.txt"), &content).unwrap();
+
+        let error = read(root, "large.txt", None, None).unwrap_err().to_string();
+        assert!(error.contains("read a line range instead"), "{error}");
+        assert_eq!(
+            read(root, "large.txt", Some(4_000), Some(4_001)).unwrap(),
+            format!("{line}\n{line}")
+        );
+
+        // A range larger than the cap is cut off with a note
+        let output = read(root, "large.txt", Some(1), None).unwrap();
+        assert!(output.len() as u64 <= MAX_READ_BYTES + 100);
+        assert!(output.ends_with("read from there for more)"));
+    }
+}
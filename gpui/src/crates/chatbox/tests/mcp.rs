@@ -0,0 +1,103 @@
+// Runs the MCP client against examples/mcp_stub_server.rs, which `cargo test`
+// builds along with the other examples.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chatbox::{register_mcp_tools, McpClient, McpRegistration, McpServerConfig, ToolRegistry};
+use gpui::TestAppContext;
+use serde_json::json;
+
+// Examples are built next to the `deps` dir that holds this test binary
+fn stub_server() -> PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.join("examples")
+        .join(format!("mcp_stub_server{}", std::env::consts::EXE_SUFFIX))
+}
+
+async fn start_stub(cx: &mut TestAppContext) -> McpClient {
+    // The server is a real process, so wait for its replies instead of
+    // treating an idle executor as a deadlock
+    cx.executor().allow_parking();
+    let config = McpServerConfig {
+        command: stub_server().display().to_string(),
+        args: Vec::new(),
+        env: Default::default(),
+        cwd: None,
+        disabled: false,
+    };
+    McpClient::start("stub".into(), &config, cx.executor())
+        .await
+        .unwrap()
+}
+
+#[gpui::test]
+async fn lists_and_calls_the_stub_servers_tools(cx: &mut TestAppContext) {
+    let client = start_stub(cx).await;
+    let tools: Vec<_> = client
+        .tools()
+        .iter()
+        .map(|tool| tool.name.as_str())
+        .collect();
+    assert_eq!(tools, ["echo", "add"]);
+    assert_eq!(client.tools()[0].input_schema["required"], json!(["text"]));
+    assert_eq!(client.resources()[0].uri, "stub://readme");
+
+    let echoed = client
+        .call_tool("echo", json!({ "text": "hello" }))
+        .await
+        .unwrap();
+    assert_eq!(echoed, "hello");
+    let sum = client
+        .call_tool("add", json!({ "a": 2, "b": 3 }))
+        .await
+        .unwrap();
+    assert_eq!(sum, "5");
+
+    let error = client
+        .call_tool("add", json!({ "a": "two" }))
+        .await
+        .unwrap_err();
+    assert_eq!(error.to_string(), "a and b must be numbers");
+    let error = client.call_tool("missing", json!({})).await.unwrap_err();
+    assert!(error.to_string().contains("unknown tool"), "{error}");
+}
+
+#[gpui::test]
+async fn registering_never_replaces_a_tool(cx: &mut TestAppContext) {
+    let client = Arc::new(start_stub(cx).await);
+    let mut registry = ToolRegistry::new();
+
+    let registration = register_mcp_tools(&mut registry, &client);
+    assert_eq!(
+        registration,
+        McpRegistration {
+            registered: 2,
+            skipped: Vec::new()
+        }
+    );
+    // Taken names fall back to `<server>__<tool>`
+    let registration = register_mcp_tools(&mut registry, &client);
+    assert_eq!(
+        registration,
+        McpRegistration {
+            registered: 2,
+            skipped: Vec::new()
+        }
+    );
+    assert!(registry.tool("stub__echo").is_some());
+    // And when that is taken too, the tool is left out
+    let registration = register_mcp_tools(&mut registry, &client);
+    assert_eq!(
+        registration,
+        McpRegistration {
+            registered: 0,
+            skipped: vec!["echo".to_string(), "add".to_string()],
+        }
+    );
+    assert_eq!(registry.definitions().len(), 4);
+}
@@ -0,0 +1,149 @@
+// Runs examples/ollama_stub_server.rs and maps its `/api/chat` stream the
+// way the Ollama provider does.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+use chatbox::ollama::map_chat_line;
+use chatbox::{CompletionEvent, StopReason, TokenUsage};
+use serde_json::{json, Value};
+
+// Examples are built next to the `deps` dir that holds this test binary
+fn stub_server() -> PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.join("examples").join(format!(
+        "ollama_stub_server{}",
+        std::env::consts::EXE_SUFFIX
+    ))
+}
+
+struct Stub {
+    process: Child,
+    address: String,
+}
+
+impl Stub {
+    fn start() -> Self {
+        let address = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        // Killed on drop, including when it never comes up
+        let stub = Self {
+            process: Command::new(stub_server()).arg(&address).spawn().unwrap(),
+            address,
+        };
+        for _ in 0..100 {
+            if TcpStream::connect(&stub.address).is_ok() {
+                return stub;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("the stub server did not start on {}", stub.address);
+    }
+
+    // Sends a request and returns the response body; the stub closes the
+    // connection after each response
+    fn request(&self, method: &str, path: &str, body: &Value) -> String {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        let body = body.to_string();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+            line.clear();
+        }
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+        body
+    }
+
+    fn chat(&self, request: Value) -> Vec<CompletionEvent> {
+        let mut saw_tool_calls = false;
+        self.request("POST", "/api/chat", &request)
+            .lines()
+            .flat_map(|line| map_chat_line(line, &mut saw_tool_calls).unwrap())
+            .collect()
+    }
+}
+
+impl Drop for Stub {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+    }
+}
+
+#[test]
+fn stub_lists_a_model_that_can_call_tools() {
+    let stub = Stub::start();
+    let tags: Value = serde_json::from_str(&stub.request("GET", "/api/tags", &json!({}))).unwrap();
+    assert_eq!(tags["models"][0]["name"], "stub-llama:latest");
+    let show: Value = serde_json::from_str(&stub.request(
+        "POST",
+        "/api/show",
+        &json!({ "model": "stub-llama:latest" }),
+    ))
+    .unwrap();
+    assert_eq!(show["capabilities"], json!(["completion", "tools"]));
+}
+
+#[test]
+fn stub_chat_streams_text_and_tool_calls() {
+    let stub = Stub::start();
+    let usage = CompletionEvent::Usage(TokenUsage {
+        input_tokens: 12,
+        output_tokens: 8,
+    });
+    assert_eq!(
+        stub.chat(json!({ "messages": [{ "role": "user", "content": "hello there" }] })),
+        vec![
+            CompletionEvent::Text("hello ".into()),
+            CompletionEvent::Text("there".into()),
+            usage.clone(),
+            CompletionEvent::Stop(StopReason::EndTurn),
+        ]
+    );
+
+    let events = stub.chat(json!({
+        "messages": [{ "role": "user", "content": "call git_status" }],
+        "tools": [{ "type": "function", "function": { "name": "git_status" } }],
+    }));
+    let [CompletionEvent::ToolUse(call), _, stop] = events.as_slice() else {
+        panic!("unexpected events: {events:?}");
+    };
+    assert_eq!(call.name, "git_status");
+    assert_eq!(call.arguments, json!({}));
+    assert_eq!(*stop, CompletionEvent::Stop(StopReason::ToolUse));
+
+    // Without tools in the request, the message is only echoed
+    let events = stub.chat(json!({
+        "messages": [{ "role": "user", "content": "call git_status" }],
+    }));
+    assert_eq!(
+        events.last(),
+        Some(&CompletionEvent::Stop(StopReason::EndTurn))
+    );
+
+    assert_eq!(
+        stub.chat(json!({ "messages": [{ "role": "tool", "content": "clean" }] })),
+        vec![
+            CompletionEvent::Text("The tool said: clean".into()),
+            usage,
+            CompletionEvent::Stop(StopReason::EndTurn),
+        ]
+    );
+}
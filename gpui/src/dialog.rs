@@ -0,0 +1,523 @@
+// 可复用的对话框：提示（alert）、确认（confirm）和输入（prompt）
+// 每个对话框返回一个 future，结果为 `Confirmed(value)` 或 `Cancelled`。指定了
+// 父窗口时，对话框以模态层的形式盖在父窗口上，打开期间父窗口不响应鼠标和键盘；
+// 否则在自己的窗口中打开。关闭对话框不会退出应用。
+
+use chatbox::interactive_chatbox::{bind_input_keys, Send};
+use chatbox::InteractiveChatInput;
+use anyhow::{Context as _, Result};
+use futures::channel::oneshot;
+use gpui::{
+    actions, div, hsla, px, rgb, AnyView, AnyWindowHandle, App, Bounds, Context,
+    DismissEvent, Entity, EventEmitter, FocusHandle, Focusable, InteractiveElement, IntoElement,
+    KeyBinding, ParentElement, Pixels, Render, SharedString, Size, Styled, Subscription, Window,
+    WindowBounds, WindowKind, WindowOptions, prelude::*, black, white,
+};
+use std::future::Future;
+use std::rc::Rc;
+
+// 对话框的大小，无论是独立窗口还是模态层
+const DIALOG_SIZE: Size<Pixels> = Size {
+    width: px(440.),
+    height: px(220.),
+};
+
+// 定义操作
+actions!(dialog, [Confirm, Cancel]);
+
+/// 注册对话框的快捷键：回车确认，Esc 取消。输入框沿用 chatbox 的编辑快捷键。
+pub fn init(cx: &mut App) {
+    bind_input_keys(cx);
+    cx.bind_keys([
+        KeyBinding::new("enter", Confirm, Some("Dialog")),
+        KeyBinding::new("escape", Cancel, Some("Dialog")),
+    ]);
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DialogResult<T> {
+    Confirmed(T),
+    Cancelled,
+}
+
+impl<T> DialogResult<T> {
+    pub fn is_confirmed(&self) -> bool {
+        matches!(self, Self::Confirmed(_))
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> DialogResult<U> {
+        match self {
+            Self::Confirmed(value) => DialogResult::Confirmed(f(value)),
+            Self::Cancelled => DialogResult::Cancelled,
+        }
+    }
+}
+
+/// 检查输入内容，返回的错误显示在输入框下方。
+#[derive(Clone)]
+pub struct Validator(Rc<dyn Fn(&str) -> Result<(), SharedString>>);
+
+impl std::fmt::Debug for Validator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Validator")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DialogOptions {
+    pub title: SharedString,
+    pub message: SharedString,
+    pub confirm_label: SharedString,
+    pub cancel_label: SharedString,
+    /// 输入框以圆点代替输入的字符，用于密码。
+    pub secret: bool,
+    /// 在密码输入框旁显示按住即可查看明文的按钮。
+    pub reveal_toggle: bool,
+    /// 输入内容最多的字符数（按字素计），超出的部分无法输入。
+    pub max_length: Option<usize>,
+    /// 输入内容无效时显示错误，并且无法确认。
+    pub validator: Option<Validator>,
+    /// 对话框以模态层显示在这个窗口中；为 `None` 时在屏幕中央打开新窗口。
+    /// 第一次在某个窗口中打开对话框时，窗口的根视图会被包进 `DialogHost`。
+    pub parent: Option<AnyWindowHandle>,
+}
+
+impl Default for DialogOptions {
+    fn default() -> Self {
+        Self {
+            title: "".into(),
+            message: "".into(),
+            confirm_label: "OK".into(),
+            cancel_label: "Cancel".into(),
+            secret: false,
+            reveal_toggle: false,
+            max_length: None,
+            validator: None,
+            parent: None,
+        }
+    }
+}
+
+impl DialogOptions {
+    pub fn new(title: impl Into<SharedString>, message: impl Into<SharedString>) -> Self {
+        Self {
+            title: title.into(),
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn confirm_label(mut self, label: impl Into<SharedString>) -> Self {
+        self.confirm_label = label.into();
+        self
+    }
+
+    pub fn cancel_label(mut self, label: impl Into<SharedString>) -> Self {
+        self.cancel_label = label.into();
+        self
+    }
+
+    pub fn secret(mut self, secret: bool) -> Self {
+        self.secret = secret;
+        self
+    }
+
+    pub fn reveal_toggle(mut self, reveal_toggle: bool) -> Self {
+        self.reveal_toggle = reveal_toggle;
+        self
+    }
+
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    pub fn validator(
+        mut self,
+        validator: impl Fn(&str) -> Result<(), SharedString> + 'static,
+    ) -> Self {
+        self.validator = Some(Validator(Rc::new(validator)));
+        self
+    }
+
+    pub fn parent(mut self, parent: AnyWindowHandle) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DialogKind {
+    Alert,
+    Confirm,
+    Prompt,
+}
+
+/// 只有一个确认按钮的提示框。按 Esc 或关闭窗口时结果为 `Cancelled`。
+/// 对话框打不开时（例如没有可用的显示器或父窗口已关闭）返回错误。
+pub fn alert(
+    options: DialogOptions,
+    cx: &mut App,
+) -> Result<impl Future<Output = DialogResult<()>>> {
+    let result = open(DialogKind::Alert, options, String::new(), cx)?;
+    Ok(async move { result.await.map(|_| ()) })
+}
+
+/// 带确认和取消按钮的确认框。
+pub fn confirm(
+    options: DialogOptions,
+    cx: &mut App,
+) -> Result<impl Future<Output = DialogResult<()>>> {
+    let result = open(DialogKind::Confirm, options, String::new(), cx)?;
+    Ok(async move { result.await.map(|_| ()) })
+}
+
+/// 带输入框的对话框，确认时返回输入的内容。
+pub fn prompt(
+    options: DialogOptions,
+    default: impl Into<String>,
+    cx: &mut App,
+) -> Result<impl Future<Output = DialogResult<String>>> {
+    open(DialogKind::Prompt, options, default.into(), cx)
+}
+
+fn open(
+    kind: DialogKind,
+    options: DialogOptions,
+    input_text: String,
+    cx: &mut App,
+) -> Result<impl Future<Output = DialogResult<String>>> {
+    let (sender, receiver) = oneshot::channel();
+    match options.parent {
+        Some(parent) => parent
+            .update(cx, |root, window, cx| {
+                let host = match window.root::<DialogHost>().flatten() {
+                    Some(host) => host,
+                    None => window.replace_root(cx, |_, _| DialogHost::new(root)),
+                };
+                let dialog =
+                    cx.new(|cx| Dialog::new(kind, options, input_text, sender, true, cx));
+                host.update(cx, |host, cx| host.push(dialog, window, cx));
+            })
+            .context("父窗口已关闭")?,
+        None => open_window(kind, options, input_text, sender, cx)?,
+    }
+    cx.activate(true);
+
+    // 对话框被丢弃时会发送 `Cancelled`，发送端不会悄悄消失
+    Ok(async move { receiver.await.unwrap_or(DialogResult::Cancelled) })
+}
+
+// 没有父窗口时，对话框独占一个居中的窗口
+fn open_window(
+    kind: DialogKind,
+    options: DialogOptions,
+    input_text: String,
+    sender: oneshot::Sender<DialogResult<String>>,
+    cx: &mut App,
+) -> Result<()> {
+    let title = options.title.clone();
+    let bounds = Bounds::centered(None, DIALOG_SIZE, cx);
+    cx.open_window(
+        WindowOptions {
+            titlebar: Some(gpui::TitlebarOptions {
+                title: Some(title),
+                ..Default::default()
+            }),
+            window_bounds: Some(WindowBounds::Windowed(bounds)),
+            kind: WindowKind::Normal,
+            is_resizable: false,
+            ..Default::default()
+        },
+        |window, cx| {
+            let dialog = cx.new(|cx| Dialog::new(kind, options, input_text, sender, false, cx));
+            window.focus(&dialog.focus_handle(cx));
+            dialog
+        },
+    )?;
+    Ok(())
+}
+
+/// 模态层：照常绘制窗口原来的根视图，对话框打开时在上面盖一层遮罩，
+/// 挡住鼠标事件，并把焦点移到对话框上，所以父窗口收不到输入。
+pub struct DialogHost {
+    content: AnyView,
+    // 后打开的对话框在上面；每个对话框关闭后，焦点回到打开它之前的位置
+    dialogs: Vec<(Entity<Dialog>, Option<FocusHandle>, Subscription)>,
+}
+
+impl DialogHost {
+    fn new(content: AnyView) -> Self {
+        Self {
+            content,
+            dialogs: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, dialog: Entity<Dialog>, window: &mut Window, cx: &mut Context<Self>) {
+        let previous_focus = window.focused(cx);
+        let subscription = cx.subscribe_in(
+            &dialog,
+            window,
+            |this, dialog, _: &DismissEvent, window, cx| {
+                let Some(index) = this.dialogs.iter().position(|(open, _, _)| open == dialog)
+                else {
+                    return;
+                };
+                let (_, previous_focus, _) = this.dialogs.remove(index);
+                if let Some((top, _, _)) = this.dialogs.last() {
+                    window.focus(&top.focus_handle(cx));
+                } else if let Some(previous_focus) = previous_focus {
+                    window.focus(&previous_focus);
+                }
+                cx.notify();
+            },
+        );
+        window.focus(&dialog.focus_handle(cx));
+        self.dialogs.push((dialog, previous_focus, subscription));
+        cx.notify();
+    }
+}
+
+impl Render for DialogHost {
+    fn render(&mut self, _: &mut Window, _: &mut Context<Self>) -> impl IntoElement {
+        div()
+            .relative()
+            .size_full()
+            .child(self.content.clone())
+            .when_some(self.dialogs.last(), |this, (dialog, _, _)| {
+                this.child(
+                    // 遮罩层
+                    div()
+                        .id("dialog-backdrop")
+                        .absolute()
+                        .inset_0()
+                        .occlude()
+                        .flex()
+                        .items_center()
+                        .justify_center()
+                        .bg(hsla(0., 0., 0., 0.3))
+                        .child(
+                            div()
+                                .w(DIALOG_SIZE.width)
+                                .h(DIALOG_SIZE.height)
+                                .rounded_md()
+                                .overflow_hidden()
+                                .shadow_lg()
+                                .child(dialog.clone()),
+                        ),
+                )
+            })
+    }
+}
+
+// 定义对话框组件
+pub struct Dialog {
+    kind: DialogKind,
+    options: DialogOptions,
+    focus_handle: FocusHandle,
+    // 只有输入对话框有输入框，焦点也在输入框里
+    input: Option<Entity<InteractiveChatInput>>,
+    // 显示在模态层中时由 `DialogHost` 移除，否则关闭自己的窗口
+    hosted: bool,
+    // 输入框变化时重新渲染，以便更新确认按钮的状态
+    _input_subscription: Option<Subscription>,
+    // 对话框关闭时取出；窗口被直接关闭时随之丢弃，结果视为取消
+    result: Option<oneshot::Sender<DialogResult<String>>>,
+}
+
+impl EventEmitter<DismissEvent> for Dialog {}
+
+impl Dialog {
+    fn new(
+        kind: DialogKind,
+        options: DialogOptions,
+        input_text: String,
+        result: oneshot::Sender<DialogResult<String>>,
+        hosted: bool,
+        cx: &mut Context<Self>,
+    ) -> Self {
+        let input = (kind == DialogKind::Prompt).then(|| {
+            let options = options.clone();
+            cx.new(|cx| {
+                let mut input = InteractiveChatInput::new(cx);
+                input.set_placeholder("");
+                input.set_text_size(px(14.), cx);
+                input.set_secret(options.secret, cx);
+                input.set_reveal_toggle(options.reveal_toggle, cx);
+                input.set_max_length(options.max_length, cx);
+                if let Some(Validator(validator)) = options.validator {
+                    input.set_validator(move |text| validator(text), cx);
+                }
+                input.set_text(input_text, cx);
+                input
+            })
+        });
+        let input_subscription = input
+            .as_ref()
+            .map(|input| cx.observe(input, |_, _, cx| cx.notify()));
+
+        Self {
+            kind,
+            options,
+            focus_handle: cx.focus_handle(),
+            input,
+            hosted,
+            _input_subscription: input_subscription,
+            result: Some(result),
+        }
+    }
+
+    fn finish(
+        &mut self,
+        result: DialogResult<String>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if let Some(sender) = self.result.take() {
+            sender.send(result).ok();
+        }
+        if self.hosted {
+            cx.emit(DismissEvent);
+        } else {
+            window.remove_window();
+        }
+    }
+
+    // 输入内容无效时不能确认
+    fn can_confirm(&self, cx: &App) -> bool {
+        self.input.as_ref().is_none_or(|input| input.read(cx).is_valid())
+    }
+
+    fn confirm(&mut self, _: &Confirm, window: &mut Window, cx: &mut Context<Self>) {
+        if !self.can_confirm(cx) {
+            return;
+        }
+        let value = self
+            .input
+            .as_ref()
+            .map(|input| input.read(cx).get_text())
+            .unwrap_or_default();
+        self.finish(DialogResult::Confirmed(value), window, cx);
+    }
+
+    fn cancel(&mut self, _: &Cancel, window: &mut Window, cx: &mut Context<Self>) {
+        self.finish(DialogResult::Cancelled, window, cx);
+    }
+
+    fn render_button(
+        label: SharedString,
+        color: u32,
+        enabled: bool,
+        cx: &mut Context<Self>,
+        on_click: fn(&mut Self, &mut Window, &mut Context<Self>),
+    ) -> impl IntoElement {
+        div()
+            .px_3()
+            .py_1()
+            .bg(rgb(color))
+            .text_color(white())
+            .rounded_sm()
+            .text_sm()
+            .when(enabled, |this| {
+                this.cursor_pointer().on_mouse_up(
+                    gpui::MouseButton::Left,
+                    cx.listener(move |this, _, window, cx| on_click(this, window, cx)),
+                )
+            })
+            .when(!enabled, |this| this.opacity(0.5))
+            .child(label)
+    }
+}
+
+impl Drop for Dialog {
+    fn drop(&mut self) {
+        if let Some(sender) = self.result.take() {
+            sender.send(DialogResult::Cancelled).ok();
+        }
+    }
+}
+
+impl Focusable for Dialog {
+    fn focus_handle(&self, cx: &App) -> FocusHandle {
+        match &self.input {
+            Some(input) => input.focus_handle(cx),
+            None => self.focus_handle.clone(),
+        }
+    }
+}
+
+impl Render for Dialog {
+    fn render(&mut self, _: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        div()
+            .key_context("Dialog")
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::confirm))
+            .on_action(cx.listener(Self::cancel))
+            // 输入框里的回车发出的是 chatbox 的发送操作
+            .on_action(cx.listener(|this, _: &Send, window, cx| {
+                this.confirm(&Confirm, window, cx)
+            }))
+            .flex()
+            .flex_col()
+            .size_full()
+            .bg(white())
+            .child(
+                // 标题栏
+                div()
+                    .flex()
+                    .items_center()
+                    .px_4()
+                    .py_3()
+                    .bg(rgb(0x2196f3)) // 蓝色标题栏
+                    .child(
+                        div()
+                            .text_base()
+                            .text_color(white())
+                            .child(self.options.title.clone())
+                    )
+            )
+            .child(
+                // 内容区域
+                div()
+                    .flex()
+                    .flex_col()
+                    .flex_1()
+                    .px_4()
+                    .py_3()
+                    .gap_3()
+                    .child(
+                        div()
+                            .text_sm()
+                            .text_color(black())
+                            .child(self.options.message.clone())
+                    )
+                    .when_some(self.input.clone(), |this, input| this.child(input))
+            )
+            .child(
+                // 按钮区域
+                div()
+                    .flex()
+                    .justify_end()
+                    .px_4()
+                    .py_3()
+                    .gap_2()
+                    .when(self.kind != DialogKind::Alert, |this| {
+                        this.child(Self::render_button(
+                            self.options.cancel_label.clone(),
+                            0x6c757d, // 灰色取消按钮
+                            true,
+                            cx,
+                            |this, window, cx| this.cancel(&Cancel, window, cx),
+                        ))
+                    })
+                    .child(Self::render_button(
+                        self.options.confirm_label.clone(),
+                        0x2196f3, // 蓝色确认按钮
+                        self.can_confirm(cx),
+                        cx,
+                        |this, window, cx| this.confirm(&Confirm, window, cx),
+                    ))
+            )
+    }
+}